@@ -1,6 +1,6 @@
 import { createContext, useContext, useState, useEffect, useCallback, ReactNode, useRef, useMemo } from "react";
 import type { InitOutput } from "client-rust-functions";
-import init, { BoidOrchestrator } from "~/rust.client";
+import init, { BoidOrchestrator, ResizeMode } from "~/rust.client";
 import { JSBoid } from "./model";
 import { BoidSerializer } from "./utils";
 import { DebugView } from "./DebugView";
@@ -103,9 +103,8 @@ export const useBoids = () => {
           console.log("Updating dimensions");
           setDimensions({ width: clientWidth, height: clientHeight });
 
-          // Update orchestrator dimensions
-          orchestrator.set_world_height(clientHeight);
-          orchestrator.set_world_width(clientWidth);
+          // Update orchestrator dimensions, stretching the flock with the container
+          orchestrator.resize_world(clientWidth, clientHeight, ResizeMode.Scale);
         }
       }
     };
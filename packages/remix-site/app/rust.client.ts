@@ -1,3 +1,3 @@
 import init from "client-rust-functions";
 export default init;
-export { greet, Universe, Cell, BoidOrchestrator } from "client-rust-functions";
+export { greet, Universe, Cell, BoidOrchestrator, BoidOrchestrator3D, BoidRenderer, SvgOptions, ResizeMode, ZoneEffect } from "client-rust-functions";
@@ -0,0 +1,9 @@
+use cfg_aliases::cfg_aliases;
+
+fn main() {
+    cfg_aliases! {
+        // The tick only goes parallel natively. Rayon's global pool needs threads, and
+        // the wasm build never sets up a pool for it to spawn them on.
+        parallel_tick: { all(feature = "parallel", not(target_arch = "wasm32")) },
+    }
+}
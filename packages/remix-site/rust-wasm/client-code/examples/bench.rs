@@ -0,0 +1,227 @@
+//! Headless benchmark for the simulations, no browser needed.
+//!
+//! ```text
+//! cargo run --release --example bench -- [config.json] [--format json|csv] [--ticks N] [--per-tick]
+//! cargo run --release --features parallel --example bench -- examples/bench.json
+//! ```
+//!
+//! The config is JSON, every field is optional (see `examples/bench.json`). Prints the
+//! time per tick, a per-rule breakdown (boids only) and the final state's metrics.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Instant;
+use std::{env, fs, process};
+
+use client_rust_functions::boids::metrics::FlockMetrics;
+use client_rust_functions::boids::settings::WorldSettings;
+use client_rust_functions::boids::BoidOrchestrator;
+use client_rust_functions::game_of_life::lib::Universe;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Simulation {
+    Boids,
+    GameOfLife,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct BenchConfig {
+    simulation: Simulation,
+    seed: u32,
+    ticks: u32,
+    dt: f32,
+    width: u32,
+    height: u32,
+    boids: u32,
+    // Full world settings, overrides width and height. Defaults when missing.
+    settings: Option<WorldSettings>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            simulation: Simulation::Boids,
+            seed: 1,
+            ticks: 500,
+            dt: 1.0 / 8.0,
+            width: 1920,
+            height: 1080,
+            boids: 1000,
+            settings: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+struct Report {
+    tick_ms: Vec<f64>,
+    rules_ms: Vec<(&'static str, f64)>,
+    metrics: Value,
+}
+
+fn main() {
+    let mut config_path = None;
+    let mut format = Format::Json;
+    let mut ticks = None;
+    let mut per_tick = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("json") => Format::Json,
+                    Some("csv") => Format::Csv,
+                    other => fail(&format!("unknown format {:?}, expected json or csv", other)),
+                }
+            }
+            "--ticks" => {
+                ticks = Some(
+                    args.next()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or_else(|| fail("--ticks needs a number")),
+                )
+            }
+            "--per-tick" => per_tick = true,
+            path if !path.starts_with("--") => config_path = Some(path.to_string()),
+            other => fail(&format!("unknown argument {}", other)),
+        }
+    }
+
+    let mut config: BenchConfig = match config_path {
+        Some(path) => {
+            let text = fs::read_to_string(&path).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+            serde_json::from_str(&text).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)))
+        }
+        None => BenchConfig::default(),
+    };
+    if let Some(ticks) = ticks {
+        config.ticks = ticks;
+    }
+
+    let report = match config.simulation {
+        Simulation::Boids => run_boids(&config),
+        Simulation::GameOfLife => run_game_of_life(&config),
+    };
+    match format {
+        Format::Json => print_json(&config, &report, per_tick),
+        Format::Csv => print_csv(&config, &report, per_tick),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("bench: {}", message);
+    process::exit(2);
+}
+
+fn run_boids(config: &BenchConfig) -> Report {
+    let settings = config
+        .settings
+        .clone()
+        .unwrap_or_else(|| WorldSettings::default(config.width, config.height));
+    let mut orchestrator = BoidOrchestrator::with_seed(settings, config.boids, config.seed);
+    let tick_ms = time_ticks(config.ticks, || orchestrator.tick(config.dt));
+
+    let start = Instant::now();
+    let rules_ms = orchestrator
+        .profile_rules(|| start.elapsed().as_secs_f64())
+        .into_iter()
+        .map(|(name, seconds)| (name, seconds * 1000.0))
+        .collect();
+    let link_distance = orchestrator.world_settings().neighbors.perception_range;
+    let metrics = FlockMetrics::measure(orchestrator.boids(), link_distance);
+    Report {
+        tick_ms,
+        rules_ms,
+        metrics: serde_json::to_value(metrics).expect("metrics serialize"),
+    }
+}
+
+fn run_game_of_life(config: &BenchConfig) -> Report {
+    let mut universe = Universe::with_seed(config.width, config.height, config.seed);
+    let tick_ms = time_ticks(config.ticks, || universe.tick());
+    Report {
+        tick_ms,
+        rules_ms: Vec::new(),
+        metrics: json!({ "live_cells": universe.live_cell_count() }),
+    }
+}
+
+fn time_ticks(ticks: u32, mut tick: impl FnMut()) -> Vec<f64> {
+    (0..ticks)
+        .map(|_| {
+            let start = Instant::now();
+            tick();
+            start.elapsed().as_secs_f64() * 1000.0
+        })
+        .collect()
+}
+
+/**
+ * mean, p50, p95, max and total of the tick times, in milliseconds.
+ */
+fn summary(tick_ms: &[f64]) -> Vec<(&'static str, f64)> {
+    if tick_ms.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = tick_ms.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+    let total: f64 = sorted.iter().sum();
+    vec![
+        ("mean", total / sorted.len() as f64),
+        ("p50", percentile(0.5)),
+        ("p95", percentile(0.95)),
+        ("max", sorted[sorted.len() - 1]),
+        ("total", total),
+    ]
+}
+
+fn print_json(config: &BenchConfig, report: &Report, per_tick: bool) {
+    let to_object = |pairs: &[(&str, f64)]| {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), json!(value)))
+            .collect::<serde_json::Map<_, _>>()
+    };
+    let mut output = json!({
+        "simulation": config.simulation,
+        "seed": config.seed,
+        "ticks": config.ticks,
+        "tick_ms": to_object(&summary(&report.tick_ms)),
+        "rules_ms": to_object(&report.rules_ms),
+        "metrics": report.metrics,
+    });
+    if per_tick {
+        output["per_tick_ms"] = json!(report.tick_ms);
+    }
+    println!("{}", serde_json::to_string_pretty(&output).expect("report serializes"));
+}
+
+fn print_csv(config: &BenchConfig, report: &Report, per_tick: bool) {
+    println!("key,value");
+    println!("seed,{}", config.seed);
+    println!("ticks,{}", config.ticks);
+    for (name, value) in summary(&report.tick_ms) {
+        println!("tick_ms.{},{}", name, value);
+    }
+    for (name, value) in &report.rules_ms {
+        println!("rules_ms.{},{}", name, value);
+    }
+    if let Value::Object(metrics) = &report.metrics {
+        for (name, value) in metrics {
+            println!("metrics.{},{}", name, value);
+        }
+    }
+    if per_tick {
+        for (tick, value) in report.tick_ms.iter().enumerate() {
+            println!("per_tick_ms.{},{}", tick + 1, value);
+        }
+    }
+}
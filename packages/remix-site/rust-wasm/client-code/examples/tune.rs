@@ -0,0 +1,135 @@
+//! Searches for boid settings that produce a target kind of flock, and prints the best
+//! `WorldSettings` as a preset.
+//!
+//! ```text
+//! cargo run --release --features parallel --example tune -- examples/tune.json > preset.json
+//! ```
+//!
+//! Every candidate runs headless on a few seeds and is scored on the `FlockMetrics` of the
+//! final tick, averaged over the seeds. Progress goes to stderr, the preset to stdout.
+
+use serde::Deserialize;
+use std::{env, fs, process};
+
+use client_rust_functions::boids::metrics::FlockMetrics;
+use client_rust_functions::boids::rng::SimRng;
+use client_rust_functions::boids::settings::WorldSettings;
+use client_rust_functions::boids::tuning::{self, Metric, MetricBound, SearchSettings, TunableParameter};
+use client_rust_functions::boids::BoidOrchestrator;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct TuneConfig {
+    seed: u32,
+    // Each candidate is run once per seed, seed..seed + runs.
+    runs: u32,
+    ticks: u32,
+    dt: f32,
+    width: u32,
+    height: u32,
+    boids: u32,
+    // Gap that splits two flocks. Fixed, so tuning the perception range can't move it.
+    link_distance: f32,
+    // Starting point, parameters that aren't tuned keep these values.
+    base: Option<WorldSettings>,
+    parameters: Vec<TunableParameter>,
+    target: Vec<MetricBound>,
+    search: SearchSettings,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        TuneConfig {
+            seed: 1,
+            runs: 3,
+            ticks: 400,
+            dt: 1.0 / 8.0,
+            width: 1280,
+            height: 720,
+            boids: 300,
+            link_distance: 100.0,
+            base: None,
+            parameters: TunableParameter::ALL.to_vec(),
+            // Tight, well aligned flocks, at least three of them.
+            target: vec![
+                MetricBound {
+                    metric: Metric::Polarization,
+                    min: Some(0.9),
+                    max: None,
+                },
+                MetricBound {
+                    metric: Metric::FlockCount,
+                    min: Some(3.0),
+                    max: None,
+                },
+            ],
+            search: SearchSettings::default(),
+        }
+    }
+}
+
+fn main() {
+    let config: TuneConfig = match env::args().nth(1) {
+        Some(path) => {
+            let text = fs::read_to_string(&path).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)));
+            serde_json::from_str(&text).unwrap_or_else(|error| fail(&format!("{}: {}", path, error)))
+        }
+        None => TuneConfig::default(),
+    };
+    if config.parameters.is_empty() {
+        fail("no parameters to tune");
+    }
+    let base = config
+        .base
+        .clone()
+        .unwrap_or_else(|| WorldSettings::default(config.width, config.height));
+
+    let mut generation = 0;
+    let result = tuning::tune(
+        &base,
+        &config.parameters,
+        &config.search,
+        &mut SimRng::new(config.seed),
+        |candidates| {
+            let scores = score_all(&config, candidates);
+            generation += 1;
+            let best = scores.iter().copied().fold(f32::INFINITY, f32::min);
+            eprintln!("generation {}: best score {:.4}", generation, best);
+            scores
+        },
+    );
+    eprintln!("best score {:.4}", result.best_score);
+    println!("{}", serde_json::to_string_pretty(&result.best).expect("settings serialize"));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("tune: {}", message);
+    process::exit(2);
+}
+
+#[cfg(feature = "parallel")]
+fn score_all(config: &TuneConfig, candidates: &[WorldSettings]) -> Vec<f32> {
+    use rayon::prelude::*;
+    candidates.par_iter().map(|settings| score_settings(config, settings)).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn score_all(config: &TuneConfig, candidates: &[WorldSettings]) -> Vec<f32> {
+    candidates.iter().map(|settings| score_settings(config, settings)).collect()
+}
+
+fn score_settings(config: &TuneConfig, settings: &WorldSettings) -> f32 {
+    let runs = config.runs.max(1);
+    let total: f32 = (0..runs)
+        .map(|run| {
+            let mut orchestrator =
+                BoidOrchestrator::with_seed(settings.clone(), config.boids, config.seed.wrapping_add(run));
+            for _ in 0..config.ticks {
+                orchestrator.tick_single_threaded(config.dt);
+            }
+            let metrics = FlockMetrics::measure(orchestrator.boids(), config.link_distance);
+            tuning::score(&config.target, &metrics)
+        })
+        .sum();
+    total / runs as f32
+}
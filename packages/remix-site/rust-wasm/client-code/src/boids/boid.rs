@@ -1,8 +1,9 @@
 use cgmath::Vector2;
-use js_sys::Math::atan2;
 use wasm_bindgen::prelude::wasm_bindgen;
 use std::fmt;
 
+use crate::boids::rng::SimRng;
+use crate::boids::traits::BoidTraits;
 use crate::boids::utils::LinearSerializable;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -14,6 +15,13 @@ pub struct Boid {
     pub velocity: Vector2<f32>,
     
     pub id: u32,
+    // Collision radius. Zero means "use the world's default boid radius".
+    pub radius: f32,
+    // Only used by the ecosystem, the boid starves when it runs out.
+    pub energy: f32,
+    // Inherited, with some mutation, by the boids it splits into.
+    #[wasm_bindgen(skip)]
+    pub traits: BoidTraits,
 }
 
 impl fmt::Display for Boid {
@@ -42,28 +50,40 @@ impl Boid {
     }
 }
 impl Boid {
+    pub const START_ENERGY: f32 = 50.0;
+
     pub fn new(position: Vector2<f32>, velocity: Vector2<f32>, id: u32) -> Boid {
         Boid {
             position,
             velocity,
             id,
+            radius: 0.0,
+            energy: Boid::START_ENERGY,
+            traits: BoidTraits::default(),
         }
     }
 
-    pub fn new_random_boid_in_world(world_size_x: u32, world_size_y: u32, id: u32) -> Boid {
-        let boid = Boid {
+    pub fn new_random_boid_in_world(
+        world_size_x: u32,
+        world_size_y: u32,
+        id: u32,
+        rng: &mut SimRng,
+    ) -> Boid {
+        Boid {
             position: Vector2 {
-                x: js_sys::Math::random() as f32 * world_size_x as f32,
-                y: js_sys::Math::random() as f32 * world_size_y as f32,
+                x: rng.next_f32() * world_size_x as f32,
+                y: rng.next_f32() * world_size_y as f32,
             },
             velocity: Vector2 { x: 0.0, y: 0.0 },
             id,
-        };
-        boid
+            radius: 0.0,
+            energy: Boid::START_ENERGY,
+            traits: BoidTraits::default(),
+        }
     }
     
     pub fn get_velocity_direction(&self) -> f64 {
-        return atan2(self.velocity.y as f64, self.velocity.x as f64);
+        (self.velocity.y as f64).atan2(self.velocity.x as f64)
     }
 }
 
@@ -85,6 +105,9 @@ impl LinearSerializable for Boid {
             position: Vector2::new(buffer[offset], buffer[offset + 1]),
             velocity: Vector2::new(0.0, 0.0),
             id: 0,
+            radius: 0.0,
+            energy: Boid::START_ENERGY,
+            traits: BoidTraits::default(),
         };
         
         (boid, Self::NUM_ELEMENTS)
@@ -95,8 +118,7 @@ impl LinearSerializable for Boid {
     }
 }
 pub fn serialize_boids(boids: &[Boid], buffer: &mut [f32]) {
-    let mut offset = 0;
-    for boid in boids {
-        offset += boid.serialize_to_array(buffer, offset);
+    for (i, boid) in boids.iter().enumerate() {
+        boid.serialize_to_array(buffer, i);
     }
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use cgmath::{InnerSpace, Vector3};
+use std::fmt;
+
+use crate::boids::rng::SimRng;
+use crate::boids::utils::LinearSerializable;
+
+/**
+ * A boid in the 3D world box. Same idea as `Boid`, one more axis.
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Boid3D {
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub id: u32,
+}
+
+impl fmt::Display for Boid3D {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "id: {}; position: {}, {}, {}; velocity: {}, {}, {}",
+            self.id,
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.velocity.x,
+            self.velocity.y,
+            self.velocity.z
+        )
+    }
+}
+
+impl Boid3D {
+    pub fn new(position: Vector3<f32>, velocity: Vector3<f32>, id: u32) -> Boid3D {
+        Boid3D {
+            position,
+            velocity,
+            id,
+        }
+    }
+
+    pub fn new_random_boid_in_world(
+        world_size_x: u32,
+        world_size_y: u32,
+        world_size_z: u32,
+        id: u32,
+        rng: &mut SimRng,
+    ) -> Boid3D {
+        Boid3D {
+            position: Vector3 {
+                x: rng.next_f32() * world_size_x as f32,
+                y: rng.next_f32() * world_size_y as f32,
+                z: rng.next_f32() * world_size_z as f32,
+            },
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            id,
+        }
+    }
+
+    /**
+     * Unit vector the boid is heading in. A boid that isn't moving faces +x.
+     */
+    pub fn forward(&self) -> Vector3<f32> {
+        if self.velocity.magnitude2() > 0.0 {
+            self.velocity.normalize()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        }
+    }
+}
+
+impl LinearSerializable for Boid3D {
+    const NUM_ELEMENTS: usize = 6;
+    fn serialize_to_array(&self, buffer: &mut [f32], offset: usize) -> usize {
+        // x, y, z, then the forward vector, which the renderer turns into a rotation.
+        let forward = self.forward();
+        let start = offset * Self::NUM_ELEMENTS;
+        buffer[start..start + Self::NUM_ELEMENTS].copy_from_slice(&[
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            forward.x,
+            forward.y,
+            forward.z,
+        ]);
+        Self::NUM_ELEMENTS
+    }
+
+    fn deserialize_from_array(buffer: &[f32], offset: usize) -> (Self, usize) {
+        let boid = Boid3D::new(
+            Vector3::new(buffer[offset], buffer[offset + 1], buffer[offset + 2]),
+            Vector3::new(0.0, 0.0, 0.0),
+            0,
+        );
+        (boid, Self::NUM_ELEMENTS)
+    }
+
+    fn serialized_size() -> usize {
+        Self::NUM_ELEMENTS
+    }
+}
+
+pub fn serialize_boids_3d(boids: &[Boid3D], buffer: &mut [f32]) {
+    for (i, boid) in boids.iter().enumerate() {
+        boid.serialize_to_array(buffer, i);
+    }
+}
@@ -0,0 +1,188 @@
+use cgmath::{InnerSpace, Vector2};
+
+use crate::boids::boid::Boid;
+use crate::boids::obstacle::Obstacle;
+use crate::boids::settings::CollisionSettings;
+use crate::boids::spatial::SpatialGrid;
+
+/**
+ * Position based collision pass, run after the boids have been integrated. Overlapping
+ * boids are pushed apart, and boids are pushed out of obstacles and (optionally) back
+ * inside the world walls.
+ *
+ * Velocities are only ever reduced: the part of a boid's velocity heading into whatever
+ * it collided with is removed, so the pass can't inject energy into the flock.
+ */
+pub fn resolve_collisions(
+    boids: &mut [Boid],
+    settings: &CollisionSettings,
+    obstacles: &[Obstacle],
+    world_width: f32,
+    world_height: f32,
+    grid: &mut SpatialGrid,
+) {
+    if !settings.enabled || boids.is_empty() {
+        return;
+    }
+    let radius_of = |boid: &Boid| {
+        let radius = if boid.radius > 0.0 {
+            boid.radius
+        } else {
+            settings.boid_radius
+        };
+        radius * boid.traits.size
+    };
+    let max_radius = boids.iter().map(radius_of).fold(0.0, f32::max);
+    let mut pairs = Vec::new();
+
+    for _ in 0..settings.iterations.max(1) {
+        if max_radius > 0.0 {
+            grid.build(boids, max_radius * 2.0);
+            pairs.clear();
+            for i in 0..boids.len() {
+                grid.for_each_in_radius(boids, boids[i].position, max_radius * 2.0, |j| {
+                    if j > i {
+                        pairs.push((i, j));
+                    }
+                });
+            }
+            pairs.sort_unstable();
+            for &(i, j) in &pairs {
+                let (a, b) = (boids[i], boids[j]);
+                let min_distance = radius_of(&a) + radius_of(&b);
+                let offset = b.position - a.position;
+                let distance = offset.magnitude();
+                if distance >= min_distance {
+                    continue;
+                }
+                let normal = if distance > f32::EPSILON {
+                    offset / distance
+                } else {
+                    // Exactly on top of each other, split them along x by id so it's stable.
+                    if a.id < b.id {
+                        Vector2::new(1.0, 0.0)
+                    } else {
+                        Vector2::new(-1.0, 0.0)
+                    }
+                };
+                let correction = normal * ((min_distance - distance) * 0.5);
+                boids[i].position -= correction;
+                boids[j].position += correction;
+
+                // Cancel the closing speed, split evenly (equal masses). The push can speed
+                // up the boid that was hit, so neither ends up faster than it was.
+                let closing = (a.velocity - b.velocity).dot(normal);
+                if closing > 0.0 {
+                    boids[i].velocity = no_faster(a.velocity - normal * (closing * 0.5), a.velocity);
+                    boids[j].velocity = no_faster(b.velocity + normal * (closing * 0.5), b.velocity);
+                }
+            }
+        }
+
+        for boid in boids.iter_mut() {
+            let radius = radius_of(boid);
+            for obstacle in obstacles {
+                push_out_of_obstacle(boid, obstacle, radius);
+            }
+            if settings.walls {
+                keep_inside_walls(boid, radius, world_width, world_height);
+            }
+        }
+    }
+}
+
+fn no_faster(velocity: Vector2<f32>, before: Vector2<f32>) -> Vector2<f32> {
+    let speed = before.magnitude();
+    if velocity.magnitude2() > speed * speed {
+        velocity.normalize_to(speed)
+    } else {
+        velocity
+    }
+}
+
+fn push_out_of_obstacle(boid: &mut Boid, obstacle: &Obstacle, radius: f32) {
+    let offset = boid.position - obstacle.center;
+    let distance = offset.magnitude();
+    let min_distance = obstacle.radius + radius;
+    if distance >= min_distance {
+        return;
+    }
+    let normal = if distance > f32::EPSILON {
+        offset / distance
+    } else {
+        Vector2::new(1.0, 0.0)
+    };
+    boid.position = obstacle.center + normal * min_distance;
+    let into = boid.velocity.dot(normal);
+    if into < 0.0 {
+        boid.velocity -= normal * into;
+    }
+}
+
+fn keep_inside_walls(boid: &mut Boid, radius: f32, world_width: f32, world_height: f32) {
+    let radius_x = radius.min(world_width * 0.5);
+    let radius_y = radius.min(world_height * 0.5);
+    if boid.position.x < radius_x {
+        boid.position.x = radius_x;
+        boid.velocity.x = boid.velocity.x.max(0.0);
+    } else if boid.position.x > world_width - radius_x {
+        boid.position.x = world_width - radius_x;
+        boid.velocity.x = boid.velocity.x.min(0.0);
+    }
+    if boid.position.y < radius_y {
+        boid.position.y = radius_y;
+        boid.velocity.y = boid.velocity.y.max(0.0);
+    } else if boid.position.y > world_height - radius_y {
+        boid.position.y = world_height - radius_y;
+        boid.velocity.y = boid.velocity.y.min(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> CollisionSettings {
+        CollisionSettings::new(true, 5.0, 4, true)
+    }
+
+    fn kinetic_energy(boids: &[Boid]) -> f32 {
+        boids.iter().map(|boid| boid.velocity.magnitude2()).sum()
+    }
+
+    #[test]
+    fn overlapping_boids_are_separated_without_gaining_energy() {
+        let mut boids = vec![
+            Boid::new(Vector2::new(50.0, 50.0), Vector2::new(3.0, 0.0), 0),
+            Boid::new(Vector2::new(54.0, 50.0), Vector2::new(-3.0, 1.0), 1),
+            Boid::new(Vector2::new(52.0, 53.0), Vector2::new(0.0, -2.0), 2),
+        ];
+        let energy_before = kinetic_energy(&boids);
+        let mut grid = SpatialGrid::new();
+        resolve_collisions(&mut boids, &settings(), &[], 100.0, 100.0, &mut grid);
+
+        for i in 0..boids.len() {
+            for j in i + 1..boids.len() {
+                let distance = (boids[i].position - boids[j].position).magnitude();
+                assert!(distance > 9.0, "boids {} and {} still overlap: {}", i, j, distance);
+            }
+        }
+        assert!(kinetic_energy(&boids) <= energy_before);
+    }
+
+    #[test]
+    fn boids_are_kept_out_of_obstacles_and_walls() {
+        let mut boids = vec![
+            Boid::new(Vector2::new(48.0, 50.0), Vector2::new(2.0, 0.0), 0),
+            Boid::new(Vector2::new(-10.0, 120.0), Vector2::new(-4.0, 4.0), 1),
+        ];
+        let obstacle = Obstacle::new(Vector2::new(50.0, 50.0), 10.0);
+        let mut grid = SpatialGrid::new();
+        resolve_collisions(&mut boids, &settings(), &[obstacle], 100.0, 100.0, &mut grid);
+
+        assert!((boids[0].position - obstacle.center).magnitude() >= 15.0 - 1e-4);
+        assert!(boids[0].velocity.x <= 0.0);
+        assert_eq!(boids[1].position, Vector2::new(5.0, 95.0));
+        assert_eq!(boids[1].velocity, Vector2::new(0.0, 0.0));
+    }
+}
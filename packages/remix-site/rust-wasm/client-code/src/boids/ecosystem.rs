@@ -0,0 +1,179 @@
+use cgmath::{InnerSpace, Vector2};
+use std::collections::VecDeque;
+
+use crate::boids::boid::Boid;
+use crate::boids::rng::SimRng;
+use crate::boids::settings::EcosystemSettings;
+
+/**
+ * A circle of food boids can eat from. Eaten food grows back at `regrowth` per second,
+ * up to `capacity`.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FoodPatch {
+    pub center: Vector2<f32>,
+    pub radius: f32,
+    pub capacity: f32,
+    pub amount: f32,
+    pub regrowth: f32,
+}
+
+impl FoodPatch {
+    pub fn new(center: Vector2<f32>, radius: f32, capacity: f32, regrowth: f32) -> FoodPatch {
+        FoodPatch {
+            center,
+            radius,
+            capacity,
+            amount: capacity,
+            regrowth,
+        }
+    }
+}
+
+/**
+ * The lifecycle layer: food patches plus population statistics. Each step boids burn
+ * energy, eat, split when they have enough and are removed when they run out.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Ecosystem {
+    pub patches: Vec<FoodPatch>,
+    births: u32,
+    deaths: u32,
+    // Flock size after each step, oldest first.
+    population: VecDeque<u32>,
+}
+
+impl Ecosystem {
+    pub const POPULATION_HISTORY_LENGTH: usize = 1000;
+
+    pub fn births(&self) -> u32 {
+        self.births
+    }
+
+    pub fn deaths(&self) -> u32 {
+        self.deaths
+    }
+
+    pub fn population_history(&self) -> &VecDeque<u32> {
+        &self.population
+    }
+
+    /**
+     * Runs one step of the lifecycle over `boids`. Children take fresh ids from
+     * `next_id`, so a dead boid's id is never handed out again, and are appended after
+     * their parents. Returns true when boids were born or died.
+     */
+    pub fn step(
+        &mut self,
+        boids: &mut Vec<Boid>,
+        settings: &EcosystemSettings,
+        dt: f32,
+        rng: &mut SimRng,
+        next_id: &mut u32,
+    ) -> bool {
+        for boid in boids.iter_mut() {
+            boid.energy -= (settings.base_drain + settings.speed_drain * boid.velocity.magnitude()) * dt;
+        }
+
+        // Boids eat in index order, so a crowded patch runs out for the later ones.
+        for patch in &mut self.patches {
+            let radius2 = patch.radius * patch.radius;
+            for boid in boids.iter_mut() {
+                if patch.amount <= 0.0 {
+                    break;
+                }
+                if (boid.position - patch.center).magnitude2() <= radius2 {
+                    let bite = (settings.eat_rate * dt).min(patch.amount);
+                    boid.energy += bite;
+                    patch.amount -= bite;
+                }
+            }
+            patch.amount = (patch.amount + patch.regrowth * dt).min(patch.capacity);
+        }
+
+        let before = boids.len();
+        boids.retain(|boid| boid.energy > 0.0);
+        let died = before - boids.len();
+
+        let mut children = Vec::new();
+        for parent in boids.iter_mut() {
+            if parent.energy < settings.reproduction_energy
+                || before - died + children.len() >= settings.max_population as usize
+            {
+                continue;
+            }
+            parent.energy /= 2.0;
+            let mut child = *parent;
+            child.id = *next_id;
+            *next_id += 1;
+            child.traits = parent.traits.mutated(settings.mutation, rng);
+            // Nudge the child off its parent so separation can pull them apart.
+            child.position += Vector2::new(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0));
+            children.push(child);
+        }
+        let born = children.len();
+        boids.extend(children);
+
+        self.births += born as u32;
+        self.deaths += died as u32;
+        if self.population.len() == Ecosystem::POPULATION_HISTORY_LENGTH {
+            self.population.pop_front();
+        }
+        self.population.push_back(boids.len() as u32);
+        born > 0 || died > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> EcosystemSettings {
+        EcosystemSettings::new(true, 10.0, 0.0, 100.0, 60.0, 0.2, 10)
+    }
+
+    #[test]
+    fn fed_boids_split_and_starving_boids_die() {
+        let mut ecosystem = Ecosystem::default();
+        ecosystem
+            .patches
+            .push(FoodPatch::new(Vector2::new(0.0, 0.0), 10.0, 1000.0, 0.0));
+        let mut boids = vec![
+            Boid::new(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 0),
+            Boid::new(Vector2::new(500.0, 0.0), Vector2::new(0.0, 0.0), 1),
+        ];
+        boids[1].energy = 5.0;
+        let mut rng = SimRng::new(3);
+        let mut next_id = 2;
+
+        assert!(ecosystem.step(&mut boids, &settings(), 1.0, &mut rng, &mut next_id));
+        // 50 - 10 + 100 = 140, enough to split into two boids with 70 each.
+        assert_eq!(boids.len(), 2);
+        assert_eq!(boids[0].id, 0);
+        // Boid 1 starved, its id isn't reused for the child.
+        assert_eq!(boids[1].id, 2);
+        assert_eq!(next_id, 3);
+        assert_eq!(boids[0].energy, 70.0);
+        assert_eq!(boids[1].energy, 70.0);
+        assert_ne!(boids[1].traits, boids[0].traits);
+        assert!(boids[1].traits.as_array().iter().all(|&value| (value - 1.0).abs() <= 0.2));
+        assert_eq!(ecosystem.births(), 1);
+        assert_eq!(ecosystem.deaths(), 1);
+        assert_eq!(ecosystem.patches[0].amount, 900.0);
+        assert_eq!(ecosystem.population_history(), &VecDeque::from(vec![2]));
+    }
+
+    #[test]
+    fn births_stop_at_the_population_cap() {
+        let mut ecosystem = Ecosystem::default();
+        let mut boids: Vec<Boid> = (0..10)
+            .map(|i| Boid::new(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0), i))
+            .collect();
+        for boid in &mut boids {
+            boid.energy = 200.0;
+        }
+        ecosystem.step(&mut boids, &settings(), 0.1, &mut SimRng::new(1), &mut 10);
+        assert_eq!(boids.len(), 10);
+        assert!(boids.iter().all(|boid| boid.energy == 199.0));
+    }
+}
@@ -0,0 +1,311 @@
+use cgmath::{InnerSpace, Vector2};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::boids::boid::Boid;
+use crate::boids::metrics::flock_labels;
+use crate::boids::region::RegionShape;
+use crate::boids::spatial::SpatialGrid;
+
+/**
+ * Something that happened in the simulation, on the tick it happened. Each event fires
+ * once when it starts, not on every tick it holds.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SimEvent {
+    LeftBounds { tick: u32, id: u32 },
+    // Boids `a` and `b` (a < b) came within the proximity distance.
+    Proximity { tick: u32, a: u32, b: u32, distance: f32 },
+    EnteredRegion { tick: u32, id: u32, region: u32 },
+    LeftRegion { tick: u32, id: u32, region: u32 },
+    // `flocks` flocks joined into one of `size` boids.
+    FlocksMerged { tick: u32, flocks: u32, size: u32 },
+    // A flock of `size` boids broke into `flocks` flocks.
+    FlockSplit { tick: u32, flocks: u32, size: u32 },
+}
+
+/**
+ * Watches the boids after every tick and queues an event when something starts. JS
+ * polls the queue instead of scanning the transfer array every frame.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct EventMonitor {
+    bounds: bool,
+    // 0 turns these events off.
+    proximity_distance: f32,
+    flock_link_distance: f32,
+    // Smallest part of a flock that counts when flocks merge or split, so single strays
+    // don't fire events.
+    min_flock_size: u32,
+    regions: Vec<(u32, RegionShape)>,
+    next_region_id: u32,
+
+    outside: HashSet<u32>,
+    close_pairs: HashSet<(u32, u32)>,
+    in_regions: HashSet<(u32, u32)>,
+    // Flock label of every boid by id, from the last observation.
+    flocks: HashMap<u32, usize>,
+
+    queue: VecDeque<SimEvent>,
+    dropped: u32,
+}
+
+impl EventMonitor {
+    // Oldest events are dropped past this, in case nobody is polling.
+    pub const MAX_QUEUED: usize = 10_000;
+
+    pub fn set_bounds(&mut self, enabled: bool) {
+        self.bounds = enabled;
+        self.outside.clear();
+    }
+
+    pub fn set_proximity_distance(&mut self, distance: f32) {
+        self.proximity_distance = distance.max(0.0);
+        self.close_pairs.clear();
+    }
+
+    pub fn set_flock_link_distance(&mut self, distance: f32, min_flock_size: u32) {
+        self.flock_link_distance = distance.max(0.0);
+        self.min_flock_size = min_flock_size.max(1);
+        self.flocks.clear();
+    }
+
+    pub fn add_region(&mut self, shape: RegionShape) -> u32 {
+        let id = self.next_region_id;
+        self.next_region_id += 1;
+        self.regions.push((id, shape));
+        id
+    }
+
+    pub fn remove_region(&mut self, id: u32) -> bool {
+        let before = self.regions.len();
+        self.regions.retain(|&(region, _)| region != id);
+        self.in_regions.retain(|&(_, region)| region != id);
+        self.regions.len() != before
+    }
+
+    pub fn clear_regions(&mut self) {
+        self.regions.clear();
+        self.in_regions.clear();
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bounds || self.proximity_distance > 0.0 || self.flock_link_distance > 0.0 || !self.regions.is_empty()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub fn pop(&mut self) -> Option<SimEvent> {
+        self.queue.pop_front()
+    }
+
+    /**
+     * Puts a popped event back at the front, for when it couldn't be delivered.
+     */
+    pub fn requeue(&mut self, event: SimEvent) {
+        self.queue.push_front(event);
+    }
+
+    pub fn drain(&mut self) -> Vec<SimEvent> {
+        self.queue.drain(..).collect()
+    }
+
+    /**
+     * Compares `boids` against the last observation and queues what changed. With
+     * `emit` false only the tracked state is updated, for when the world jumped (a seek)
+     * rather than moved. `grid` has to be built over `boids`.
+     */
+    pub fn observe(&mut self, tick: u32, boids: &[Boid], grid: &SpatialGrid, world: Vector2<f32>, emit: bool) {
+        let mut events = Vec::new();
+
+        if self.bounds {
+            let mut outside = HashSet::new();
+            for boid in boids {
+                let p = boid.position;
+                if p.x < 0.0 || p.y < 0.0 || p.x > world.x || p.y > world.y {
+                    outside.insert(boid.id);
+                    if !self.outside.contains(&boid.id) {
+                        events.push(SimEvent::LeftBounds { tick, id: boid.id });
+                    }
+                }
+            }
+            self.outside = outside;
+        }
+
+        if self.proximity_distance > 0.0 {
+            let mut close_pairs = HashSet::new();
+            for boid in boids {
+                grid.for_each_in_radius(boids, boid.position, self.proximity_distance, |j| {
+                    let other = &boids[j];
+                    if boid.id < other.id {
+                        let pair = (boid.id, other.id);
+                        close_pairs.insert(pair);
+                        if !self.close_pairs.contains(&pair) {
+                            let distance = (other.position - boid.position).magnitude();
+                            events.push(SimEvent::Proximity { tick, a: pair.0, b: pair.1, distance });
+                        }
+                    }
+                });
+            }
+            self.close_pairs = close_pairs;
+        }
+
+        if !self.regions.is_empty() {
+            let mut in_regions = HashSet::new();
+            for &(region, shape) in &self.regions {
+                for boid in boids {
+                    if shape.contains(boid.position) {
+                        in_regions.insert((boid.id, region));
+                        if !self.in_regions.contains(&(boid.id, region)) {
+                            events.push(SimEvent::EnteredRegion { tick, id: boid.id, region });
+                        }
+                    }
+                }
+            }
+            let alive: HashSet<u32> = boids.iter().map(|boid| boid.id).collect();
+            // Boids that were removed don't leave, they're just gone.
+            let mut left: Vec<(u32, u32)> = self
+                .in_regions
+                .iter()
+                .filter(|&&(id, region)| alive.contains(&id) && !in_regions.contains(&(id, region)))
+                .copied()
+                .collect();
+            left.sort_unstable();
+            events.extend(left.into_iter().map(|(id, region)| SimEvent::LeftRegion { tick, id, region }));
+            self.in_regions = in_regions;
+        }
+
+        if self.flock_link_distance > 0.0 {
+            let labels = flock_labels(boids, grid, self.flock_link_distance);
+            events.extend(self.flock_changes(tick, boids, &labels));
+            self.flocks = boids.iter().zip(labels).map(|(boid, label)| (boid.id, label)).collect();
+        }
+
+        if emit {
+            for event in events {
+                if self.queue.len() == EventMonitor::MAX_QUEUED {
+                    self.queue.pop_front();
+                    self.dropped += 1;
+                }
+                self.queue.push_back(event);
+            }
+        }
+    }
+
+    /**
+     * Merges and splits between the last flocks and `labels`. A flock only takes part
+     * with at least `min_flock_size` of its boids, so boids drifting between flocks
+     * don't count.
+     */
+    fn flock_changes(&self, tick: u32, boids: &[Boid], labels: &[usize]) -> Vec<SimEvent> {
+        // Boids shared by each (previous flock, current flock) pair.
+        let mut overlap: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut sizes: HashMap<usize, u32> = HashMap::new();
+        for (boid, &label) in boids.iter().zip(labels) {
+            *sizes.entry(label).or_default() += 1;
+            if let Some(&previous) = self.flocks.get(&boid.id) {
+                *overlap.entry((previous, label)).or_default() += 1;
+            }
+        }
+        let mut previous_sizes: HashMap<usize, u32> = HashMap::new();
+        for &label in self.flocks.values() {
+            *previous_sizes.entry(label).or_default() += 1;
+        }
+
+        let mut sources: HashMap<usize, u32> = HashMap::new();
+        let mut parts: HashMap<usize, u32> = HashMap::new();
+        for (&(previous, current), &count) in &overlap {
+            if count >= self.min_flock_size {
+                *sources.entry(current).or_default() += 1;
+                *parts.entry(previous).or_default() += 1;
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut merged: Vec<(usize, u32)> = sources.into_iter().filter(|&(_, count)| count > 1).collect();
+        merged.sort_unstable();
+        for (current, flocks) in merged {
+            events.push(SimEvent::FlocksMerged { tick, flocks, size: sizes[&current] });
+        }
+        let mut split: Vec<(usize, u32)> = parts.into_iter().filter(|&(_, count)| count > 1).collect();
+        split.sort_unstable();
+        for (previous, flocks) in split {
+            events.push(SimEvent::FlockSplit { tick, flocks, size: previous_sizes[&previous] });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe(monitor: &mut EventMonitor, tick: u32, boids: &[Boid]) {
+        let mut grid = SpatialGrid::new();
+        grid.build(boids, 20.0);
+        monitor.observe(tick, boids, &grid, Vector2::new(100.0, 100.0), true);
+    }
+
+    fn boid(id: u32, x: f32, y: f32) -> Boid {
+        Boid::new(Vector2::new(x, y), Vector2::new(0.0, 0.0), id)
+    }
+
+    #[test]
+    fn fires_once_when_a_condition_starts() {
+        let mut monitor = EventMonitor::default();
+        monitor.set_bounds(true);
+        monitor.set_proximity_distance(5.0);
+        let region = monitor.add_region(RegionShape::Circle {
+            center: Vector2::new(50.0, 50.0),
+            radius: 10.0,
+        });
+
+        observe(&mut monitor, 1, &[boid(0, 10.0, 10.0), boid(1, 30.0, 10.0)]);
+        assert_eq!(monitor.queued(), 0);
+        observe(&mut monitor, 2, &[boid(0, -1.0, 10.0), boid(1, 52.0, 50.0)]);
+        observe(&mut monitor, 3, &[boid(0, -2.0, 10.0), boid(1, 52.0, 50.0), boid(2, 54.0, 50.0)]);
+        observe(&mut monitor, 4, &[boid(0, -2.0, 10.0), boid(1, 80.0, 50.0)]);
+        // An event that couldn't be delivered goes back to the front.
+        let first = monitor.pop().unwrap();
+        monitor.requeue(first);
+        assert_eq!(
+            monitor.drain(),
+            vec![
+                SimEvent::LeftBounds { tick: 2, id: 0 },
+                SimEvent::EnteredRegion { tick: 2, id: 1, region },
+                SimEvent::Proximity { tick: 3, a: 1, b: 2, distance: 2.0 },
+                SimEvent::EnteredRegion { tick: 3, id: 2, region },
+                SimEvent::LeftRegion { tick: 4, id: 1, region },
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_flocks_merging_and_splitting() {
+        let mut monitor = EventMonitor::default();
+        monitor.set_flock_link_distance(5.0, 2);
+        let apart = [boid(0, 0.0, 0.0), boid(1, 3.0, 0.0), boid(2, 50.0, 0.0), boid(3, 53.0, 0.0)];
+        let together = [boid(0, 20.0, 0.0), boid(1, 23.0, 0.0), boid(2, 26.0, 0.0), boid(3, 29.0, 0.0)];
+        observe(&mut monitor, 1, &apart);
+        observe(&mut monitor, 2, &together);
+        observe(&mut monitor, 3, &apart);
+        assert_eq!(
+            monitor.drain(),
+            vec![
+                SimEvent::FlocksMerged { tick: 2, flocks: 2, size: 4 },
+                SimEvent::FlockSplit { tick: 3, flocks: 2, size: 4 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use cgmath::{InnerSpace, Vector2};
+
+use crate::boids::noise::Perlin;
+
+/**
+ * What the flow field is currently generated from. Kept around (instead of only the
+ * baked grid) so the field can be rebuilt when the world or the grid resolution changes,
+ * and so the curl noise can keep evolving over time.
+ */
+#[derive(Clone)]
+pub enum FlowSource {
+    None,
+    Uniform {
+        wind: Vector2<f32>,
+    },
+    Vortex {
+        center: Vector2<f32>,
+        strength: f32,
+        radius: f32,
+    },
+    /**
+     * Pulls boids towards `center`. A negative strength turns it into a source.
+     */
+    Sink {
+        center: Vector2<f32>,
+        strength: f32,
+        radius: f32,
+    },
+    CurlNoise {
+        noise: Box<Perlin>,
+        scale: f32,
+        strength: f32,
+        speed: f32,
+    },
+}
+
+/**
+ * A coarse grid of flow vectors covering the world. Each vector sits in the center of its
+ * cell, and boids sample it with bilinear interpolation.
+ */
+#[derive(Clone)]
+pub struct FlowField {
+    columns: usize,
+    rows: usize,
+    cell_size: f32,
+    time: f32,
+    source: FlowSource,
+    // x, y pairs, row major.
+    vectors: Vec<f32>,
+}
+
+impl FlowField {
+    pub const DEFAULT_CELL_SIZE: f32 = 50.0;
+
+    pub fn new(world_width: u32, world_height: u32, cell_size: f32) -> FlowField {
+        let mut field = FlowField {
+            columns: 0,
+            rows: 0,
+            cell_size,
+            time: 0.0,
+            source: FlowSource::None,
+            vectors: Vec::new(),
+        };
+        field.resize(world_width, world_height);
+        field
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn vectors(&self) -> &[f32] {
+        &self.vectors
+    }
+
+    pub fn source(&self) -> &FlowSource {
+        &self.source
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.source, FlowSource::None)
+    }
+
+    /**
+     * Re-grids the field for a new world size, keeping the current source.
+     */
+    pub fn resize(&mut self, world_width: u32, world_height: u32) {
+        self.columns = ((world_width as f32 / self.cell_size).ceil() as usize).max(1);
+        self.rows = ((world_height as f32 / self.cell_size).ceil() as usize).max(1);
+        self.vectors = vec![0.0; self.columns * self.rows * 2];
+        self.regenerate();
+    }
+
+    /**
+     * Moves the center of a vortex or sink with `f` and stretches the source by
+     * `length_factor`, used when the world is rescaled.
+     */
+    pub fn map_points(&mut self, f: impl Fn(Vector2<f32>) -> Vector2<f32>, length_factor: f32) {
+        match &mut self.source {
+            FlowSource::Vortex { center, radius, .. } | FlowSource::Sink { center, radius, .. } => {
+                *center = f(*center);
+                *radius *= length_factor;
+            }
+            // The scale is a frequency, a bigger world needs a lower one for the same swirls.
+            FlowSource::CurlNoise { scale, .. } if length_factor > 0.0 => *scale /= length_factor,
+            _ => return,
+        }
+        self.regenerate();
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f32, world_width: u32, world_height: u32) {
+        self.cell_size = cell_size.max(1.0);
+        self.resize(world_width, world_height);
+    }
+
+    pub fn set_source(&mut self, source: FlowSource) {
+        self.source = source;
+        self.time = 0.0;
+        self.regenerate();
+    }
+
+    /**
+     * Moves time forward. Only time varying sources (curl noise) need to be rebuilt.
+     */
+    pub fn advance(&mut self, dt: f32) {
+        if let FlowSource::CurlNoise { .. } = self.source {
+            self.time += dt;
+            self.regenerate();
+        }
+    }
+
+    /**
+     * Bilinearly interpolates the flow at a world position. Positions outside the grid
+     * are clamped to the closest edge.
+     */
+    pub fn sample(&self, position: Vector2<f32>) -> Vector2<f32> {
+        if self.is_empty() {
+            return Vector2::new(0.0, 0.0);
+        }
+        let gx = (position.x / self.cell_size - 0.5).clamp(0.0, (self.columns - 1) as f32);
+        let gy = (position.y / self.cell_size - 0.5).clamp(0.0, (self.rows - 1) as f32);
+        let x0 = gx.floor() as usize;
+        let y0 = gy.floor() as usize;
+        let x1 = (x0 + 1).min(self.columns - 1);
+        let y1 = (y0 + 1).min(self.rows - 1);
+        let tx = gx - x0 as f32;
+        let ty = gy - y0 as f32;
+
+        let top = self.vector_at(x0, y0) * (1.0 - tx) + self.vector_at(x1, y0) * tx;
+        let bottom = self.vector_at(x0, y1) * (1.0 - tx) + self.vector_at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    fn vector_at(&self, column: usize, row: usize) -> Vector2<f32> {
+        let i = (row * self.columns + column) * 2;
+        Vector2::new(self.vectors[i], self.vectors[i + 1])
+    }
+
+    fn regenerate(&mut self) {
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let center = Vector2::new(
+                    (column as f32 + 0.5) * self.cell_size,
+                    (row as f32 + 0.5) * self.cell_size,
+                );
+                let flow = self.flow_at(center);
+                let i = (row * self.columns + column) * 2;
+                self.vectors[i] = flow.x;
+                self.vectors[i + 1] = flow.y;
+            }
+        }
+    }
+
+    fn flow_at(&self, point: Vector2<f32>) -> Vector2<f32> {
+        match &self.source {
+            FlowSource::None => Vector2::new(0.0, 0.0),
+            FlowSource::Uniform { wind } => *wind,
+            FlowSource::Vortex {
+                center,
+                strength,
+                radius,
+            } => {
+                let offset = point - center;
+                let distance = offset.magnitude();
+                if distance < f32::EPSILON {
+                    return Vector2::new(0.0, 0.0);
+                }
+                let tangent = Vector2::new(-offset.y, offset.x) / distance;
+                tangent * (strength * falloff(distance, *radius))
+            }
+            FlowSource::Sink {
+                center,
+                strength,
+                radius,
+            } => {
+                let offset = center - point;
+                let distance = offset.magnitude();
+                if distance < f32::EPSILON {
+                    return Vector2::new(0.0, 0.0);
+                }
+                (offset / distance) * (strength * falloff(distance, *radius))
+            }
+            FlowSource::CurlNoise {
+                noise,
+                scale,
+                strength,
+                speed,
+            } => {
+                // The curl of a scalar potential is divergence free, so the boids swirl
+                // around instead of bunching up in sinks.
+                let z = self.time * speed;
+                let x = point.x * scale;
+                let y = point.y * scale;
+                let e = 0.01;
+                let d_dx = (noise.get(x + e, y, z) - noise.get(x - e, y, z)) / (2.0 * e);
+                let d_dy = (noise.get(x, y + e, z) - noise.get(x, y - e, z)) / (2.0 * e);
+                let curl = Vector2::new(d_dy, -d_dx);
+                if curl.magnitude() < f32::EPSILON {
+                    return curl;
+                }
+                curl.normalize() * *strength
+            }
+        }
+    }
+}
+
+/**
+ * Full strength inside `radius`, then fades out with distance.
+ */
+fn falloff(distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 || distance <= radius {
+        1.0
+    } else {
+        radius / distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_wind_samples_the_same_everywhere() {
+        let mut field = FlowField::new(400, 200, 50.0);
+        field.set_source(FlowSource::Uniform {
+            wind: Vector2::new(2.0, -1.0),
+        });
+        for point in [Vector2::new(0.0, 0.0), Vector2::new(123.0, 77.0), Vector2::new(900.0, -20.0)] {
+            assert_eq!(field.sample(point), Vector2::new(2.0, -1.0));
+        }
+    }
+
+    #[test]
+    fn sample_interpolates_between_cell_centers() {
+        let mut field = FlowField::new(100, 50, 50.0);
+        field.vectors = vec![0.0, 0.0, 4.0, 2.0];
+        field.source = FlowSource::Uniform {
+            wind: Vector2::new(0.0, 0.0),
+        };
+        // Halfway between the centers at x = 25 and x = 75.
+        assert_eq!(field.sample(Vector2::new(50.0, 25.0)), Vector2::new(2.0, 1.0));
+        assert_eq!(field.sample(Vector2::new(25.0, 25.0)), Vector2::new(0.0, 0.0));
+        assert_eq!(field.sample(Vector2::new(75.0, 25.0)), Vector2::new(4.0, 2.0));
+    }
+
+    #[test]
+    fn vortex_flows_around_its_center() {
+        let mut field = FlowField::new(400, 400, 10.0);
+        let center = Vector2::new(200.0, 200.0);
+        field.set_source(FlowSource::Vortex {
+            center,
+            strength: 3.0,
+            radius: 100.0,
+        });
+        let point = Vector2::new(255.0, 205.0);
+        let flow = field.sample(point);
+        assert!(flow.dot(point - center).abs() < 0.5);
+        assert!((flow.magnitude() - 3.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn curl_noise_is_deterministic_and_evolves() {
+        let source = || FlowSource::CurlNoise {
+            noise: Box::new(Perlin::new(7)),
+            scale: 0.01,
+            strength: 1.5,
+            speed: 0.5,
+        };
+        let mut a = FlowField::new(300, 300, 30.0);
+        let mut b = FlowField::new(300, 300, 30.0);
+        a.set_source(source());
+        b.set_source(source());
+        assert_eq!(a.vectors(), b.vectors());
+
+        let before = a.vectors().to_vec();
+        a.advance(1.0);
+        assert_ne!(a.vectors(), &before[..]);
+    }
+}
@@ -0,0 +1,228 @@
+use cgmath::{InnerSpace, Vector2};
+use std::collections::{HashMap, HashSet};
+
+use crate::boids::boid::Boid;
+
+/**
+ * A shape for the flock to fly into: a set of target points and the boid each one is
+ * assigned to. Boids past the number of targets stay free.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Formation {
+    targets: Vec<Vector2<f32>>,
+    // Target index of each assigned boid, by id.
+    assignment: HashMap<u32, usize>,
+    taken: Vec<bool>,
+}
+
+impl Formation {
+    pub fn new(targets: Vec<Vector2<f32>>) -> Formation {
+        let taken = vec![false; targets.len()];
+        Formation {
+            targets,
+            assignment: HashMap::new(),
+            taken,
+        }
+    }
+
+    pub fn targets(&self) -> &[Vector2<f32>] {
+        &self.targets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn assigned(&self) -> usize {
+        self.assignment.len()
+    }
+
+    pub fn target(&self, id: u32) -> Option<Vector2<f32>> {
+        self.assignment.get(&id).map(|&target| self.targets[target])
+    }
+
+    pub fn map_targets(&mut self, map: impl Fn(Vector2<f32>) -> Vector2<f32>) {
+        for target in self.targets.iter_mut() {
+            *target = map(*target);
+        }
+    }
+
+    /**
+     * Throws the current assignment away and assigns every target again.
+     */
+    pub fn reassign(&mut self, boids: &[Boid]) {
+        self.assignment.clear();
+        self.taken.fill(false);
+        self.sync(boids);
+    }
+
+    /**
+     * Frees the targets of boids that are gone and hands free targets to boids without
+     * one. Boids that already have a target keep it, so adding a boid doesn't reshuffle
+     * the whole flock.
+     */
+    pub fn sync(&mut self, boids: &[Boid]) {
+        if self.targets.is_empty() {
+            return;
+        }
+        if !self.assignment.is_empty() {
+            let alive: HashSet<u32> = boids.iter().map(|boid| boid.id).collect();
+            let taken = &mut self.taken;
+            self.assignment.retain(|id, &mut target| {
+                let keep = alive.contains(id);
+                if !keep {
+                    taken[target] = false;
+                }
+                keep
+            });
+        }
+        let free: Vec<usize> = (0..self.targets.len()).filter(|&target| !self.taken[target]).collect();
+        if free.is_empty() {
+            return;
+        }
+        let unassigned: Vec<usize> = (0..boids.len())
+            .filter(|&i| !self.assignment.contains_key(&boids[i].id))
+            .collect();
+        for (target, i) in greedy_assignment(&self.targets, &free, boids, unassigned) {
+            self.assignment.insert(boids[i].id, target);
+            self.taken[target] = true;
+        }
+    }
+}
+
+/**
+ * Pairs each of the `free` targets with the nearest boid still unpaired. Targets
+ * farthest from the flock's center go first: they're the ones a boid can only reach
+ * from one side, and leaving them last is what makes greedy matches cross each other.
+ * Not optimal like the Hungarian method, but O(targets * boids) instead of cubic.
+ */
+fn greedy_assignment(
+    targets: &[Vector2<f32>],
+    free: &[usize],
+    boids: &[Boid],
+    mut unassigned: Vec<usize>,
+) -> Vec<(usize, usize)> {
+    if unassigned.is_empty() {
+        return Vec::new();
+    }
+    let center = unassigned
+        .iter()
+        .fold(Vector2::new(0.0, 0.0), |sum, &i| sum + boids[i].position)
+        / unassigned.len() as f32;
+    let mut order = free.to_vec();
+    // Stable, so targets at the same distance keep their order.
+    order.sort_by(|&a, &b| {
+        let a = (targets[a] - center).magnitude2();
+        let b = (targets[b] - center).magnitude2();
+        b.total_cmp(&a)
+    });
+
+    let mut pairs = Vec::new();
+    for target in order {
+        let Some((slot, _)) = unassigned
+            .iter()
+            .enumerate()
+            .map(|(slot, &i)| (slot, (boids[i].position - targets[target]).magnitude2()))
+            .fold(None, |best: Option<(usize, f32)>, candidate| match best {
+                Some(best) if best.1 <= candidate.1 => Some(best),
+                _ => Some(candidate),
+            })
+        else {
+            break;
+        };
+        pairs.push((target, unassigned.remove(slot)));
+    }
+    pairs
+}
+
+/**
+ * `count` points spaced evenly along a closed polygon's outline, starting at its first
+ * point.
+ */
+pub fn outline_points(polygon: &[Vector2<f32>], count: usize) -> Vec<Vector2<f32>> {
+    if polygon.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let edges: Vec<(Vector2<f32>, Vector2<f32>)> = (0..polygon.len())
+        .map(|i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+        .collect();
+    let perimeter: f32 = edges.iter().map(|(a, b)| (b - a).magnitude()).sum();
+    if perimeter <= f32::EPSILON {
+        return vec![polygon[0]; count];
+    }
+    let step = perimeter / count as f32;
+    let mut points = Vec::with_capacity(count);
+    let mut edge = 0;
+    let mut edge_start = 0.0;
+    for i in 0..count {
+        let along = i as f32 * step;
+        while edge + 1 < edges.len() && along > edge_start + (edges[edge].1 - edges[edge].0).magnitude() {
+            edge_start += (edges[edge].1 - edges[edge].0).magnitude();
+            edge += 1;
+        }
+        let (a, b) = edges[edge];
+        let length = (b - a).magnitude();
+        let t = if length > 0.0 {
+            ((along - edge_start) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        points.push(a + (b - a) * t);
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boid(id: u32, x: f32, y: f32) -> Boid {
+        Boid::new(Vector2::new(x, y), Vector2::new(0.0, 0.0), id)
+    }
+
+    #[test]
+    fn outline_points_are_evenly_spaced() {
+        let square = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(10.0, 0.0),
+            Vector2::new(10.0, 10.0),
+            Vector2::new(0.0, 10.0),
+        ];
+        assert_eq!(
+            outline_points(&square, 8),
+            vec![
+                Vector2::new(0.0, 0.0),
+                Vector2::new(5.0, 0.0),
+                Vector2::new(10.0, 0.0),
+                Vector2::new(10.0, 5.0),
+                Vector2::new(10.0, 10.0),
+                Vector2::new(5.0, 10.0),
+                Vector2::new(0.0, 10.0),
+                Vector2::new(0.0, 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn boids_keep_their_targets_and_free_ones_are_handed_out() {
+        let mut formation = Formation::new(vec![Vector2::new(0.0, 0.0), Vector2::new(100.0, 0.0)]);
+        let mut boids = vec![boid(3, 90.0, 5.0), boid(7, 10.0, 5.0), boid(9, 50.0, 50.0)];
+        formation.sync(&boids);
+        assert_eq!(formation.target(3), Some(Vector2::new(100.0, 0.0)));
+        assert_eq!(formation.target(7), Some(Vector2::new(0.0, 0.0)));
+        assert_eq!(formation.target(9), None);
+
+        // Boid 3 is gone so boid 9 gets its target. Boid 7 keeps its own, even though
+        // it's now next to the other one.
+        boids.remove(0);
+        boids[0].position = Vector2::new(99.0, 0.0);
+        formation.sync(&boids);
+        assert_eq!(formation.target(7), Some(Vector2::new(0.0, 0.0)));
+        assert_eq!(formation.target(9), Some(Vector2::new(100.0, 0.0)));
+        assert_eq!(formation.assigned(), 2);
+
+        formation.reassign(&boids);
+        assert_eq!(formation.target(7), Some(Vector2::new(100.0, 0.0)));
+        assert_eq!(formation.target(9), Some(Vector2::new(0.0, 0.0)));
+    }
+}
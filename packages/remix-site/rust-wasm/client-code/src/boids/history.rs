@@ -0,0 +1,325 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::mem::{size_of, size_of_val};
+use std::sync::Arc;
+
+use crate::boids::boid::Boid;
+use crate::boids::ecosystem::Ecosystem;
+use crate::boids::flow_field::FlowField;
+use crate::boids::formation::Formation;
+use crate::boids::mask::WorldMask;
+use crate::boids::obstacle::Obstacle;
+use crate::boids::path::BoidPath;
+use crate::boids::rng::SimRng;
+use crate::boids::settings::WorldSettings;
+use crate::boids::zones::Zone;
+
+/**
+ * Everything a tick reads or changes, as it was at the start of `tick_count`. Restoring
+ * one and re-running the same `dt`s gives back exactly the same states.
+ */
+#[derive(Clone)]
+pub struct Keyframe {
+    pub tick_count: u32,
+    pub time: f32,
+    pub boids: Vec<Boid>,
+    pub world_settings: WorldSettings,
+    pub flow_field: FlowField,
+    pub rng: SimRng,
+    pub path: BoidPath,
+    pub leaders: BTreeSet<u32>,
+    pub obstacles: Vec<Obstacle>,
+    pub zones: Vec<Zone>,
+    pub mask: Option<Arc<WorldMask>>,
+    pub formation: Formation,
+    pub next_id: u32,
+    pub ecosystem: Ecosystem,
+}
+
+impl Keyframe {
+    /**
+     * Rough heap + inline size, used to keep the history under its memory cap.
+     */
+    fn size_in_bytes(&self) -> usize {
+        size_of::<Keyframe>()
+            + size_of_val(self.boids.as_slice())
+            + size_of_val(self.flow_field.vectors())
+            + size_of_val(self.path.points())
+            + self.leaders.len() * size_of::<u32>()
+            + size_of_val(self.obstacles.as_slice())
+            + size_of_val(self.zones.as_slice())
+            + size_of_val(self.formation.targets())
+            + self.formation.assigned() * size_of::<(u32, usize)>()
+            + size_of_val(self.ecosystem.patches.as_slice())
+            + self.ecosystem.population_history().len() * size_of::<u32>()
+    }
+}
+
+/**
+ * A keyframe followed by the `dt` of every tick run after it.
+ */
+struct Segment {
+    keyframe: Keyframe,
+    dts: Vec<f32>,
+}
+
+impl Segment {
+    fn end_tick(&self) -> u32 {
+        self.keyframe.tick_count + self.dts.len() as u32
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.keyframe.size_in_bytes() + size_of_val(self.dts.as_slice())
+    }
+}
+
+/**
+ * Bounded history of past ticks. Keeps a full keyframe every `keyframe_interval` ticks
+ * (and after anything other than a tick changed the world) plus the `dt` of each tick
+ * in between, so any tick in the history can be rebuilt by re-simulating from the
+ * keyframe before it. The oldest segments are dropped once `max_bytes` is exceeded.
+ */
+pub struct TickHistory {
+    segments: VecDeque<Segment>,
+    keyframe_interval: u32,
+    max_bytes: usize,
+    bytes: usize,
+    // Set when the world was edited since the last tick, the next tick must start a new
+    // keyframe since re-simulating the old one would miss the edit.
+    needs_keyframe: bool,
+}
+
+impl TickHistory {
+    pub const DEFAULT_KEYFRAME_INTERVAL: u32 = 30;
+    pub const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+    pub fn new(keyframe_interval: u32, max_bytes: usize) -> TickHistory {
+        TickHistory {
+            segments: VecDeque::new(),
+            keyframe_interval: keyframe_interval.max(1),
+            max_bytes,
+            bytes: 0,
+            needs_keyframe: true,
+        }
+    }
+
+    pub fn keyframe_interval(&self) -> u32 {
+        self.keyframe_interval
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /**
+     * A `max_bytes` of zero turns the history off.
+     */
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    pub fn set_limits(&mut self, keyframe_interval: u32, max_bytes: usize) {
+        self.keyframe_interval = keyframe_interval.max(1);
+        self.max_bytes = max_bytes;
+        if !self.is_enabled() {
+            self.clear();
+        }
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.bytes = 0;
+        self.needs_keyframe = true;
+    }
+
+    /**
+     * Oldest tick that can still be restored, if any.
+     */
+    pub fn start_tick(&self) -> Option<u32> {
+        self.segments.front().map(|segment| segment.keyframe.tick_count)
+    }
+
+    /**
+     * Newest tick that can be restored, if any. Past the current tick after stepping back.
+     */
+    pub fn end_tick(&self) -> Option<u32> {
+        self.segments.back().map(Segment::end_tick)
+    }
+
+    /**
+     * True when the tick about to run from `tick_count` has to be preceded by `push_keyframe`.
+     */
+    pub fn wants_keyframe(&self) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        match self.segments.back() {
+            Some(segment) => self.needs_keyframe || segment.dts.len() as u32 >= self.keyframe_interval,
+            None => true,
+        }
+    }
+
+    pub fn push_keyframe(&mut self, keyframe: Keyframe) {
+        if !self.is_enabled() {
+            return;
+        }
+        let segment = Segment {
+            keyframe,
+            dts: Vec::new(),
+        };
+        self.bytes += segment.size_in_bytes();
+        self.segments.push_back(segment);
+        self.needs_keyframe = false;
+        self.evict();
+    }
+
+    pub fn push_tick(&mut self, dt: f32) {
+        if let Some(segment) = self.segments.back_mut() {
+            segment.dts.push(dt);
+            self.bytes += size_of::<f32>();
+            self.evict();
+        }
+    }
+
+    /**
+     * Forgets every tick after `tick_count`, for when the simulation continues from an
+     * earlier point and the old future no longer applies.
+     */
+    pub fn truncate(&mut self, tick_count: u32) {
+        while let Some(segment) = self.segments.back_mut() {
+            if segment.keyframe.tick_count > tick_count {
+                self.bytes -= segment.size_in_bytes();
+                self.segments.pop_back();
+            } else {
+                let keep = (tick_count - segment.keyframe.tick_count) as usize;
+                if segment.dts.len() > keep {
+                    self.bytes -= (segment.dts.len() - keep) * size_of::<f32>();
+                    segment.dts.truncate(keep);
+                }
+                break;
+            }
+        }
+    }
+
+    /**
+     * The world was edited at `tick_count`: drop the future and start a keyframe next tick.
+     */
+    pub fn invalidate(&mut self, tick_count: u32) {
+        self.truncate(tick_count);
+        self.needs_keyframe = true;
+    }
+
+    /**
+     * The keyframe to restore and the `dt`s to re-run from it to reach `tick_count`, or
+     * `None` when that tick isn't in the history. Prefers the newest keyframe, so edits
+     * made right before a keyframe are kept.
+     */
+    pub fn plan(&self, tick_count: u32) -> Option<(&Keyframe, &[f32])> {
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.keyframe.tick_count <= tick_count)?;
+        if tick_count > segment.end_tick() {
+            return None;
+        }
+        let steps = (tick_count - segment.keyframe.tick_count) as usize;
+        Some((&segment.keyframe, &segment.dts[..steps]))
+    }
+
+    /**
+     * Called after restoring a keyframe: the world matches the history again.
+     */
+    pub fn mark_restored(&mut self) {
+        self.needs_keyframe = false;
+    }
+
+    fn evict(&mut self) {
+        while self.bytes > self.max_bytes && self.segments.len() > 1 {
+            if let Some(segment) = self.segments.pop_front() {
+                self.bytes -= segment.size_in_bytes();
+            }
+        }
+    }
+}
+
+impl Default for TickHistory {
+    fn default() -> Self {
+        TickHistory::new(TickHistory::DEFAULT_KEYFRAME_INTERVAL, TickHistory::DEFAULT_MAX_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(tick_count: u32) -> Keyframe {
+        let settings = WorldSettings::new(100, 100, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        Keyframe {
+            tick_count,
+            time: tick_count as f32,
+            boids: Vec::new(),
+            flow_field: FlowField::new(100, 100, FlowField::DEFAULT_CELL_SIZE),
+            world_settings: settings,
+            rng: SimRng::new(1),
+            path: BoidPath::default(),
+            leaders: BTreeSet::new(),
+            obstacles: Vec::new(),
+            zones: Vec::new(),
+            mask: None,
+            formation: Formation::default(),
+            next_id: 0,
+            ecosystem: Ecosystem::default(),
+        }
+    }
+
+    fn run(history: &mut TickHistory, from: u32, to: u32) {
+        for tick in from..to {
+            if history.wants_keyframe() {
+                history.push_keyframe(keyframe(tick));
+            }
+            history.push_tick(1.0);
+        }
+    }
+
+    #[test]
+    fn plans_from_the_closest_keyframe_and_drops_the_future_on_truncate() {
+        let mut history = TickHistory::new(10, usize::MAX);
+        run(&mut history, 0, 25);
+        assert_eq!(history.start_tick(), Some(0));
+        assert_eq!(history.end_tick(), Some(25));
+
+        let (keyframe, dts) = history.plan(17).unwrap();
+        assert_eq!(keyframe.tick_count, 10);
+        assert_eq!(dts.len(), 7);
+        let (keyframe, dts) = history.plan(20).unwrap();
+        assert_eq!((keyframe.tick_count, dts.len()), (20, 0));
+        assert!(history.plan(26).is_none());
+
+        history.truncate(15);
+        assert_eq!(history.end_tick(), Some(15));
+        history.invalidate(15);
+        assert!(history.wants_keyframe());
+        run(&mut history, 15, 16);
+        assert_eq!(history.plan(15).unwrap().0.tick_count, 15);
+    }
+
+    #[test]
+    fn stays_under_the_memory_cap() {
+        let segment_bytes = keyframe(0).size_in_bytes() + 10 * size_of::<f32>();
+        let mut history = TickHistory::new(10, segment_bytes * 3);
+        run(&mut history, 0, 100);
+        assert!(history.size_in_bytes() <= segment_bytes * 3);
+        assert_eq!(history.start_tick(), Some(70));
+        assert_eq!(history.end_tick(), Some(100));
+
+        history.set_limits(10, 0);
+        assert!(!history.is_enabled());
+        assert_eq!(history.size_in_bytes(), 0);
+        assert_eq!(history.end_tick(), None);
+    }
+}
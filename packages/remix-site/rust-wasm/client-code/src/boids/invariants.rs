@@ -0,0 +1,352 @@
+use cgmath::{InnerSpace, Vector2};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::boids::boid::Boid;
+use crate::boids::settings::WorldSettings;
+use crate::boids::traits::BoidTraits;
+use crate::boids::utils::LinearSerializable;
+use crate::boids::zones::{max_speed_limit, speed_limit, Zone};
+
+// Room for rounding when a velocity is scaled back down to the limit.
+const SPEED_TOLERANCE: f32 = 1e-3;
+
+/**
+ * Something about the world that should never happen. Boids are named by id, buffer
+ * positions by index into the buffer.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InvariantViolation {
+    SpeedOverLimit { id: u32, speed: f32, limit: f32 },
+    NotFinite { id: u32, field: &'static str },
+    DuplicateId { id: u32 },
+    TransferLength { expected: usize, actual: usize },
+    TraitsLength { expected: usize, actual: usize },
+    TransferMismatch { id: u32, index: usize },
+    TraitsMismatch { id: u32, index: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::SpeedOverLimit { id, speed, limit } => {
+                write!(f, "boid {} flies at {} over its limit of {}", id, speed, limit)
+            }
+            InvariantViolation::NotFinite { id, field } => write!(f, "boid {} has a non-finite {}", id, field),
+            InvariantViolation::DuplicateId { id } => write!(f, "more than one boid has id {}", id),
+            InvariantViolation::TransferLength { expected, actual } => {
+                write!(f, "transfer array holds {} values, expected {}", actual, expected)
+            }
+            InvariantViolation::TraitsLength { expected, actual } => {
+                write!(f, "traits array holds {} values, expected {}", actual, expected)
+            }
+            InvariantViolation::TransferMismatch { id, index } => {
+                write!(f, "transfer array at {} doesn't match boid {}", index, id)
+            }
+            InvariantViolation::TraitsMismatch { id, index } => {
+                write!(f, "traits array at {} doesn't match boid {}", index, id)
+            }
+        }
+    }
+}
+
+/**
+ * Checks the boids against the settings, zones and the buffers JS reads them from. Returns
+ * every violation found, empty when the world is consistent.
+ *
+ * Zones set the speed limit where a boid starts its tick, which `start_positions` holds
+ * by id. Boids missing from it (born or added since) are only held to the highest limit
+ * anywhere in the world.
+ */
+pub fn check_invariants(
+    boids: &[Boid],
+    transfer_array: &[f32],
+    traits_array: &[f32],
+    settings: &WorldSettings,
+    zones: &[Zone],
+    start_positions: &HashMap<u32, Vector2<f32>>,
+) -> Vec<InvariantViolation> {
+    let mut violations = Vec::new();
+    let mut ids = HashSet::new();
+    for boid in boids {
+        if !ids.insert(boid.id) {
+            violations.push(InvariantViolation::DuplicateId { id: boid.id });
+        }
+        let fields = [
+            ("position", boid.position.x.is_finite() && boid.position.y.is_finite()),
+            ("velocity", boid.velocity.x.is_finite() && boid.velocity.y.is_finite()),
+            ("energy", boid.energy.is_finite()),
+            ("traits", boid.traits.as_array().iter().all(|value| value.is_finite())),
+        ];
+        for (field, finite) in fields {
+            if !finite {
+                violations.push(InvariantViolation::NotFinite { id: boid.id, field });
+            }
+        }
+        let speed = boid.velocity.magnitude();
+        let velocity_limit = match start_positions.get(&boid.id) {
+            // Collisions can push a boid across a zone's edge after its limit was applied,
+            // so the limit where it ended up counts as well.
+            Some(&start) => speed_limit(zones, start, settings.velocity_limit)
+                .max(speed_limit(zones, boid.position, settings.velocity_limit)),
+            None => max_speed_limit(zones, settings.velocity_limit),
+        };
+        let limit = velocity_limit * boid.traits.max_speed;
+        if speed > limit * (1.0 + SPEED_TOLERANCE) {
+            violations.push(InvariantViolation::SpeedOverLimit { id: boid.id, speed, limit });
+        }
+    }
+
+    let expected = boids.len() * Boid::NUM_ELEMENTS;
+    if transfer_array.len() != expected {
+        violations.push(InvariantViolation::TransferLength {
+            expected,
+            actual: transfer_array.len(),
+        });
+    } else {
+        let mut values = [0.0; Boid::NUM_ELEMENTS];
+        for (i, boid) in boids.iter().enumerate() {
+            boid.serialize_to_array(&mut values, 0);
+            if !same_bits(&values, &transfer_array[i * Boid::NUM_ELEMENTS..(i + 1) * Boid::NUM_ELEMENTS]) {
+                violations.push(InvariantViolation::TransferMismatch {
+                    id: boid.id,
+                    index: i * Boid::NUM_ELEMENTS,
+                });
+            }
+        }
+    }
+
+    let expected = boids.len() * BoidTraits::NUM_ELEMENTS;
+    if traits_array.len() != expected {
+        violations.push(InvariantViolation::TraitsLength {
+            expected,
+            actual: traits_array.len(),
+        });
+    } else {
+        for (i, boid) in boids.iter().enumerate() {
+            let start = i * BoidTraits::NUM_ELEMENTS;
+            if !same_bits(&boid.traits.as_array(), &traits_array[start..start + BoidTraits::NUM_ELEMENTS]) {
+                violations.push(InvariantViolation::TraitsMismatch { id: boid.id, index: start });
+            }
+        }
+    }
+    violations
+}
+
+// Bitwise, so a NaN in both places still counts as in sync.
+fn same_bits(a: &[f32], b: &[f32]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::boid::serialize_boids;
+    use crate::boids::traits::serialize_traits;
+    use cgmath::Vector2;
+
+    #[test]
+    fn reports_each_broken_invariant() {
+        let settings = WorldSettings::default(100, 100);
+        let mut boids = vec![
+            Boid::new(Vector2::new(1.0, 1.0), Vector2::new(3.0, 4.0), 0),
+            Boid::new(Vector2::new(2.0, 2.0), Vector2::new(1.0, 0.0), 1),
+        ];
+        let mut transfer = vec![0.0; 6];
+        serialize_boids(&boids, &mut transfer);
+        let mut traits = vec![0.0; 16];
+        serialize_traits(&boids, &mut traits);
+        assert_eq!(check_invariants(&boids, &transfer, &traits, &settings, &[], &HashMap::new()), vec![]);
+
+        boids[0].velocity = Vector2::new(40.0, 30.0);
+        boids[1].id = 0;
+        boids[1].energy = f32::NAN;
+        traits.pop();
+        assert_eq!(
+            check_invariants(&boids, &transfer, &traits, &settings, &[], &HashMap::new()),
+            vec![
+                InvariantViolation::SpeedOverLimit {
+                    id: 0,
+                    speed: 50.0,
+                    limit: 25.0
+                },
+                InvariantViolation::DuplicateId { id: 0 },
+                InvariantViolation::NotFinite { id: 0, field: "energy" },
+                InvariantViolation::TransferMismatch { id: 0, index: 0 },
+                InvariantViolation::TraitsLength {
+                    expected: 16,
+                    actual: 15
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn zones_limit_speed_where_the_boid_started_its_tick() {
+        use crate::boids::region::RegionShape;
+        use crate::boids::zones::ZoneEffect;
+
+        let settings = WorldSettings::default(100, 100);
+        let rect = |x: f32| RegionShape::Rect {
+            min: Vector2::new(x, 0.0),
+            max: Vector2::new(x + 10.0, 10.0),
+        };
+        let zones = vec![
+            Zone::new("fast", rect(0.0), ZoneEffect::VelocityLimit, 40.0),
+            Zone::new("slow", rect(50.0), ZoneEffect::SlowDown, 0.5),
+        ];
+        // Boid 0 speeds far from the fast zone. Boid 1 flew into the slow zone at the
+        // speed allowed outside it, which is fine.
+        let boids = vec![
+            Boid::new(Vector2::new(30.0, 5.0), Vector2::new(30.0, 0.0), 0),
+            Boid::new(Vector2::new(52.0, 5.0), Vector2::new(20.0, 0.0), 1),
+        ];
+        let starts = HashMap::from([(0, Vector2::new(27.0, 5.0)), (1, Vector2::new(49.0, 5.0))]);
+        let mut transfer = vec![0.0; 6];
+        serialize_boids(&boids, &mut transfer);
+        let mut traits = vec![0.0; 16];
+        serialize_traits(&boids, &mut traits);
+        assert_eq!(
+            check_invariants(&boids, &transfer, &traits, &settings, &zones, &starts),
+            vec![InvariantViolation::SpeedOverLimit {
+                id: 0,
+                speed: 30.0,
+                limit: 25.0
+            }]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    mod properties {
+        use super::*;
+        use crate::boids::orchestrator::BoidOrchestrator;
+        use crate::boids::settings::{NeighborMode, ResizeMode};
+        use crate::boids::zones::ZoneEffect;
+        use proptest::prelude::*;
+
+        fn world_settings() -> impl Strategy<Value = WorldSettings> {
+            (
+                (1.0f32..80.0, 0.0f32..3.0, 0.0f32..3.0, 0.0f32..3.0, 0.0f32..3.0),
+                (1.0f32..100.0, 10.0f32..250.0, 0u8..3, 0.0f32..2.0),
+                (any::<bool>(), any::<bool>(), 0.0f32..0.5),
+            )
+                .prop_map(|(rules, neighbors, layers)| {
+                    let (velocity_limit, cohesion, separation, alignment, border) = rules;
+                    let (avoidance_range, perception_range, mode, wander) = neighbors;
+                    let (collisions, ecosystem, spread) = layers;
+                    let mut settings = WorldSettings::default(400, 300);
+                    settings.velocity_limit = velocity_limit;
+                    settings.pc.p_center_modifier = cohesion;
+                    settings.avoidance.avoidance_modifier = separation;
+                    settings.avoidance.avoidance_range = avoidance_range;
+                    settings.velocity_matching.velocity_matching_modifier = alignment;
+                    settings.border_constraint.border_constraint_modifier = border;
+                    settings.neighbors.perception_range = perception_range;
+                    settings.neighbors.mode = match mode {
+                        0 => NeighborMode::All,
+                        1 => NeighborMode::Metric,
+                        _ => NeighborMode::KNearest,
+                    };
+                    settings.wander.wander_modifier = wander;
+                    settings.collision.enabled = collisions;
+                    settings.ecosystem.enabled = ecosystem;
+                    settings.traits.spread = spread;
+                    settings
+                })
+        }
+
+        /**
+         * Something done to the world between ticks. The generated scenarios mix them in
+         * with the ticks, so the layers that move boids around get checked too.
+         */
+        #[derive(Debug, Clone)]
+        enum Step {
+            Tick,
+            Resize { width: u32, height: u32, mode: ResizeMode },
+            Mask { points: Vec<f32> },
+            ClearMask,
+            Formation { points: Vec<f32>, outline: bool, blend: f32 },
+            ClearFormation,
+            Zone { rect: [f32; 4], effect: ZoneEffect, strength: f32 },
+            ClearZones,
+        }
+
+        fn polygon() -> impl Strategy<Value = Vec<f32>> {
+            prop::collection::vec(-50.0f32..850.0, 6..16)
+        }
+
+        fn step() -> impl Strategy<Value = Step> {
+            let mode = prop_oneof![
+                Just(ResizeMode::Scale),
+                Just(ResizeMode::Clamp),
+                Just(ResizeMode::Wrap),
+                Just(ResizeMode::Keep),
+            ];
+            let effect = prop_oneof![
+                Just(ZoneEffect::Counter),
+                Just(ZoneEffect::SlowDown),
+                Just(ZoneEffect::NoGo),
+                Just(ZoneEffect::VelocityLimit),
+            ];
+            prop_oneof![
+                12 => Just(Step::Tick),
+                1 => (0u32..800, 0u32..600, mode)
+                    .prop_map(|(width, height, mode)| Step::Resize { width, height, mode }),
+                1 => polygon().prop_map(|points| Step::Mask { points }),
+                1 => Just(Step::ClearMask),
+                1 => (polygon(), any::<bool>(), 0.0f32..1.0)
+                    .prop_map(|(points, outline, blend)| Step::Formation { points, outline, blend }),
+                1 => Just(Step::ClearFormation),
+                1 => (prop::array::uniform4(-100.0f32..500.0), effect, 0.0f32..60.0)
+                    .prop_map(|(rect, effect, strength)| Step::Zone { rect, effect, strength }),
+                1 => Just(Step::ClearZones),
+            ]
+        }
+
+        fn run(orchestrator: &mut BoidOrchestrator, step: Step, dt: f32, zone: usize) {
+            match step {
+                Step::Tick => orchestrator.tick(dt),
+                Step::Resize { width, height, mode } => orchestrator.resize_world(width, height, mode),
+                Step::Mask { points } => orchestrator.set_world_mask_polygon(&points),
+                Step::ClearMask => orchestrator.clear_world_mask(),
+                Step::Formation { points, outline, blend } => {
+                    orchestrator.set_formation_polygon(&points, outline);
+                    orchestrator.set_formation_blend(blend);
+                    orchestrator.set_formation_modifier(1.0);
+                }
+                Step::ClearFormation => orchestrator.clear_formation(),
+                Step::Zone { rect: [x, y, width, height], effect, strength } => {
+                    orchestrator.add_zone_rect(&format!("zone {}", zone), x, y, width, height, effect, strength)
+                }
+                Step::ClearZones => orchestrator.clear_zones(),
+            }
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(48))]
+
+            #[test]
+            fn ticking_keeps_every_invariant(
+                settings in world_settings(),
+                seed in any::<u32>(),
+                num_boids in 0u32..60,
+                dt in 0.01f32..0.5,
+                steps in prop::collection::vec(step(), 1..40),
+            ) {
+                let mut orchestrator = BoidOrchestrator::with_seed(settings, num_boids, seed);
+                prop_assert_eq!(orchestrator.check_invariants(), Ok(()));
+                for (i, step) in steps.into_iter().enumerate() {
+                    // Speed limits hold for the zones a tick ran with, so removing a zone
+                    // that let boids speed up leaves them too fast until the next tick.
+                    let tick = matches!(step, Step::Tick);
+                    run(&mut orchestrator, step, dt, i);
+                    if tick {
+                        prop_assert_eq!(orchestrator.check_invariants(), Ok(()));
+                    }
+                }
+            }
+        }
+    }
+}
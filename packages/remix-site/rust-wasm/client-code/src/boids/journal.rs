@@ -0,0 +1,406 @@
+use serde::{Deserialize, Serialize};
+use std::mem;
+
+use crate::boids::boid::Boid;
+use crate::boids::settings::{NeighborMode, ResizeMode, WorldSettings};
+use crate::boids::traits::BoidTraits;
+use crate::boids::zones::ZoneEffect;
+
+/**
+ * A boid as it was when the journal started.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalBoid {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub radius: f32,
+    pub energy: f32,
+    pub traits: BoidTraits,
+}
+
+impl From<&Boid> for JournalBoid {
+    fn from(boid: &Boid) -> Self {
+        JournalBoid {
+            id: boid.id,
+            x: boid.position.x,
+            y: boid.position.y,
+            vx: boid.velocity.x,
+            vy: boid.velocity.y,
+            radius: boid.radius,
+            energy: boid.energy,
+            traits: boid.traits,
+        }
+    }
+}
+
+impl From<&JournalBoid> for Boid {
+    fn from(boid: &JournalBoid) -> Self {
+        let mut restored = Boid::new(
+            cgmath::Vector2::new(boid.x, boid.y),
+            cgmath::Vector2::new(boid.vx, boid.vy),
+            boid.id,
+        );
+        restored.radius = boid.radius;
+        restored.energy = boid.energy;
+        restored.traits = boid.traits;
+        restored
+    }
+}
+
+/**
+ * One call into the simulation that changed its state.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum JournalEvent {
+    // Runs of ticks with the same dt are stored once with a count.
+    Tick { dt: f32, count: u32 },
+    AddBoid { count: u32 },
+    RemoveLastBoid,
+    SetWorldWidth { width: u32 },
+    SetWorldHeight { height: u32 },
+    SetAvoidanceRange { range: f32 },
+    SetAvoidanceModifier { modifier: f32 },
+    SetPCenterModifier { modifier: f32 },
+    SetVelocityMatchingModifier { modifier: f32 },
+    SetBorderConstraintModifier { modifier: f32 },
+    SetFlowFieldModifier { modifier: f32 },
+    SetWanderModifier { modifier: f32 },
+    SetWanderFrequency { frequency: f32 },
+    SetPathFollowingModifier { modifier: f32 },
+    SetLeaderFollowingModifier { modifier: f32 },
+    SetFollowDistance { distance: f32 },
+    SetArrivalRadius { radius: f32 },
+    SetNeighborMode { mode: NeighborMode },
+    SetPerceptionRange { range: f32 },
+    SetKNearest { k: u32 },
+    SetCollisionsEnabled { enabled: bool },
+    SetBoidRadius { radius: f32 },
+    SetCollisionIterations { iterations: u32 },
+    SetWallCollisions { walls: bool },
+    SetBoidCollisionRadius { boid_id: u32, radius: f32 },
+    AddObstacle { x: f32, y: f32, radius: f32 },
+    RemoveObstacle { index: u32 },
+    ClearObstacles,
+    AddZoneRect {
+        name: String,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        effect: ZoneEffect,
+        strength: f32,
+    },
+    AddZoneCircle { name: String, x: f32, y: f32, radius: f32, effect: ZoneEffect, strength: f32 },
+    RemoveZone { name: String },
+    ClearZones,
+    SetWorldMaskPolygon { points: Vec<f32> },
+    SetWorldMaskBitmap {
+        width: u32,
+        height: u32,
+        #[serde(with = "base64_bytes")]
+        pixels: Vec<u8>,
+    },
+    ClearWorldMask,
+    SetFormationPoints { points: Vec<f32> },
+    SetFormationPolygon { points: Vec<f32>, outline: bool },
+    SetFormationBitmap {
+        width: u32,
+        height: u32,
+        #[serde(with = "base64_bytes")]
+        pixels: Vec<u8>,
+    },
+    ReassignFormation,
+    ClearFormation,
+    SetPath { points: Vec<f32>, looping: bool },
+    ClearPath,
+    PromoteLeader { boid_id: u32 },
+    DemoteLeader { boid_id: u32 },
+    SetFlowFieldUniform { wind_x: f32, wind_y: f32 },
+    SetFlowFieldVortex { center_x: f32, center_y: f32, strength: f32, radius: f32 },
+    SetFlowFieldSink { center_x: f32, center_y: f32, strength: f32, radius: f32 },
+    SetFlowFieldCurlNoise { scale: f32, strength: f32, speed: f32, seed: u32 },
+    ClearFlowField,
+    SetFlowFieldCellSize { cell_size: f32 },
+    Seek { tick: u32 },
+    SetHistoryLimits { keyframe_interval: u32, max_bytes: u32 },
+    SetEcosystemEnabled { enabled: bool },
+    SetEnergyDrain { base_drain: f32, speed_drain: f32 },
+    SetEatRate { eat_rate: f32 },
+    SetReproductionEnergy { energy: f32 },
+    SetMutation { mutation: f32 },
+    SetMaxPopulation { max_population: u32 },
+    AddFoodPatch { x: f32, y: f32, radius: f32, capacity: f32, regrowth: f32 },
+    ClearFoodPatches,
+    SetTraitSpread { spread: f32 },
+    SetFormationBlend { blend: f32 },
+    SetFormationModifier { modifier: f32 },
+    SetFormationArrivalRadius { radius: f32 },
+    ResizeWorld { width: u32, height: u32, mode: ResizeMode },
+}
+
+/**
+ * Bitmaps are stored as one base64 string rather than a JSON array of numbers, which
+ * takes up to four characters per byte.
+ */
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
+}
+
+impl JournalEvent {
+    /**
+     * Whether the event only sets a value in the world settings, so a later event of the
+     * same kind fully replaces it.
+     */
+    fn is_setting(&self) -> bool {
+        matches!(
+            self,
+            JournalEvent::SetAvoidanceRange { .. }
+                | JournalEvent::SetAvoidanceModifier { .. }
+                | JournalEvent::SetPCenterModifier { .. }
+                | JournalEvent::SetVelocityMatchingModifier { .. }
+                | JournalEvent::SetBorderConstraintModifier { .. }
+                | JournalEvent::SetFlowFieldModifier { .. }
+                | JournalEvent::SetWanderModifier { .. }
+                | JournalEvent::SetWanderFrequency { .. }
+                | JournalEvent::SetPathFollowingModifier { .. }
+                | JournalEvent::SetLeaderFollowingModifier { .. }
+                | JournalEvent::SetFollowDistance { .. }
+                | JournalEvent::SetArrivalRadius { .. }
+                | JournalEvent::SetNeighborMode { .. }
+                | JournalEvent::SetPerceptionRange { .. }
+                | JournalEvent::SetKNearest { .. }
+                | JournalEvent::SetCollisionsEnabled { .. }
+                | JournalEvent::SetBoidRadius { .. }
+                | JournalEvent::SetCollisionIterations { .. }
+                | JournalEvent::SetWallCollisions { .. }
+                | JournalEvent::SetEcosystemEnabled { .. }
+                | JournalEvent::SetEnergyDrain { .. }
+                | JournalEvent::SetEatRate { .. }
+                | JournalEvent::SetReproductionEnergy { .. }
+                | JournalEvent::SetMutation { .. }
+                | JournalEvent::SetMaxPopulation { .. }
+                | JournalEvent::SetTraitSpread { .. }
+                | JournalEvent::SetFormationBlend { .. }
+                | JournalEvent::SetFormationModifier { .. }
+                | JournalEvent::SetFormationArrivalRadius { .. }
+        )
+    }
+
+    /**
+     * Whether every number in the event is finite. NaN and infinity serialize to null,
+     * which doesn't parse back, so calls carrying them are turned away unjournaled.
+     */
+    pub fn is_finite(&self) -> bool {
+        fn all_finite(values: &[f32]) -> bool {
+            values.iter().all(|value| value.is_finite())
+        }
+        match self {
+            JournalEvent::Tick { dt, .. } => dt.is_finite(),
+            JournalEvent::SetAvoidanceRange { range } | JournalEvent::SetPerceptionRange { range } => {
+                range.is_finite()
+            }
+            JournalEvent::SetAvoidanceModifier { modifier }
+            | JournalEvent::SetPCenterModifier { modifier }
+            | JournalEvent::SetVelocityMatchingModifier { modifier }
+            | JournalEvent::SetBorderConstraintModifier { modifier }
+            | JournalEvent::SetFlowFieldModifier { modifier }
+            | JournalEvent::SetWanderModifier { modifier }
+            | JournalEvent::SetPathFollowingModifier { modifier }
+            | JournalEvent::SetLeaderFollowingModifier { modifier }
+            | JournalEvent::SetFormationModifier { modifier } => modifier.is_finite(),
+            JournalEvent::SetWanderFrequency { frequency } => frequency.is_finite(),
+            JournalEvent::SetFollowDistance { distance } => distance.is_finite(),
+            JournalEvent::SetArrivalRadius { radius }
+            | JournalEvent::SetBoidRadius { radius }
+            | JournalEvent::SetBoidCollisionRadius { radius, .. }
+            | JournalEvent::SetFormationArrivalRadius { radius } => radius.is_finite(),
+            JournalEvent::AddObstacle { x, y, radius } => all_finite(&[*x, *y, *radius]),
+            JournalEvent::AddZoneRect {
+                x,
+                y,
+                width,
+                height,
+                strength,
+                ..
+            } => all_finite(&[*x, *y, *width, *height, *strength]),
+            JournalEvent::AddZoneCircle {
+                x, y, radius, strength, ..
+            } => all_finite(&[*x, *y, *radius, *strength]),
+            JournalEvent::SetWorldMaskPolygon { points }
+            | JournalEvent::SetFormationPoints { points }
+            | JournalEvent::SetFormationPolygon { points, .. }
+            | JournalEvent::SetPath { points, .. } => all_finite(points),
+            JournalEvent::SetFlowFieldUniform { wind_x, wind_y } => all_finite(&[*wind_x, *wind_y]),
+            JournalEvent::SetFlowFieldVortex {
+                center_x,
+                center_y,
+                strength,
+                radius,
+            }
+            | JournalEvent::SetFlowFieldSink {
+                center_x,
+                center_y,
+                strength,
+                radius,
+            } => all_finite(&[*center_x, *center_y, *strength, *radius]),
+            JournalEvent::SetFlowFieldCurlNoise { scale, strength, speed, .. } => {
+                all_finite(&[*scale, *strength, *speed])
+            }
+            JournalEvent::SetFlowFieldCellSize { cell_size } => cell_size.is_finite(),
+            JournalEvent::SetEnergyDrain { base_drain, speed_drain } => all_finite(&[*base_drain, *speed_drain]),
+            JournalEvent::SetEatRate { eat_rate } => eat_rate.is_finite(),
+            JournalEvent::SetReproductionEnergy { energy } => energy.is_finite(),
+            JournalEvent::SetMutation { mutation } => mutation.is_finite(),
+            JournalEvent::AddFoodPatch {
+                x,
+                y,
+                radius,
+                capacity,
+                regrowth,
+            } => all_finite(&[*x, *y, *radius, *capacity, *regrowth]),
+            JournalEvent::SetTraitSpread { spread } => spread.is_finite(),
+            JournalEvent::SetFormationBlend { blend } => blend.is_finite(),
+            JournalEvent::AddBoid { .. }
+            | JournalEvent::RemoveLastBoid
+            | JournalEvent::SetWorldWidth { .. }
+            | JournalEvent::SetWorldHeight { .. }
+            | JournalEvent::SetNeighborMode { .. }
+            | JournalEvent::SetKNearest { .. }
+            | JournalEvent::SetCollisionsEnabled { .. }
+            | JournalEvent::SetCollisionIterations { .. }
+            | JournalEvent::SetWallCollisions { .. }
+            | JournalEvent::RemoveObstacle { .. }
+            | JournalEvent::ClearObstacles
+            | JournalEvent::RemoveZone { .. }
+            | JournalEvent::ClearZones
+            | JournalEvent::SetWorldMaskBitmap { .. }
+            | JournalEvent::ClearWorldMask
+            | JournalEvent::SetFormationBitmap { .. }
+            | JournalEvent::ReassignFormation
+            | JournalEvent::ClearFormation
+            | JournalEvent::ClearPath
+            | JournalEvent::PromoteLeader { .. }
+            | JournalEvent::DemoteLeader { .. }
+            | JournalEvent::ClearFlowField
+            | JournalEvent::Seek { .. }
+            | JournalEvent::SetHistoryLimits { .. }
+            | JournalEvent::SetEcosystemEnabled { .. }
+            | JournalEvent::SetMaxPopulation { .. }
+            | JournalEvent::ClearFoodPatches
+            | JournalEvent::ResizeWorld { .. } => true,
+        }
+    }
+}
+
+/**
+ * Everything needed to rebuild a simulation exactly: its seed, the settings and boids it
+ * started with, and every state changing call made on it since. Holds at most
+ * `MAX_EVENTS` events, calls made after that aren't kept and the journal is marked
+ * `truncated`.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub seed: u32,
+    pub settings: WorldSettings,
+    pub initial_boids: Vec<JournalBoid>,
+    pub events: Vec<JournalEvent>,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl Journal {
+    pub const MAX_EVENTS: usize = 100_000;
+
+    pub fn new(seed: u32, settings: &WorldSettings, initial_boids: &[Boid]) -> Journal {
+        Journal {
+            seed,
+            settings: settings.clone(),
+            initial_boids: initial_boids.iter().map(JournalBoid::from).collect(),
+            events: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    pub fn push(&mut self, event: JournalEvent) {
+        if self.truncated {
+            return;
+        }
+        let full = self.events.len() >= Journal::MAX_EVENTS;
+        match (self.events.last_mut(), &event) {
+            (Some(JournalEvent::Tick { dt, count }), JournalEvent::Tick { dt: next_dt, count: next_count })
+                if dt.to_bits() == next_dt.to_bits() =>
+            {
+                *count += next_count;
+            }
+            (Some(JournalEvent::AddBoid { count }), JournalEvent::AddBoid { count: next_count }) => {
+                *count += next_count;
+            }
+            // Dragging a slider sets the same setting over and over, only the last one counts.
+            (Some(last), _) if event.is_setting() && mem::discriminant(last) == mem::discriminant(&event) => {
+                *last = event;
+            }
+            _ if full => self.truncated = true,
+            _ => self.events.push(event),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Every field serializes cleanly, so this can't fail.
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Journal, String> {
+        serde_json::from_str(json).map_err(|error| format!("invalid journal: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::settings::test_settings;
+
+    #[test]
+    fn repeated_settings_merge_and_the_journal_stops_at_its_limit() {
+        let settings = test_settings(400, 200);
+        let mut journal = Journal::new(1, &settings, &[]);
+        journal.push(JournalEvent::SetWanderModifier { modifier: 1.0 });
+        journal.push(JournalEvent::SetWanderModifier { modifier: 2.0 });
+        journal.push(JournalEvent::Tick { dt: 0.125, count: 1 });
+        journal.push(JournalEvent::SetWanderModifier { modifier: 3.0 });
+        assert_eq!(
+            journal.events,
+            vec![
+                JournalEvent::SetWanderModifier { modifier: 2.0 },
+                JournalEvent::Tick { dt: 0.125, count: 1 },
+                JournalEvent::SetWanderModifier { modifier: 3.0 },
+            ]
+        );
+
+        for i in journal.events.len()..Journal::MAX_EVENTS {
+            journal.push(match i % 2 {
+                0 => JournalEvent::RemoveLastBoid,
+                _ => JournalEvent::ClearZones,
+            });
+        }
+        assert!(!journal.truncated);
+        journal.push(JournalEvent::ClearObstacles);
+        assert!(journal.truncated);
+        assert_eq!(journal.events.len(), Journal::MAX_EVENTS);
+        assert!(Journal::from_json(&journal.to_json()).unwrap().truncated);
+    }
+}
@@ -0,0 +1,330 @@
+use cgmath::{InnerSpace, Vector2};
+
+use crate::boids::rng::SimRng;
+
+/**
+ * What a world mask is built from. Kept around (instead of only the baked grid) so the
+ * mask can be rebuilt when the world is resized.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaskSource {
+    // A simple polygon in world coordinates. Self-intersecting ones use the even-odd rule.
+    Polygon(Vec<Vector2<f32>>),
+    // Row major pixels stretched over the whole world, anything above 127 is inside.
+    Bitmap {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+}
+
+/**
+ * Limits the world to an arbitrary shape. Baked into a grid of signed distances to the
+ * shape's edge (positive inside), which boids sample with bilinear interpolation.
+ */
+#[derive(Clone, Debug)]
+pub struct WorldMask {
+    source: MaskSource,
+    world: Vector2<f32>,
+    columns: usize,
+    rows: usize,
+    cell_size: f32,
+    distances: Vec<f32>,
+    // Cells whose centers are inside, for spawning.
+    inside_cells: Vec<usize>,
+}
+
+impl WorldMask {
+    pub const CELL_SIZE: f32 = 8.0;
+    // Boids closer than this to the edge start turning away from it.
+    pub const EDGE_MARGIN: f32 = 40.0;
+
+    pub fn new(source: MaskSource, world_width: u32, world_height: u32) -> WorldMask {
+        let cell_size = WorldMask::CELL_SIZE;
+        let columns = ((world_width as f32 / cell_size).ceil() as usize).max(1);
+        let rows = ((world_height as f32 / cell_size).ceil() as usize).max(1);
+        let world = Vector2::new(world_width as f32, world_height as f32);
+        let center = |i: usize| {
+            Vector2::new(
+                ((i % columns) as f32 + 0.5) * cell_size,
+                ((i / columns) as f32 + 0.5) * cell_size,
+            )
+        };
+
+        let distances: Vec<f32> = match &source {
+            MaskSource::Polygon(points) => (0..columns * rows)
+                .map(|i| polygon_signed_distance(points, center(i)))
+                .collect(),
+            MaskSource::Bitmap { width, height, pixels } => {
+                let inside: Vec<bool> = (0..columns * rows)
+                    .map(|i| {
+                        let p = center(i);
+                        let x = ((p.x / world.x.max(1.0)) * *width as f32) as usize;
+                        let y = ((p.y / world.y.max(1.0)) * *height as f32) as usize;
+                        let x = x.min(*width as usize - 1);
+                        let y = y.min(*height as usize - 1);
+                        pixels.get(y * *width as usize + x).is_some_and(|&pixel| pixel > 127)
+                    })
+                    .collect();
+                let to_outside = chamfer_distances(&inside, columns, rows, false);
+                let to_inside = chamfer_distances(&inside, columns, rows, true);
+                // The edge runs between cells, half a cell from either center.
+                (0..columns * rows)
+                    .map(|i| {
+                        if inside[i] {
+                            (to_outside[i] - 0.5) * cell_size
+                        } else {
+                            (0.5 - to_inside[i]) * cell_size
+                        }
+                    })
+                    .collect()
+            }
+        };
+        let inside_cells = (0..columns * rows).filter(|&i| distances[i] > 0.0).collect();
+        WorldMask {
+            source,
+            world,
+            columns,
+            rows,
+            cell_size,
+            distances,
+            inside_cells,
+        }
+    }
+
+    pub fn source(&self) -> &MaskSource {
+        &self.source
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inside_cells.is_empty()
+    }
+
+    /**
+     * Signed distance from `position` to the edge, positive inside the mask.
+     */
+    pub fn distance(&self, position: Vector2<f32>) -> f32 {
+        let gx = (position.x / self.cell_size - 0.5).clamp(0.0, (self.columns - 1) as f32);
+        let gy = (position.y / self.cell_size - 0.5).clamp(0.0, (self.rows - 1) as f32);
+        let x0 = gx.floor() as usize;
+        let y0 = gy.floor() as usize;
+        let x1 = (x0 + 1).min(self.columns - 1);
+        let y1 = (y0 + 1).min(self.rows - 1);
+        let tx = gx - x0 as f32;
+        let ty = gy - y0 as f32;
+        let at = |x: usize, y: usize| self.distances[y * self.columns + x];
+        let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+        let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    pub fn contains(&self, position: Vector2<f32>) -> bool {
+        position.x >= 0.0
+            && position.y >= 0.0
+            && position.x <= self.world.x
+            && position.y <= self.world.y
+            && self.distance(position) > 0.0
+    }
+
+    /**
+     * Unit vector pointing into the mask, along the distance gradient. Zero on flat
+     * stretches of the grid.
+     */
+    pub fn inward(&self, position: Vector2<f32>) -> Vector2<f32> {
+        let h = self.cell_size * 0.5;
+        let gradient = Vector2::new(
+            self.distance(position + Vector2::new(h, 0.0)) - self.distance(position - Vector2::new(h, 0.0)),
+            self.distance(position + Vector2::new(0.0, h)) - self.distance(position - Vector2::new(0.0, h)),
+        );
+        if gradient.magnitude2() > f32::EPSILON {
+            gradient.normalize()
+        } else {
+            Vector2::new(0.0, 0.0)
+        }
+    }
+
+    /**
+     * A uniformly random point inside the mask, or None when nothing is inside.
+     */
+    pub fn random_point(&self, rng: &mut SimRng) -> Option<Vector2<f32>> {
+        const ATTEMPTS: usize = 16;
+        if self.inside_cells.is_empty() {
+            return None;
+        }
+        let mut corner = Vector2::new(0.0, 0.0);
+        // Cells on the edge are only partly inside, so a point can miss. Try again.
+        for _ in 0..ATTEMPTS {
+            let cell = self.inside_cells[((rng.next_f32() * self.inside_cells.len() as f32) as usize)
+                .min(self.inside_cells.len() - 1)];
+            corner = Vector2::new(
+                (cell % self.columns) as f32 * self.cell_size,
+                (cell / self.columns) as f32 * self.cell_size,
+            );
+            let point = corner + Vector2::new(rng.next_f32(), rng.next_f32()) * self.cell_size;
+            if self.contains(point) {
+                return Some(point);
+            }
+        }
+        // Near the center of the last cell, which is inside. Jittered, so boids that
+        // all end up here don't land on the same spot.
+        let jitter = Vector2::new(rng.range(-0.05, 0.05), rng.range(-0.05, 0.05));
+        Some(corner + (Vector2::new(0.5, 0.5) + jitter) * self.cell_size)
+    }
+
+    /**
+     * About `count` points spread evenly over the inside of the mask, on a square
+     * lattice. Fewer when the mask is too small to fit them, none when it's empty.
+     */
+    pub fn inside_points(&self, count: usize) -> Vec<Vector2<f32>> {
+        if count == 0 || self.inside_cells.is_empty() {
+            return Vec::new();
+        }
+        let area = self.inside_cells.len() as f32 * self.cell_size * self.cell_size;
+        let mut spacing = (area / count as f32).sqrt();
+        let mut points = Vec::new();
+        // The edge cuts lattice points off, so tighten it until enough are inside.
+        for _ in 0..16 {
+            points.clear();
+            let mut y = spacing * 0.5;
+            while y < self.world.y {
+                let mut x = spacing * 0.5;
+                while x < self.world.x {
+                    let point = Vector2::new(x, y);
+                    if self.contains(point) {
+                        points.push(point);
+                    }
+                    x += spacing;
+                }
+                y += spacing;
+            }
+            if points.len() >= count || spacing < 1.0 {
+                break;
+            }
+            spacing *= 0.9;
+        }
+        if points.len() > count {
+            let total = points.len();
+            points = (0..count).map(|i| points[i * total / count]).collect();
+        }
+        points
+    }
+}
+
+/**
+ * Distance from `point` to the polygon's edge, positive inside.
+ */
+fn polygon_signed_distance(points: &[Vector2<f32>], point: Vector2<f32>) -> f32 {
+    if points.len() < 3 {
+        return -f32::MAX;
+    }
+    let mut inside = false;
+    let mut nearest = f32::MAX;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+        let edge = b - a;
+        let t = if edge.magnitude2() > 0.0 {
+            ((point - a).dot(edge) / edge.magnitude2()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        nearest = nearest.min((point - (a + edge * t)).magnitude());
+    }
+    if inside {
+        nearest
+    } else {
+        -nearest
+    }
+}
+
+/**
+ * Two pass chamfer distance, in cells, from every cell to the nearest cell whose
+ * `inside` equals `target`. Those cells themselves get 0.
+ */
+fn chamfer_distances(inside: &[bool], columns: usize, rows: usize, target: bool) -> Vec<f32> {
+    let far = (columns + rows) as f32 * 2.0;
+    let mut distances: Vec<f32> = inside.iter().map(|&cell| if cell == target { 0.0 } else { far }).collect();
+    let diagonal = std::f32::consts::SQRT_2;
+    let forward = [(-1, 0, 1.0), (0, -1, 1.0), (-1, -1, diagonal), (1, -1, diagonal)];
+    let backward = [(1, 0, 1.0), (0, 1, 1.0), (1, 1, diagonal), (-1, 1, diagonal)];
+    let mut relax = |x: usize, y: usize, neighbors: &[(isize, isize, f32)]| {
+        for &(dx, dy, cost) in neighbors {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx >= 0 && ny >= 0 && (nx as usize) < columns && (ny as usize) < rows {
+                let candidate = distances[ny as usize * columns + nx as usize] + cost;
+                let current = &mut distances[y * columns + x];
+                *current = current.min(candidate);
+            }
+        }
+    };
+    for y in 0..rows {
+        for x in 0..columns {
+            relax(x, y, &forward);
+        }
+    }
+    for y in (0..rows).rev() {
+        for x in (0..columns).rev() {
+            relax(x, y, &backward);
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_and_bitmap_masks_agree_on_a_square() {
+        let square = vec![
+            Vector2::new(40.0, 40.0),
+            Vector2::new(120.0, 40.0),
+            Vector2::new(120.0, 120.0),
+            Vector2::new(40.0, 120.0),
+        ];
+        let polygon = WorldMask::new(MaskSource::Polygon(square), 160, 160);
+        // 4x4 pixels over a 160 world, the middle 2x2 covers 40..120.
+        let mut pixels = vec![0; 16];
+        for i in [5, 6, 9, 10] {
+            pixels[i] = 255;
+        }
+        let bitmap = WorldMask::new(
+            MaskSource::Bitmap {
+                width: 4,
+                height: 4,
+                pixels,
+            },
+            160,
+            160,
+        );
+        for mask in [&polygon, &bitmap] {
+            assert!(mask.contains(Vector2::new(80.0, 80.0)));
+            assert!(!mask.contains(Vector2::new(20.0, 80.0)));
+            assert!((mask.distance(Vector2::new(80.0, 80.0)) - 40.0).abs() < 6.0);
+            assert!(mask.inward(Vector2::new(30.0, 80.0)).x > 0.9);
+            assert!(mask.inward(Vector2::new(80.0, 130.0)).y < -0.9);
+        }
+        let mut rng = SimRng::new(4);
+        for _ in 0..100 {
+            assert!(bitmap.contains(bitmap.random_point(&mut rng).unwrap()));
+        }
+        // A thin diagonal stroke, like text: most of its cells are only partly inside.
+        let mut pixels = vec![0; 40 * 20];
+        for x in 0..40 {
+            pixels[(x / 2) * 40 + x] = 255;
+        }
+        let line = WorldMask::new(MaskSource::Bitmap { width: 40, height: 20, pixels }, 400, 200);
+        let mut spawned: Vec<Vector2<f32>> = (0..200).map(|_| line.random_point(&mut rng).unwrap()).collect();
+        assert!(spawned.iter().all(|&point| line.contains(point)));
+        spawned.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        spawned.dedup();
+        assert_eq!(spawned.len(), 200);
+
+        let points = polygon.inside_points(10);
+        assert_eq!(points.len(), 10);
+        assert!(points.iter().all(|&point| polygon.contains(point)));
+    }
+}
@@ -0,0 +1,129 @@
+use cgmath::{InnerSpace, Vector2};
+use serde::Serialize;
+
+use crate::boids::boid::Boid;
+use crate::boids::spatial::SpatialGrid;
+
+/**
+ * Numbers describing the shape of a flock at one moment, for benchmarks and tuning.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct FlockMetrics {
+    pub boid_count: usize,
+    // Length of the average heading, 1 when every boid flies the same way, ~0 when
+    // they're going every which way.
+    pub polarization: f32,
+    pub mean_speed: f32,
+    pub mean_nearest_distance: f32,
+    // Groups of boids chained together by gaps of at most the link distance.
+    pub flock_count: usize,
+    pub largest_flock: usize,
+}
+
+impl FlockMetrics {
+    /**
+     * Measures `boids`. Two boids are in the same flock when there's a chain of boids
+     * between them with no gap longer than `link_distance`.
+     */
+    pub fn measure(boids: &[Boid], link_distance: f32) -> FlockMetrics {
+        if boids.is_empty() {
+            return FlockMetrics::default();
+        }
+        let n = boids.len() as f32;
+        let heading_sum = boids
+            .iter()
+            .filter(|boid| boid.velocity.magnitude2() > 0.0)
+            .fold(Vector2::new(0.0, 0.0), |acc, boid| acc + boid.velocity.normalize());
+        let speed_sum: f32 = boids.iter().map(|boid| boid.velocity.magnitude()).sum();
+
+        let mut grid = SpatialGrid::new();
+        grid.build(boids, link_distance.max(1.0));
+        let mut nearest_sum = 0.0;
+        for (i, boid) in boids.iter().enumerate() {
+            if let Some(&j) = grid.k_nearest(boids, boid.position, 1, Some(i)).first() {
+                nearest_sum += (boids[j].position - boid.position).magnitude();
+            }
+        }
+
+        let mut sizes = vec![0usize; boids.len()];
+        for label in flock_labels(boids, &grid, link_distance) {
+            sizes[label] += 1;
+        }
+
+        FlockMetrics {
+            boid_count: boids.len(),
+            polarization: heading_sum.magnitude() / n,
+            mean_speed: speed_sum / n,
+            mean_nearest_distance: if boids.len() > 1 { nearest_sum / n } else { 0.0 },
+            flock_count: sizes.iter().filter(|&&size| size > 0).count(),
+            largest_flock: sizes.into_iter().max().unwrap_or(0),
+        }
+    }
+}
+
+/**
+ * Which flock each boid is in, as the index of one boid of that flock. `grid` has to be
+ * built over `boids`.
+ */
+pub fn flock_labels(boids: &[Boid], grid: &SpatialGrid, link_distance: f32) -> Vec<usize> {
+    let mut flocks = DisjointSet::new(boids.len());
+    for (i, boid) in boids.iter().enumerate() {
+        grid.for_each_in_radius(boids, boid.position, link_distance, |j| {
+            if j > i {
+                flocks.union(i, j);
+            }
+        });
+    }
+    (0..boids.len()).map(|i| flocks.find(i)).collect()
+}
+
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> DisjointSet {
+        DisjointSet {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[a.max(b)] = a.min(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_polarization_and_separate_flocks() {
+        let boid = |x: f32, y: f32, vx: f32, id| Boid::new(Vector2::new(x, y), Vector2::new(vx, 0.0), id);
+        let boids = vec![
+            boid(0.0, 0.0, 2.0, 0),
+            boid(10.0, 0.0, 2.0, 1),
+            boid(20.0, 0.0, 2.0, 2),
+            boid(500.0, 0.0, -2.0, 3),
+            boid(503.0, 4.0, -2.0, 4),
+        ];
+        let metrics = FlockMetrics::measure(&boids, 15.0);
+        assert_eq!(metrics.boid_count, 5);
+        assert_eq!(metrics.flock_count, 2);
+        assert_eq!(metrics.largest_flock, 3);
+        assert!((metrics.polarization - 0.2).abs() < 1e-6);
+        assert_eq!(metrics.mean_speed, 2.0);
+        assert_eq!(metrics.mean_nearest_distance, 8.0);
+    }
+}
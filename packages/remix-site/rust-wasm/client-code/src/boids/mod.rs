@@ -2,6 +2,35 @@ pub mod boid;
 pub mod settings;
 pub mod orchestrator;
 pub mod utils;
+pub mod rng;
+pub mod noise;
+pub mod flow_field;
+pub mod path;
+pub mod spatial;
+pub mod obstacle;
+pub mod collision;
+pub mod render;
+pub mod trails;
+pub mod svg;
+pub mod rules;
+pub mod recorder;
+pub mod journal;
+pub mod history;
+pub mod viewport;
+pub mod boid3d;
+pub mod orchestrator3d;
+pub mod ecosystem;
+pub mod traits;
+pub mod resize;
+pub mod metrics;
+pub mod tuning;
+pub mod invariants;
+pub mod region;
+pub mod zones;
+pub mod mask;
+pub mod formation;
+pub mod events;
 // Re-export main components for backward compatibility
 pub use orchestrator::BoidOrchestrator;
+pub use orchestrator3d::BoidOrchestrator3D;
 pub use boid::Boid;
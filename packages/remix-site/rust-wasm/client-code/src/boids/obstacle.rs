@@ -0,0 +1,26 @@
+use cgmath::Vector2;
+
+/**
+ * A solid circle in the world that boids can't pass through.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obstacle {
+    pub center: Vector2<f32>,
+    pub radius: f32,
+}
+
+impl Obstacle {
+    pub fn new(center: Vector2<f32>, radius: f32) -> Obstacle {
+        Obstacle { center, radius }
+    }
+}
+
+/**
+ * Flattens obstacles into [x, y, radius, ...] for JS.
+ */
+pub fn obstacles_to_flat(obstacles: &[Obstacle]) -> Vec<f32> {
+    obstacles
+        .iter()
+        .flat_map(|obstacle| [obstacle.center.x, obstacle.center.y, obstacle.radius])
+        .collect()
+}
@@ -1,11 +1,39 @@
 use cgmath::{InnerSpace, Vector2};
-use js_sys::Math::atan2;
+use js_sys::Float32Array;
+#[cfg(parallel_tick)]
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
-use crate::boids::boid::Boid;
-use crate::boids::settings::WorldSettings;
+use crate::boids::boid::{serialize_boids, Boid};
+use crate::boids::collision::resolve_collisions;
+use crate::boids::ecosystem::{Ecosystem, FoodPatch};
+use crate::boids::events::{EventMonitor, SimEvent};
+use crate::boids::flow_field::{FlowField, FlowSource};
+use crate::boids::formation::{outline_points, Formation};
+use crate::boids::history::{Keyframe, TickHistory};
+use crate::boids::invariants::{check_invariants, InvariantViolation};
+use crate::boids::journal::{Journal, JournalEvent};
+use crate::boids::mask::{MaskSource, WorldMask};
+use crate::boids::noise::Perlin;
+use crate::boids::obstacle::{obstacles_to_flat, Obstacle};
+use crate::boids::path::{arrive, BoidPath};
+use crate::boids::recorder::TrajectoryRecorder;
+use crate::boids::region::RegionShape;
+use crate::boids::rng::{random_seed, SimRng};
+use crate::boids::rules::RuleVelocities;
+use crate::boids::resize::{clamp_inward, resize_length, resize_position};
+use crate::boids::settings::{NeighborMode, ResizeMode, WorldSettings};
+use crate::boids::spatial::SpatialGrid;
+use crate::boids::svg::{frame_to_svg, SvgOptions};
+use crate::boids::trails::TrailHistory;
+use crate::boids::traits::{serialize_traits, BoidTraits};
 use crate::boids::utils::LinearSerializable;
+use crate::boids::viewport::{CulledView, Viewport};
+use crate::boids::zones::{self, keep_out, speed_limit, Zone, ZoneEffect};
+use crate::utils::{float32_view, BufferGeneration};
 
 #[wasm_bindgen]
 extern "C" {
@@ -13,97 +41,149 @@ extern "C" {
     fn log(s: &str);
 }
 
+// Keeps the wander noise from lining up with other noise seeded from the same value.
+const WANDER_NOISE_SALT: u64 = 0x5741_4E44_4552;
+
 #[wasm_bindgen]
 pub struct BoidOrchestrator {
     boids: Vec<Boid>,
     transfer_array: Vec<f32>,
     world_settings: WorldSettings,
+    flow_field: FlowField,
+    seed: u32,
+    rng: SimRng,
+    wander_noise: Perlin,
+    path: BoidPath,
+    // Ids of the boids that follow the path, the rest follow them.
+    leaders: BTreeSet<u32>,
+    obstacles: Vec<Obstacle>,
+    // Named areas that change how boids move inside them, in the order they were added.
+    zones: Vec<Zone>,
+    // Shape the world is limited to, instead of its whole rectangle. Shared with the
+    // history keyframes, it only changes when a new mask is set.
+    mask: Option<Arc<WorldMask>>,
+    // Target points the flock can be blended into, and which boid flies to which.
+    formation: Formation,
+    // Id for the next boid added or born. Only ever goes up, so an id is never reused
+    // for a different boid.
+    next_id: u32,
+    // Food and population stats for the optional lifecycle layer.
+    ecosystem: Ecosystem,
+    // Rebuilt whenever boids move, are added or removed, so it always matches `boids`.
+    spatial_index: SpatialGrid,
+    trails: TrailHistory,
+    recorder: TrajectoryRecorder,
+    // Queues events for JS to poll, like boids leaving the world or entering a region.
+    events: EventMonitor,
+    // Every state changing call since construction, so the run can be replayed.
+    journal: Journal,
+    // Keyframes and per-tick dts for stepping back through time.
+    history: TickHistory,
+    // Compact transfer buffers for the boids the camera can see.
+    culled_view: CulledView,
+    // Let JS tell when its typed-array views over the buffers above went stale.
+    transfer_generation: BufferGeneration,
+    // Each boid's `BoidTraits`, in the same order as the transfer array.
+    traits_array: Vec<f32>,
+    traits_generation: BufferGeneration,
+    visible_generation: BufferGeneration,
+    // Whether to check the invariants after every tick, and what the last check found.
+    invariant_checks: bool,
+    invariant_violations: Vec<InvariantViolation>,
+    // Where each boid started the last tick, which is where zones set its speed limit.
+    // Only kept while there are zones, the limit is the same everywhere otherwise.
+    tick_start_positions: HashMap<u32, Vector2<f32>>,
+    // Position and velocity of every leader at the start of the last tick, so followers
+    // don't have to look each leader up by id.
+    tick_leaders: Vec<(Vector2<f32>, Vector2<f32>)>,
+    // Number of ticks run so far.
+    tick_count: u32,
+    // Total simulated time, the sum of every `dt` passed to `tick`.
+    time: f32,
 }
 
 #[wasm_bindgen]
 impl BoidOrchestrator {
     #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         world_width: u32,
         world_height: u32,
         num_boids: u32,
         velocity_limit: f32,
-        pcModifier: f32,
-        avoidanceModifier: f32,
-        avoidanceRange: f32,
-        velocityMatchingModifier: f32,
-        borderConstraintModifier: f32,
+        pc_modifier: f32,
+        avoidance_modifier: f32,
+        avoidance_range: f32,
+        velocity_matching_modifier: f32,
+        border_constraint_modifier: f32,
     ) -> BoidOrchestrator {
         // set_panic_hook();
-        use crate::boids::settings::WorldSettings;
-
-        let mut t_array = Vec::with_capacity(num_boids as usize * 3);
-        let mut boids = Vec::with_capacity(num_boids as usize);
-
-        // Create the boids.
-        for i in 0..num_boids {
-            let boid = Boid {
-                position: Vector2 {
-                    x: js_sys::Math::random() as f32 * world_width as f32,
-                    y: js_sys::Math::random() as f32 * world_height as f32,
-                },
-                velocity: Vector2 { x: 0.0, y: 0.0 },
-                id: i,
-            };
-
-            t_array.push(boid.position.x);
-            t_array.push(boid.position.y);
-            t_array.push(boid.get_velocity_direction() as f32); // TODO get angle calcualtion.
-            boids.push(boid);
-        }
-        
         // Create world settings using the constructor from settings.rs
         let settings = WorldSettings::new(
             world_width,
             world_height,
             velocity_limit,
-            pcModifier,
-            avoidanceModifier,
-            avoidanceRange,
-            velocityMatchingModifier,
-            borderConstraintModifier,
+            pc_modifier,
+            avoidance_modifier,
+            avoidance_range,
+            velocity_matching_modifier,
+            border_constraint_modifier,
         );
-        BoidOrchestrator {
-            boids,
-            transfer_array: t_array,
-            world_settings: settings,
+        BoidOrchestrator::with_seed(settings, num_boids, random_seed())
+    }
+
+    /**
+     * Creates a world whose randomness (spawn positions, wander, ...) is fully determined
+     * by `seed`, so the same seed and inputs always give the same simulation.
+     */
+    pub fn with_seed(world_settings: WorldSettings, num_boids: u32, seed: u32) -> BoidOrchestrator {
+        let mut orchestrator = BoidOrchestrator::from_boids(world_settings, Vec::new(), seed);
+        for _ in 0..num_boids {
+            orchestrator.add_boid();
         }
+        orchestrator
+    }
+
+    pub fn get_seed(&self) -> u32 {
+        self.seed
     }
 
+    /**
+     * Advances the simulation by `dt`. Every boid's rules are evaluated against the
+     * state from the previous tick (double buffered), so the order boids are visited in
+     * doesn't matter. With the `parallel` feature native builds split this work across
+     * threads.
+     */
     pub fn tick(&mut self, dt: f32) {
-        // log("before Tick");
-        // log(&self.boids[0].position.x.to_string());
-        for i in 0..self.boids.len() {
-            let boid = self.boids[i];
-            let new_boid = self.apply_rules(&boid, dt);
-            
-            // // add x pos
-            // self.transfer_array[i * 3] = boid.position.x;
-            // // add y pos
-            // self.transfer_array[(i * 3) + 1] = boid.position.y;
-            // // add theta
-            // self.transfer_array[(i * 3) + 2] = boid.get_velocity_direction() as f32;
-
-            // boid.position.x = new_boid.position.x;
-
-            // boid.position.y = new_boid.position.y;
-            // boid.velocity.x = new_boid.velocity.x;
-            // boid.velocity.y = new_boid.velocity.y;
-            new_boid.serialize_to_array(&mut self.transfer_array, i);
-            self.boids[i] = new_boid;
-        }
-        // log("after Tick");
-        // log(&self.boids[0].position.x.to_string());
+        if !self.record(JournalEvent::Tick { dt, count: 1 }) {
+            return;
+        }
+        self.push_history(dt);
+        self.prepare_tick(dt);
+        let (next_boids, rules) = self.compute_next_boids(dt, false);
+        self.commit_tick(next_boids, rules, dt);
     }
-    
+
     pub fn get_transfer_array_ptr(&self) -> *const f32 {
         self.transfer_array.as_ptr()
     }
+
+    /**
+     * A fresh `Float32Array` over the transfer array, without copying. Adding or
+     * removing boids and wasm memory growth invalidate it, so keep it together with
+     * `get_transfer_generation` and make a new one when the generation changes.
+     */
+    pub fn get_transfer_array_view(&self) -> Float32Array {
+        float32_view(&self.transfer_array)
+    }
+
+    /**
+     * Bumped whenever the transfer array moved since this was last asked, e.g. because
+     * a boid was added or the wasm memory grew.
+     */
+    pub fn get_transfer_generation(&self) -> u32 {
+        self.transfer_generation.current(&self.transfer_array)
+    }
     /**
      * would be slower b/c it's cloning the data, before returning it, rather than not
      */
@@ -116,64 +196,79 @@ impl BoidOrchestrator {
     }
 
     pub fn add_boid(&mut self) {
-        let boid = Boid::new_random_boid_in_world(
+        self.record(JournalEvent::AddBoid { count: 1 });
+        let mut boid = Boid::new_random_boid_in_world(
             self.world_settings.world_width,
             self.world_settings.world_height,
-            self.boids.len() as u32,
+            self.next_boid_id(),
+            &mut self.rng,
         );
+        boid.traits = BoidTraits::random(self.world_settings.traits.spread, &mut self.rng);
+        if let Some(point) = self.mask.as_ref().and_then(|mask| mask.random_point(&mut self.rng)) {
+            boid.position = point;
+        }
 
         self.transfer_array.push(boid.position.x);
         self.transfer_array.push(boid.position.y);
         self.transfer_array
             .push(boid.get_velocity_direction() as f32); // TODO get angle calcualtion.
         self.boids.push(boid);
+        self.sync_traits_array();
+        self.rebuild_spatial_index();
+        self.update_culled_view();
     }
     
     pub fn remove_last_boid(&mut self) {
+        self.record(JournalEvent::RemoveLastBoid);
         self.transfer_array.pop();
         self.transfer_array.pop();
         self.transfer_array.pop();
-        self.boids.pop();
+        if let Some(boid) = self.boids.pop() {
+            self.leaders.remove(&boid.id);
+        }
+        self.sync_traits_array();
+        self.rebuild_spatial_index();
+        self.update_culled_view();
     }
 
     pub fn get_velocity_to_percived_center_x(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_velocity_to_perceived_center(boid).x,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_velocity_to_perceived_center(&self.boids[i], &self.neighbor_indices(i)).x,
             None => 0.0,
         }
     }
     
     pub fn get_velocity_to_percived_center_y(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_velocity_to_perceived_center(boid).y,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_velocity_to_perceived_center(&self.boids[i], &self.neighbor_indices(i)).y,
             None => 0.0,
         }
     }
     
     pub fn get_avoidance_velocity_x(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_avoidance_velocity(boid).x,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_avoidance_velocity(&self.boids[i], &self.neighbor_indices(i)).x,
             None => 0.0,
         }
     }
     
     pub fn get_avoidance_velocity_y(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_avoidance_velocity(boid).y,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_avoidance_velocity(&self.boids[i], &self.neighbor_indices(i)).y,
             None => 0.0,
         }
     }
     
     pub fn get_match_percived_velocity_x(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_match_percived_velocity(boid).x,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_match_percived_velocity(&self.boids[i], &self.neighbor_indices(i)).x,
             None => 0.0,
         }
     }
     
     pub fn get_match_percived_velocity_y(&self, boid_id: usize) -> f32 {
-        match self.get_boid(boid_id) {
-            Some(boid) => self.get_match_percived_velocity(boid).y,
+        match self.boid_index(boid_id) {
+            Some(i) => self.get_match_percived_velocity(&self.boids[i], &self.neighbor_indices(i)).y,
             None => 0.0,
         }
     }
@@ -187,7 +282,7 @@ impl BoidOrchestrator {
     
     pub fn get_velocity_direction(&self, boid_id: usize) -> f64 {
         match self.get_boid(boid_id) {
-            Some(boid) => atan2(boid.velocity.y as f64, boid.velocity.x as f64),
+            Some(boid) => boid.get_velocity_direction(),
             None => 0.0,
         }
     }
@@ -216,11 +311,84 @@ impl BoidOrchestrator {
     }
 
     pub fn set_world_width(&mut self, width: u32) {
+        self.record(JournalEvent::SetWorldWidth { width });
         self.world_settings.set_world_width(width);
+        self.flow_field
+            .resize(self.world_settings.world_width, self.world_settings.world_height);
+        self.rebuild_mask(|point| point);
     }
 
     pub fn set_world_height(&mut self, height: u32) {
+        self.record(JournalEvent::SetWorldHeight { height });
         self.world_settings.set_world_height(height);
+        self.flow_field
+            .resize(self.world_settings.world_width, self.world_settings.world_height);
+        self.rebuild_mask(|point| point);
+    }
+
+    /**
+     * Resizes the world and moves everything in it (boids, obstacles, zones, the mask,
+     * the path, formation targets, food patches and flow field centers) according to `mode`. Unlike `set_world_width`
+     * and `set_world_height`, nothing is left stranded outside the new bounds unless
+     * `mode` is `Keep`. A zero width or height is ignored, there's nothing to scale to
+     * or back from.
+     */
+    pub fn resize_world(&mut self, width: u32, height: u32, mode: ResizeMode) {
+        self.record(JournalEvent::ResizeWorld {
+            width,
+            height,
+            mode,
+        });
+        if width == 0 || height == 0 {
+            return;
+        }
+        let old_size = Vector2::new(
+            self.world_settings.world_width as f32,
+            self.world_settings.world_height as f32,
+        );
+        let new_size = Vector2::new(width as f32, height as f32);
+        let map = |point| resize_position(point, old_size, new_size, mode);
+
+        let margin = self.world_settings.avoidance.avoidance_range;
+        for boid in self.boids.iter_mut() {
+            boid.position = match mode {
+                ResizeMode::Clamp => clamp_inward(boid.position, new_size, margin, &mut self.rng),
+                _ => map(boid.position),
+            };
+        }
+        for obstacle in self.obstacles.iter_mut() {
+            obstacle.center = map(obstacle.center);
+            obstacle.radius = resize_length(obstacle.radius, old_size, new_size, mode);
+        }
+        for patch in self.ecosystem.patches.iter_mut() {
+            patch.center = map(patch.center);
+            patch.radius = resize_length(patch.radius, old_size, new_size, mode);
+        }
+        for zone in self.zones.iter_mut() {
+            zone.shape = zone.shape.mapped(
+                map,
+                |length| resize_length(length, old_size, new_size, mode),
+                mode == ResizeMode::Scale,
+            );
+        }
+        self.path.map_points(map);
+        self.formation.map_targets(map);
+
+        self.world_settings.set_world_width(width);
+        self.world_settings.set_world_height(height);
+        self.flow_field.resize(width, height);
+        self.flow_field
+            .map_points(map, resize_length(1.0, old_size, new_size, mode));
+        self.rebuild_mask(map);
+
+        if mode != ResizeMode::Keep {
+            // Old trail points are in the old layout and would draw lines across the world.
+            self.trails.clear();
+        }
+        serialize_boids(&self.boids, &mut self.transfer_array);
+        self.rebuild_spatial_index();
+        self.update_culled_view();
+        zones::update_counts(&mut self.zones, &self.boids);
     }
 
     pub fn get_avoidance_range(&self) -> f32 {
@@ -228,6 +396,9 @@ impl BoidOrchestrator {
     }
 
     pub fn set_avoidance_range(&mut self, range: f32) {
+        if !self.record(JournalEvent::SetAvoidanceRange { range }) {
+            return;
+        }
         self.world_settings.set_avoidance_range(range);
     }
 
@@ -236,6 +407,9 @@ impl BoidOrchestrator {
     }
 
     pub fn set_avoidance_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetAvoidanceModifier { modifier }) {
+            return;
+        }
         self.world_settings.set_avoidance_modifier(modifier);
     }
 
@@ -244,6 +418,9 @@ impl BoidOrchestrator {
     }
 
     pub fn set_p_center_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetPCenterModifier { modifier }) {
+            return;
+        }
         self.world_settings.set_p_center_modifier(modifier);
     }
 
@@ -252,6 +429,9 @@ impl BoidOrchestrator {
     }
 
     pub fn set_velocity_matching_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetVelocityMatchingModifier { modifier }) {
+            return;
+        }
         self.world_settings.set_velocity_matching_modifier(modifier);
     }
 
@@ -260,164 +440,2942 @@ impl BoidOrchestrator {
     }
 
     pub fn set_border_constraint_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetBorderConstraintModifier { modifier }) {
+            return;
+        }
         self.world_settings.set_border_constraint_modifier(modifier);
     }
-}
 
-impl fmt::Display for BoidOrchestrator {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.boids)?;
-        Ok(())
+    pub fn get_flow_field_modifier(&self) -> f32 {
+        self.world_settings.flow_field.flow_field_modifier
     }
-}
 
-impl BoidOrchestrator {
-    fn get_boid(&self, boid_id: usize) -> Option<&Boid> {
-        self.boids
-            .iter()
-            .find(|boid| boid.id == boid_id as u32)
+    pub fn set_flow_field_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetFlowFieldModifier { modifier }) {
+            return;
+        }
+        self.world_settings.set_flow_field_modifier(modifier);
     }
-    
-    fn apply_rules(&self, boid: &Boid, dt: f32) -> Boid {
-        // Get all of the rule's velocities.
-        let convergence_vel: Vector2<f32> =
-            self.get_velocity_to_perceived_center(boid) * self.world_settings.pc.p_center_modifier;
-        let avoidance_vel: Vector2<f32> =
-            self.get_avoidance_velocity(boid) * self.world_settings.avoidance.avoidance_modifier;
-        let vel_matching_vel: Vector2<f32> = self.get_match_percived_velocity(boid)
-            * self
-                .world_settings
-                .velocity_matching
-                .velocity_matching_modifier;
-        let border_constraint_velocity: Vector2<f32> = self.get_border_velocity(boid)
-            * self
-                .world_settings
-                .border_constraint
-                .border_constraint_modifier;
-        // log("Id: ");
-        // log(&boid.id.to_string());
-        // log("oldVel:");
-        // log(&boid.velocity.x.to_string());
-        // log(&boid.velocity.y.to_string());
-        // add them to the old vel to get the new vel.
-        let mut new_velocity = boid.velocity
-            + convergence_vel
-            + avoidance_vel
-            + vel_matching_vel
-            + border_constraint_velocity;
 
-        let vel_limit: f32 = self.world_settings.velocity_limit; //25.0
-        if new_velocity.magnitude() > (vel_limit) {
-            new_velocity = new_velocity.normalize_to(vel_limit)
+    pub fn get_wander_modifier(&self) -> f32 {
+        self.world_settings.wander.wander_modifier
+    }
+
+    pub fn set_wander_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetWanderModifier { modifier }) {
+            return;
         }
+        self.world_settings.set_wander_modifier(modifier);
+    }
 
-        // log("newVel: ");
-        // log(&new_velocity.x.to_string());
-        // log(&new_velocity.y.to_string());
-        // create the new position, with the dt
-        let new_position = Vector2 {
-            x: boid.position.x + (new_velocity.x * dt),
-            y: boid.position.y + (new_velocity.y * dt),
+    pub fn get_wander_frequency(&self) -> f32 {
+        self.world_settings.wander.wander_frequency
+    }
+
+    pub fn set_wander_frequency(&mut self, frequency: f32) {
+        if !self.record(JournalEvent::SetWanderFrequency { frequency }) {
+            return;
+        }
+        self.world_settings.set_wander_frequency(frequency);
+    }
+
+    pub fn get_path_following_modifier(&self) -> f32 {
+        self.world_settings.path.path_following_modifier
+    }
+
+    pub fn set_path_following_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetPathFollowingModifier { modifier }) {
+            return;
+        }
+        self.world_settings.set_path_following_modifier(modifier);
+    }
+
+    pub fn get_leader_following_modifier(&self) -> f32 {
+        self.world_settings.path.leader_following_modifier
+    }
+
+    pub fn set_leader_following_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetLeaderFollowingModifier { modifier }) {
+            return;
+        }
+        self.world_settings.set_leader_following_modifier(modifier);
+    }
+
+    pub fn get_follow_distance(&self) -> f32 {
+        self.world_settings.path.follow_distance
+    }
+
+    pub fn set_follow_distance(&mut self, distance: f32) {
+        if !self.record(JournalEvent::SetFollowDistance { distance }) {
+            return;
+        }
+        self.world_settings.set_follow_distance(distance);
+    }
+
+    pub fn get_arrival_radius(&self) -> f32 {
+        self.world_settings.path.arrival_radius
+    }
+
+    pub fn set_arrival_radius(&mut self, radius: f32) {
+        if !self.record(JournalEvent::SetArrivalRadius { radius }) {
+            return;
+        }
+        self.world_settings.set_arrival_radius(radius);
+    }
+
+    pub fn get_neighbor_mode(&self) -> NeighborMode {
+        self.world_settings.neighbors.mode
+    }
+
+    pub fn set_neighbor_mode(&mut self, mode: NeighborMode) {
+        self.record(JournalEvent::SetNeighborMode { mode });
+        self.world_settings.set_neighbor_mode(mode);
+    }
+
+    pub fn get_perception_range(&self) -> f32 {
+        self.world_settings.neighbors.perception_range
+    }
+
+    pub fn set_perception_range(&mut self, range: f32) {
+        if !self.record(JournalEvent::SetPerceptionRange { range }) {
+            return;
+        }
+        self.world_settings.set_perception_range(range);
+        self.rebuild_spatial_index();
+    }
+
+    pub fn get_k_nearest(&self) -> u32 {
+        self.world_settings.neighbors.k
+    }
+
+    pub fn set_k_nearest(&mut self, k: u32) {
+        self.record(JournalEvent::SetKNearest { k });
+        self.world_settings.set_k_nearest(k);
+    }
+
+    pub fn get_collisions_enabled(&self) -> bool {
+        self.world_settings.collision.enabled
+    }
+
+    pub fn set_collisions_enabled(&mut self, enabled: bool) {
+        self.record(JournalEvent::SetCollisionsEnabled { enabled });
+        self.world_settings.set_collisions_enabled(enabled);
+    }
+
+    pub fn get_boid_radius(&self) -> f32 {
+        self.world_settings.collision.boid_radius
+    }
+
+    pub fn set_boid_radius(&mut self, radius: f32) {
+        if !self.record(JournalEvent::SetBoidRadius { radius }) {
+            return;
+        }
+        self.world_settings.set_boid_radius(radius);
+    }
+
+    pub fn get_collision_iterations(&self) -> u32 {
+        self.world_settings.collision.iterations
+    }
+
+    pub fn set_collision_iterations(&mut self, iterations: u32) {
+        self.record(JournalEvent::SetCollisionIterations { iterations });
+        self.world_settings.set_collision_iterations(iterations);
+    }
+
+    pub fn get_wall_collisions(&self) -> bool {
+        self.world_settings.collision.walls
+    }
+
+    pub fn set_wall_collisions(&mut self, walls: bool) {
+        self.record(JournalEvent::SetWallCollisions { walls });
+        self.world_settings.set_wall_collisions(walls);
+    }
+
+    /**
+     * Gives one boid its own collision radius. Pass 0 to go back to the world default.
+     */
+    pub fn set_boid_collision_radius(&mut self, boid_id: u32, radius: f32) {
+        if !self.record(JournalEvent::SetBoidCollisionRadius { boid_id, radius }) {
+            return;
+        }
+        if let Some(boid) = self.boids.iter_mut().find(|boid| boid.id == boid_id) {
+            boid.radius = radius.max(0.0);
+        }
+    }
+
+    // Obstacles
+    /**
+     * Adds a solid circle to the world and returns its index, or nothing when a number
+     * isn't finite.
+     */
+    pub fn add_obstacle(&mut self, x: f32, y: f32, radius: f32) -> Option<u32> {
+        if !self.record(JournalEvent::AddObstacle { x, y, radius }) {
+            return None;
+        }
+        self.obstacles.push(Obstacle::new(Vector2::new(x, y), radius));
+        Some((self.obstacles.len() - 1) as u32)
+    }
+
+    pub fn remove_obstacle(&mut self, index: u32) {
+        self.record(JournalEvent::RemoveObstacle { index });
+        if (index as usize) < self.obstacles.len() {
+            self.obstacles.remove(index as usize);
+        }
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.record(JournalEvent::ClearObstacles);
+        self.obstacles.clear();
+    }
+
+    pub fn get_obstacle_count(&self) -> u32 {
+        self.obstacles.len() as u32
+    }
+
+    /**
+     * The obstacles as a flat [x, y, radius, ...] array, for the renderers.
+     */
+    pub fn get_obstacles(&self) -> Vec<f32> {
+        obstacles_to_flat(&self.obstacles)
+    }
+
+    // Zones
+    /**
+     * Adds a rectangular zone, replacing any zone with the same name. `strength` is the
+     * slow down factor or the velocity limit, depending on `effect`.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_zone_rect(
+        &mut self,
+        name: &str,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        effect: ZoneEffect,
+        strength: f32,
+    ) {
+        if !self.record(JournalEvent::AddZoneRect {
+            name: name.to_string(),
+            x,
+            y,
+            width,
+            height,
+            effect,
+            strength,
+        }) {
+            return;
+        }
+        let shape = RegionShape::Rect {
+            min: Vector2::new(x.min(x + width), y.min(y + height)),
+            max: Vector2::new(x.max(x + width), y.max(y + height)),
         };
-        // log("oldPosX");
-        // log(&boid.position.x.to_string());
-        // boid.position = new_position;
-        // log("setNewPos:");
-        // log(&new_position.x.to_string());
-        // boid.position.x = boid.position.x + (new_velocity.x * dt);
-        // boid.position.y = boid.position.y + (new_velocity.y * dt);
-        // boid.velocity = new_velocity;
-        // boid.velocity.x = new_velocity.x;
-        // boid.velocity.y = new_velocity.y;
+        self.put_zone(Zone::new(name, shape, effect, strength));
+    }
 
-        let new_boid = Boid {
-            velocity: new_velocity,
-            position: new_position,
-            id: boid.id,
+    pub fn add_zone_circle(&mut self, name: &str, x: f32, y: f32, radius: f32, effect: ZoneEffect, strength: f32) {
+        if !self.record(JournalEvent::AddZoneCircle {
+            name: name.to_string(),
+            x,
+            y,
+            radius,
+            effect,
+            strength,
+        }) {
+            return;
+        }
+        let shape = RegionShape::Circle {
+            center: Vector2::new(x, y),
+            radius,
         };
-        // log(format!("boid: {:?}", boid).as_ref());
-        return new_boid;
+        self.put_zone(Zone::new(name, shape, effect, strength));
+    }
+
+    pub fn remove_zone(&mut self, name: &str) -> bool {
+        self.record(JournalEvent::RemoveZone { name: name.to_string() });
+        let before = self.zones.len();
+        self.zones.retain(|zone| zone.name != name);
+        self.zones.len() != before
+    }
+
+    pub fn clear_zones(&mut self) {
+        self.record(JournalEvent::ClearZones);
+        self.zones.clear();
+    }
+
+    pub fn get_zone_count(&self) -> u32 {
+        self.zones.len() as u32
     }
 
     /**
-     * Rule 1. The boid is attracted to the percived center of all boids.   
-     * get average boid position, then get a vector from the boid pos to that.  
+     * How many boids are inside the zone called `name`, 0 if there's no such zone.
      */
-    fn get_velocity_to_perceived_center(&self, boid: &Boid) -> Vector2<f32> {
-        let sum_of_positions: Vector2<f32> = self
-            .boids
+    pub fn get_zone_boid_count(&self, name: &str) -> u32 {
+        self.zones
             .iter()
-            .filter(|&other_boids| other_boids.id != boid.id)
-            .fold(Vector2 { x: 0.0, y: 0.0 }, |acc, other_boid| {
-                acc + other_boid.position
-            });
+            .find(|zone| zone.name == name)
+            .map_or(0, |zone| zone.count)
+    }
 
-        // log(format!("sum_of_positions: {:?}", sum_of_positions).as_ref());
-        let center = sum_of_positions / (self.boids.len() - 1) as f32;
-        // log(format!("center: {:?}", center).as_ref());
-        // log(format!("boid position: {:?}", boid.position).as_ref());
-        return (center - boid.position) / 100.0;
+    /**
+     * Every zone as a JSON array of {name, shape, effect, strength, count}, for drawing
+     * them and showing their counters.
+     */
+    pub fn get_zones(&self) -> String {
+        let zones: Vec<serde_json::Value> = self.zones.iter().map(Zone::to_json).collect();
+        serde_json::Value::Array(zones).to_string()
     }
 
+    // World mask
     /**
-     * Rule 2. Boids want to avoid each other.  
-     * get all boids within a min distance, then get a vec between the boid and them
+     * Limits the world to a polygon given as a flat [x0, y0, x1, y1, ...] array. Boids
+     * turn away from its edges and new boids spawn inside it. Fewer than 3 points leave
+     * the mask as it was, they'd make a mask with nothing inside.
      */
-    fn get_avoidance_velocity(&self, boid: &Boid) -> Vector2<f32> {
-        let sum_of_avoidance_vector: Vector2<f32> = self
-            .boids
-            .iter()
-            .filter(|other_boids| other_boids.id != boid.id)
-            // only boids less than avoidance_distance TODO: if also within sightline.
-            .filter(|other_boids| {
-                return (other_boids.position - boid.position).magnitude()
-                    < self.world_settings.avoidance.avoidance_range;
-            })
-            .fold(Vector2::<f32> { x: 0.0, y: 0.0 }, |acc, other_boid| {
-                // acc - (other_boid.position - boid.position)
-                
-                let diff = other_boid.position - boid.position;
-                let distance = diff.magnitude();
-                // Weight by inverse distance (closer boids have more influence)
-                let accu=  diff * (4.0 / distance);
-                // if other boid is almost on top of this one (within 0.1), then move away from it
-                // didn't work super well, was jittery
-                // if distance < 0.1 {
-                //     return acc - diff;
-                // }
-                acc - accu
-            });
-        sum_of_avoidance_vector
+    pub fn set_world_mask_polygon(&mut self, points: &[f32]) {
+        if !self.record(JournalEvent::SetWorldMaskPolygon { points: points.to_vec() }) {
+            return;
+        }
+        let points: Vec<Vector2<f32>> = points
+            .chunks_exact(2)
+            .map(|point| Vector2::new(point[0], point[1]))
+            .collect();
+        if points.len() < 3 {
+            return;
+        }
+        self.set_mask(MaskSource::Polygon(points));
     }
-    
-    fn get_match_percived_velocity(&self, boid: &Boid) -> Vector2<f32> {
-        let sum_of_velocity: Vector2<f32> = self
-            .boids
-            .iter()
-            .filter(|&other_boids| other_boids.id != boid.id)
-            .fold(Vector2 { x: 0.0, y: 0.0 }, |acc, other_boid| {
-                acc + other_boid.velocity
-            });
 
-        // log(format!("sum_of_velocity: {:?}", sum_of_velocity).as_ref());
-        let center = sum_of_velocity / (self.boids.len() - 1) as f32;
-        // log(format!("center: {:?}", center).as_ref());
-        // log(format!("boid vel: {:?}", boid.velocity).as_ref());
-        return (center - boid.velocity) / 8.0;
+    /**
+     * Limits the world to a `width` x `height` bitmap (text, a logo, ...) stretched over
+     * it, one byte per pixel in row major order. Pixels above 127 are inside. An empty
+     * bitmap or one with the wrong number of pixels leaves the mask as it was.
+     */
+    pub fn set_world_mask_bitmap(&mut self, width: u32, height: u32, pixels: &[u8]) {
+        self.record(JournalEvent::SetWorldMaskBitmap {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        });
+        if width == 0 || height == 0 || pixels.len() != width as usize * height as usize {
+            return;
+        }
+        self.set_mask(MaskSource::Bitmap {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        });
     }
 
-    fn get_border_velocity(&self, boid: &Boid) -> Vector2<f32> {
-        let mut border_velocity_vec: Vector2<f32> = Vector2::new(0.0, 0.0);
-        if boid.position.x < 0.0 {
-            border_velocity_vec.x = 10.0;
-        } else if boid.position.x > self.world_settings.world_width as f32 {
-            border_velocity_vec.x = -10.0;
+    pub fn clear_world_mask(&mut self) {
+        self.record(JournalEvent::ClearWorldMask);
+        self.mask = None;
+    }
+
+    pub fn has_world_mask(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    /**
+     * Whether (x, y) is inside the world, mask included.
+     */
+    pub fn world_mask_contains(&self, x: f32, y: f32) -> bool {
+        let point = Vector2::new(x, y);
+        match &self.mask {
+            Some(mask) => mask.contains(point),
+            None => self.in_world_rect(point),
         }
-        if boid.position.y < 0.0 {
-            border_velocity_vec.y = 10.0;
-        } else if boid.position.y > self.world_settings.world_height as f32 {
-            border_velocity_vec.y = -10.0;
+    }
+
+    // Formation
+    /**
+     * Gives the flock a formation to fly into, from a flat [x0, y0, x1, y1, ...] array of
+     * target points. Each point goes to the nearest boid still without one, boids past
+     * the number of points keep flocking. How strongly boids are pulled into formation
+     * is up to the blend, which starts at 0.
+     */
+    pub fn set_formation_points(&mut self, points: &[f32]) {
+        if !self.record(JournalEvent::SetFormationPoints { points: points.to_vec() }) {
+            return;
+        }
+        let targets = points
+            .chunks_exact(2)
+            .map(|point| Vector2::new(point[0], point[1]))
+            .collect();
+        self.set_formation(targets);
+    }
+
+    /**
+     * A formation with one target per boid, spread along the outline of a polygon given
+     * as a flat [x0, y0, x1, y1, ...] array when `outline` is true, or over its inside
+     * otherwise.
+     */
+    pub fn set_formation_polygon(&mut self, points: &[f32], outline: bool) {
+        if !self.record(JournalEvent::SetFormationPolygon {
+            points: points.to_vec(),
+            outline,
+        }) {
+            return;
+        }
+        let polygon: Vec<Vector2<f32>> = points
+            .chunks_exact(2)
+            .map(|point| Vector2::new(point[0], point[1]))
+            .collect();
+        let targets = if outline {
+            outline_points(&polygon, self.boids.len())
+        } else {
+            let settings = &self.world_settings;
+            WorldMask::new(MaskSource::Polygon(polygon), settings.world_width, settings.world_height)
+                .inside_points(self.boids.len())
+        };
+        self.set_formation(targets);
+    }
+
+    /**
+     * A formation with one target per boid, spread over the inside of a `width` x
+     * `height` bitmap stretched over the world, the same layout as
+     * `set_world_mask_bitmap`. For text or a logo, draw it into a canvas and pass its
+     * alpha channel.
+     */
+    pub fn set_formation_bitmap(&mut self, width: u32, height: u32, pixels: &[u8]) {
+        self.record(JournalEvent::SetFormationBitmap {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        });
+        if width == 0 || height == 0 || pixels.len() != width as usize * height as usize {
+            return;
+        }
+        let source = MaskSource::Bitmap {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        };
+        let settings = &self.world_settings;
+        let targets =
+            WorldMask::new(source, settings.world_width, settings.world_height).inside_points(self.boids.len());
+        self.set_formation(targets);
+    }
+
+    /**
+     * Assigns every target again from where the boids are now, e.g. after the flock
+     * scattered and boids ended up far from their own targets.
+     */
+    pub fn reassign_formation(&mut self) {
+        self.record(JournalEvent::ReassignFormation);
+        self.formation.reassign(&self.boids);
+    }
+
+    pub fn clear_formation(&mut self) {
+        self.record(JournalEvent::ClearFormation);
+        self.formation = Formation::default();
+    }
+
+    pub fn has_formation(&self) -> bool {
+        !self.formation.is_empty()
+    }
+
+    /**
+     * The formation's target points as a flat [x0, y0, x1, y1, ...] array.
+     */
+    pub fn get_formation_targets(&self) -> Vec<f32> {
+        self.formation
+            .targets()
+            .iter()
+            .flat_map(|target| [target.x, target.y])
+            .collect()
+    }
+
+    /**
+     * The [x, y] target of a boid, empty when it has none.
+     */
+    pub fn get_formation_target(&self, boid_id: usize) -> Vec<f32> {
+        match self.formation.target(boid_id as u32) {
+            Some(target) => vec![target.x, target.y],
+            None => Vec::new(),
+        }
+    }
+
+    /**
+     * 0 flocks freely, 1 flies straight into formation, anything in between mixes the
+     * two. Boids steer to their targets over many ticks, so a single jump from 0 to 1
+     * already morphs the flock into the shape. Don't animate it every frame: each call
+     * is journaled and drops the history after the current tick.
+     */
+    pub fn set_formation_blend(&mut self, blend: f32) {
+        if !self.record(JournalEvent::SetFormationBlend { blend }) {
+            return;
+        }
+        self.world_settings.set_formation_blend(blend);
+    }
+
+    pub fn get_formation_blend(&self) -> f32 {
+        self.world_settings.formation.blend
+    }
+
+    pub fn set_formation_modifier(&mut self, modifier: f32) {
+        if !self.record(JournalEvent::SetFormationModifier { modifier }) {
+            return;
+        }
+        self.world_settings.set_formation_modifier(modifier);
+    }
+
+    pub fn get_formation_modifier(&self) -> f32 {
+        self.world_settings.formation.formation_modifier
+    }
+
+    pub fn set_formation_arrival_radius(&mut self, radius: f32) {
+        if !self.record(JournalEvent::SetFormationArrivalRadius { radius }) {
+            return;
+        }
+        self.world_settings.set_formation_arrival_radius(radius);
+    }
+
+    pub fn get_formation_arrival_radius(&self) -> f32 {
+        self.world_settings.formation.arrival_radius
+    }
+
+    // Export
+    /**
+     * How many past positions to keep per boid for trails. 0 turns recording off.
+     */
+    pub fn set_trail_length(&mut self, length: u32) {
+        self.trails.set_length(length as usize);
+    }
+
+    pub fn get_trail_length(&self) -> u32 {
+        self.trails.length() as u32
+    }
+
+    /**
+     * The current frame as a standalone SVG document.
+     */
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        frame_to_svg(self, options)
+    }
+
+    // Replay
+    /**
+     * The journal of this simulation as JSON, small enough to attach to a bug report.
+     */
+    pub fn export_journal(&self) -> String {
+        self.journal.to_json()
+    }
+
+    /**
+     * Rebuilds a simulation from a journal exported with `export_journal`. The result is
+     * in exactly the same state as the simulation the journal was taken from. Journals
+     * that hit their event limit are refused, they're missing the calls after it.
+     */
+    pub fn replay(journal: &str) -> Result<BoidOrchestrator, JsValue> {
+        Journal::from_json(journal)
+            .and_then(|journal| match journal.truncated {
+                true => Err(format!("journal stopped after {} events", journal.events.len())),
+                false => Ok(journal),
+            })
+            .map(|journal| BoidOrchestrator::from_journal(&journal))
+            .map_err(|error| JsValue::from_str(&error))
+    }
+
+    // Ecosystem
+    /**
+     * Turns on the lifecycle layer: boids burn energy (more when fast), eat from food
+     * patches, split in two when they have `reproduction_energy` and die at zero.
+     */
+    pub fn set_ecosystem_enabled(&mut self, enabled: bool) {
+        self.record(JournalEvent::SetEcosystemEnabled { enabled });
+        self.world_settings.set_ecosystem_enabled(enabled);
+    }
+
+    pub fn get_ecosystem_enabled(&self) -> bool {
+        self.world_settings.ecosystem.enabled
+    }
+
+    pub fn set_energy_drain(&mut self, base_drain: f32, speed_drain: f32) {
+        if !self.record(JournalEvent::SetEnergyDrain {
+            base_drain,
+            speed_drain,
+        }) {
+            return;
+        }
+        self.world_settings.set_energy_drain(base_drain, speed_drain);
+    }
+
+    pub fn get_base_energy_drain(&self) -> f32 {
+        self.world_settings.ecosystem.base_drain
+    }
+
+    pub fn get_speed_energy_drain(&self) -> f32 {
+        self.world_settings.ecosystem.speed_drain
+    }
+
+    pub fn set_eat_rate(&mut self, eat_rate: f32) {
+        if !self.record(JournalEvent::SetEatRate { eat_rate }) {
+            return;
+        }
+        self.world_settings.set_eat_rate(eat_rate);
+    }
+
+    pub fn get_eat_rate(&self) -> f32 {
+        self.world_settings.ecosystem.eat_rate
+    }
+
+    pub fn set_reproduction_energy(&mut self, energy: f32) {
+        if !self.record(JournalEvent::SetReproductionEnergy { energy }) {
+            return;
+        }
+        self.world_settings.set_reproduction_energy(energy);
+    }
+
+    pub fn get_reproduction_energy(&self) -> f32 {
+        self.world_settings.ecosystem.reproduction_energy
+    }
+
+    pub fn set_mutation(&mut self, mutation: f32) {
+        if !self.record(JournalEvent::SetMutation { mutation }) {
+            return;
+        }
+        self.world_settings.set_mutation(mutation);
+    }
+
+    pub fn get_mutation(&self) -> f32 {
+        self.world_settings.ecosystem.mutation
+    }
+
+    pub fn set_max_population(&mut self, max_population: u32) {
+        self.record(JournalEvent::SetMaxPopulation { max_population });
+        self.world_settings.set_max_population(max_population);
+    }
+
+    pub fn get_max_population(&self) -> u32 {
+        self.world_settings.ecosystem.max_population
+    }
+
+    /**
+     * Adds a full food patch and returns its index, or nothing when a number isn't
+     * finite.
+     */
+    pub fn add_food_patch(&mut self, x: f32, y: f32, radius: f32, capacity: f32, regrowth: f32) -> Option<u32> {
+        if !self.record(JournalEvent::AddFoodPatch {
+            x,
+            y,
+            radius,
+            capacity,
+            regrowth,
+        }) {
+            return None;
+        }
+        self.ecosystem
+            .patches
+            .push(FoodPatch::new(Vector2::new(x, y), radius, capacity, regrowth));
+        Some((self.ecosystem.patches.len() - 1) as u32)
+    }
+
+    pub fn clear_food_patches(&mut self) {
+        self.record(JournalEvent::ClearFoodPatches);
+        self.ecosystem.patches.clear();
+    }
+
+    /**
+     * Food patches as [x, y, radius, amount, ...].
+     */
+    pub fn get_food_patches(&self) -> Vec<f32> {
+        self.ecosystem
+            .patches
+            .iter()
+            .flat_map(|patch| [patch.center.x, patch.center.y, patch.radius, patch.amount])
+            .collect()
+    }
+
+    pub fn get_population(&self) -> u32 {
+        self.boids.len() as u32
+    }
+
+    /**
+     * The population after each of the last ticks the ecosystem ran, oldest first.
+     */
+    pub fn get_population_history(&self) -> Vec<u32> {
+        self.ecosystem.population_history().iter().copied().collect()
+    }
+
+    pub fn get_births(&self) -> u32 {
+        self.ecosystem.births()
+    }
+
+    pub fn get_deaths(&self) -> u32 {
+        self.ecosystem.deaths()
+    }
+
+    pub fn get_energy(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => boid.energy,
+            None => 0.0,
+        }
+    }
+
+    // Traits
+    /**
+     * How much the traits of boids spawned from now on vary, e.g. 0.2 for every
+     * multiplier somewhere in [0.8, 1.2]. Existing boids keep theirs.
+     */
+    pub fn set_trait_spread(&mut self, spread: f32) {
+        if !self.record(JournalEvent::SetTraitSpread { spread }) {
+            return;
+        }
+        self.world_settings.set_trait_spread(spread);
+    }
+
+    pub fn get_trait_spread(&self) -> f32 {
+        self.world_settings.traits.spread
+    }
+
+    /**
+     * Every boid's traits as [max_speed, perception, separation_distance, size,
+     * cohesion, separation, alignment, border, ...], in transfer array order. Renderers
+     * can use `size` to scale boids.
+     */
+    pub fn get_traits_ptr(&self) -> *const f32 {
+        self.traits_array.as_ptr()
+    }
+
+    pub fn get_traits_view(&self) -> Float32Array {
+        float32_view(&self.traits_array)
+    }
+
+    pub fn get_traits_generation(&self) -> u32 {
+        self.traits_generation.current(&self.traits_array)
+    }
+
+    pub fn get_traits_stride(&self) -> u32 {
+        BoidTraits::NUM_ELEMENTS as u32
+    }
+
+    pub fn get_boid_traits(&self, boid_id: usize) -> Vec<f32> {
+        match self.get_boid(boid_id) {
+            Some(boid) => boid.traits.as_array().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    // Viewport
+    /**
+     * Puts a camera over the world, centered on (center_x, center_y) in world units.
+     * `width` and `height` are the container size in pixels, `zoom` scales world units
+     * to pixels. From then on every tick also fills the visible buffer with just the
+     * boids inside the camera's view plus the margin.
+     */
+    pub fn set_viewport(&mut self, center_x: f32, center_y: f32, width: f32, height: f32, zoom: f32) {
+        self.culled_view.set_viewport(Some(Viewport::new(
+            Vector2::new(center_x, center_y),
+            width,
+            height,
+            zoom,
+        )));
+        self.update_culled_view();
+    }
+
+    pub fn clear_viewport(&mut self) {
+        self.culled_view.set_viewport(None);
+    }
+
+    pub fn has_viewport(&self) -> bool {
+        self.culled_view.viewport().is_some()
+    }
+
+    /**
+     * The visible world rect including the margin as [min_x, min_y, max_x, max_y], empty
+     * without a viewport.
+     */
+    pub fn get_viewport_bounds(&self) -> Vec<f32> {
+        match self.culled_view.viewport() {
+            Some(viewport) => {
+                let (min, max) = viewport.bounds(self.culled_view.margin());
+                vec![min.x, min.y, max.x, max.y]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /**
+     * Extra world units around the viewport that still count as visible, so boids
+     * don't pop in at the edges.
+     */
+    pub fn set_viewport_margin(&mut self, margin: f32) {
+        self.culled_view.set_margin(margin);
+        self.update_culled_view();
+    }
+
+    pub fn get_viewport_margin(&self) -> f32 {
+        self.culled_view.margin()
+    }
+
+    /**
+     * Same layout as the transfer array, but only the boids in the viewport.
+     * `get_visible_count` of them are valid. Empty without a viewport.
+     */
+    pub fn get_visible_ptr(&self) -> *const f32 {
+        self.culled_view.visible().as_ptr()
+    }
+
+    /**
+     * A fresh `Float32Array` over the visible buffer, see `get_transfer_array_view`.
+     */
+    pub fn get_visible_view(&self) -> Float32Array {
+        float32_view(self.culled_view.visible())
+    }
+
+    pub fn get_visible_generation(&self) -> u32 {
+        self.visible_generation.current(self.culled_view.visible())
+    }
+
+    pub fn get_visible_count(&self) -> u32 {
+        self.culled_view.visible_count() as u32
+    }
+
+    /**
+     * The ids of the boids in the visible buffer, in the same order.
+     */
+    pub fn get_visible_ids_ptr(&self) -> *const u32 {
+        self.culled_view.visible_ids().as_ptr()
+    }
+
+    /**
+     * Merges the boids outside the viewport into one point per `cell_size` square
+     * of the world, for drawing far away flocks cheaply. 0 turns it off.
+     */
+    pub fn set_cluster_cell_size(&mut self, cell_size: f32) {
+        self.culled_view.set_cluster_cell_size(cell_size);
+        self.update_culled_view();
+    }
+
+    pub fn get_cluster_cell_size(&self) -> f32 {
+        self.culled_view.cluster_cell_size()
+    }
+
+    /**
+     * Clusters as [x, y, count, ...], where (x, y) is the average position of the
+     * boids in the cluster.
+     */
+    pub fn get_clusters_ptr(&self) -> *const f32 {
+        self.culled_view.clusters().as_ptr()
+    }
+
+    pub fn get_cluster_count(&self) -> u32 {
+        self.culled_view.cluster_count() as u32
+    }
+
+    // Time controls
+    /**
+     * Restores the simulation to how it was right after `tick`. Only ticks between
+     * `get_history_start_tick` and `get_history_end_tick` can be reached; returns false
+     * otherwise. Seeking back keeps the later ticks around until the simulation is
+     * ticked or edited, so the timeline can be scrubbed in both directions.
+     */
+    pub fn seek(&mut self, tick: u32) -> bool {
+        if tick == self.tick_count {
+            return true;
+        }
+        let (keyframe, dts) = match self.history.plan(tick) {
+            Some((keyframe, dts)) => (keyframe.clone(), dts.to_vec()),
+            None => return false,
+        };
+        self.record(JournalEvent::Seek { tick });
+        self.restore_keyframe(keyframe);
+        // Re-simulated ticks were already seen, keep them out of the recording.
+        let recorder = std::mem::take(&mut self.recorder);
+        let mut events = std::mem::take(&mut self.events);
+        for dt in dts {
+            self.prepare_tick(dt);
+            let (next_boids, rules) = self.compute_next_boids(dt, false);
+            self.commit_tick(next_boids, rules, dt);
+        }
+        self.recorder = recorder;
+        // The boids jumped rather than moved, so catch up without firing anything.
+        events.observe(self.tick_count, &self.boids, &self.spatial_index, self.world_size(), false);
+        self.events = events;
+        self.history.mark_restored();
+        true
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        self.tick_count > 0 && self.seek(self.tick_count - 1)
+    }
+
+    /**
+     * Moves one tick forward through history that was stepped back over, without
+     * discarding it the way `tick` would.
+     */
+    pub fn step_forward(&mut self) -> bool {
+        self.seek(self.tick_count + 1)
+    }
+
+    pub fn get_history_start_tick(&self) -> u32 {
+        self.history.start_tick().unwrap_or(self.tick_count)
+    }
+
+    pub fn get_history_end_tick(&self) -> u32 {
+        self.history.end_tick().unwrap_or(self.tick_count)
+    }
+
+    pub fn get_history_bytes(&self) -> u32 {
+        self.history.size_in_bytes() as u32
+    }
+
+    pub fn get_history_keyframe_interval(&self) -> u32 {
+        self.history.keyframe_interval()
+    }
+
+    pub fn get_history_max_bytes(&self) -> u32 {
+        self.history.max_bytes() as u32
+    }
+
+    /**
+     * Takes a keyframe every `keyframe_interval` ticks and keeps the history under
+     * `max_bytes`, dropping the oldest ticks first. A `max_bytes` of 0 turns it off.
+     */
+    pub fn set_history_limits(&mut self, keyframe_interval: u32, max_bytes: u32) {
+        self.record(JournalEvent::SetHistoryLimits {
+            keyframe_interval,
+            max_bytes,
+        });
+        self.history.set_limits(keyframe_interval, max_bytes as usize);
+    }
+
+    pub fn get_tick_count(&self) -> u32 {
+        self.tick_count
+    }
+
+    // Trajectory recording
+    /**
+     * Starts recording every tick from `from_tick` to `to_tick` (inclusive, 0 for no end).
+     * At most `max_rows` rows are kept, older rows are dropped first. With `include_rules`
+     * each row also carries the weighted rule vectors that produced it.
+     */
+    pub fn start_recording(&mut self, from_tick: u32, to_tick: u32, max_rows: u32, include_rules: bool) {
+        self.recorder
+            .start(from_tick, to_tick, max_rows as usize, include_rules);
+    }
+
+    /**
+     * Only record these boids. An empty list records every boid.
+     */
+    pub fn set_recorded_boids(&mut self, boid_ids: Vec<u32>) {
+        self.recorder.set_boids(boid_ids);
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recorder.stop();
+    }
+
+    pub fn clear_recording(&mut self) {
+        self.recorder.clear();
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_active()
+    }
+
+    pub fn get_recorded_row_count(&self) -> u32 {
+        self.recorder.len() as u32
+    }
+
+    /**
+     * Rows dropped because the recording went over its row limit.
+     */
+    pub fn get_dropped_row_count(&self) -> u32 {
+        self.recorder.dropped() as u32
+    }
+
+    pub fn export_recording_csv(&self) -> String {
+        self.recorder.to_csv()
+    }
+
+    pub fn export_recording_json_lines(&self) -> String {
+        self.recorder.to_json_lines()
+    }
+
+    // Simulation events. Each fires once, on the tick it starts.
+    pub fn set_bounds_events(&mut self, enabled: bool) {
+        self.events.set_bounds(enabled);
+    }
+
+    /**
+     * Fires an event when two boids come within `distance` of each other. 0 turns it off.
+     */
+    pub fn set_proximity_events(&mut self, distance: f32) {
+        self.events.set_proximity_distance(distance);
+    }
+
+    /**
+     * Fires events when flocks merge or split. Boids closer than `link_distance` are in
+     * the same flock, and only groups of at least `min_flock_size` boids count. A
+     * `link_distance` of 0 turns it off.
+     */
+    pub fn set_flock_events(&mut self, link_distance: f32, min_flock_size: u32) {
+        self.events.set_flock_link_distance(link_distance, min_flock_size);
+    }
+
+    /**
+     * Adds a region that fires events as boids enter and leave it. Returns its id. A
+     * negative width or height extends the rectangle left or up from `x`, `y`.
+     */
+    pub fn add_event_region_rect(&mut self, x: f32, y: f32, width: f32, height: f32) -> u32 {
+        self.events.add_region(RegionShape::Rect {
+            min: Vector2::new(x.min(x + width), y.min(y + height)),
+            max: Vector2::new(x.max(x + width), y.max(y + height)),
+        })
+    }
+
+    pub fn add_event_region_circle(&mut self, x: f32, y: f32, radius: f32) -> u32 {
+        self.events.add_region(RegionShape::Circle {
+            center: Vector2::new(x, y),
+            radius,
+        })
+    }
+
+    pub fn remove_event_region(&mut self, region: u32) -> bool {
+        self.events.remove_region(region)
+    }
+
+    pub fn clear_event_regions(&mut self) {
+        self.events.clear_regions();
+    }
+
+    pub fn get_event_count(&self) -> u32 {
+        self.events.queued() as u32
+    }
+
+    /**
+     * Events dropped because the queue was full, oldest first.
+     */
+    pub fn get_dropped_event_count(&self) -> u32 {
+        self.events.dropped()
+    }
+
+    /**
+     * Takes every queued event, as a JSON array of objects with a `kind` field.
+     */
+    pub fn poll_events(&mut self) -> String {
+        serde_json::to_string(&self.events.drain()).unwrap_or_default()
+    }
+
+    /**
+     * Calls `callback` with each queued event object, oldest first, and returns how many
+     * it was called with. If the callback throws, the event it threw on and the rest stay
+     * queued, and the next call starts with that event again. The callback is
+     * passed in rather than stored, since JS functions aren't `Sync` and the orchestrator
+     * has to be for the parallel tick.
+     */
+    pub fn dispatch_events(&mut self, callback: &js_sys::Function) -> Result<u32, JsValue> {
+        let mut dispatched = 0;
+        while let Some(event) = self.events.pop() {
+            let delivered = serde_json::to_string(&event)
+                .map_err(|error| JsValue::from_str(&error.to_string()))
+                .and_then(|json| js_sys::JSON::parse(&json))
+                .and_then(|value| callback.call1(&JsValue::NULL, &value));
+            if let Err(error) = delivered {
+                self.events.requeue(event);
+                return Err(error);
+            }
+            dispatched += 1;
+        }
+        Ok(dispatched)
+    }
+
+    // Invariant checks, for debugging the simulation itself.
+    /**
+     * Checks the world after every tick and keeps what's wrong with it. Only debug builds
+     * run the check, release builds ignore this.
+     */
+    pub fn set_check_invariants(&mut self, enabled: bool) {
+        self.invariant_checks = enabled;
+        self.invariant_violations.clear();
+    }
+
+    pub fn get_check_invariants(&self) -> bool {
+        self.invariant_checks
+    }
+
+    /**
+     * What the last checked tick found wrong, as a JSON array. Empty when all was well.
+     */
+    pub fn get_invariant_violations(&self) -> String {
+        serde_json::to_string(&self.invariant_violations).unwrap_or_default()
+    }
+
+    // Spatial queries, for picking and inspecting boids. All of them return boid ids.
+    /**
+     * The boid closest to (x, y), if there's one within `radius`.
+     */
+    pub fn boid_at(&self, x: f32, y: f32, radius: f32) -> Option<u32> {
+        let point = Vector2::new(x, y);
+        let mut closest: Option<(f32, usize)> = None;
+        self.spatial_index
+            .for_each_in_radius(&self.boids, point, radius, |i| {
+                let distance = (self.boids[i].position - point).magnitude2();
+                if closest.is_none_or(|(best, _)| distance < best) {
+                    closest = Some((distance, i));
+                }
+            });
+        closest.map(|(_, i)| self.boids[i].id)
+    }
+
+    pub fn boids_in_rect(&self, min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Vec<u32> {
+        let mut ids = Vec::new();
+        self.spatial_index.for_each_in_rect(
+            &self.boids,
+            Vector2::new(min_x.min(max_x), min_y.min(max_y)),
+            Vector2::new(min_x.max(max_x), min_y.max(max_y)),
+            |i| ids.push(self.boids[i].id),
+        );
+        ids
+    }
+
+    /**
+     * The ids of the `k` boids closest to (x, y), nearest first.
+     */
+    pub fn k_nearest(&self, x: f32, y: f32, k: u32) -> Vec<u32> {
+        self.spatial_index
+            .k_nearest(&self.boids, Vector2::new(x, y), k as usize, None)
+            .into_iter()
+            .map(|i| self.boids[i].id)
+            .collect()
+    }
+
+    /**
+     * The ids of the boids the flocking rules currently consider neighbors of `boid_id`,
+     * using the same neighbor mode as the simulation.
+     */
+    pub fn neighbors_of(&self, boid_id: u32) -> Vec<u32> {
+        match self.boid_index(boid_id as usize) {
+            Some(i) => self
+                .neighbor_indices(i)
+                .into_iter()
+                .map(|i| self.boids[i].id)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Path and leaders
+    /**
+     * Sets the path leaders follow, from a flat [x0, y0, x1, y1, ...] array.
+     */
+    pub fn set_path(&mut self, points: &[f32], looping: bool) {
+        if !self.record(JournalEvent::SetPath {
+            points: points.to_vec(),
+            looping,
+        }) {
+            return;
+        }
+        // A path without length is no path, leaders go back to flocking.
+        self.path = BoidPath::from_flat(points, looping).unwrap_or_default();
+    }
+
+    pub fn clear_path(&mut self) {
+        self.record(JournalEvent::ClearPath);
+        self.path = BoidPath::default();
+    }
+
+    /**
+     * The path as a flat [x0, y0, x1, y1, ...] array, for the renderers.
+     */
+    pub fn get_path_points(&self) -> Vec<f32> {
+        self.path.to_flat()
+    }
+
+    pub fn is_path_looping(&self) -> bool {
+        self.path.is_looping()
+    }
+
+    pub fn promote_leader(&mut self, boid_id: u32) {
+        self.record(JournalEvent::PromoteLeader { boid_id });
+        if self.get_boid(boid_id as usize).is_some() {
+            self.leaders.insert(boid_id);
+        }
+    }
+
+    pub fn demote_leader(&mut self, boid_id: u32) {
+        self.record(JournalEvent::DemoteLeader { boid_id });
+        self.leaders.remove(&boid_id);
+    }
+
+    pub fn is_leader(&self, boid_id: u32) -> bool {
+        self.leaders.contains(&boid_id)
+    }
+
+    pub fn get_leader_ids(&self) -> Vec<u32> {
+        self.leaders.iter().copied().collect()
+    }
+
+    pub fn get_path_following_velocity_x(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_path_following_velocity(boid).x,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_path_following_velocity_y(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_path_following_velocity(boid).y,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_leader_following_velocity_x(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_leader_following_velocity(boid, &self.leader_states()).x,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_leader_following_velocity_y(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_leader_following_velocity(boid, &self.leader_states()).y,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_wander_velocity_x(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_wander_velocity(boid).x,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_wander_velocity_y(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_wander_velocity(boid).y,
+            None => 0.0,
+        }
+    }
+
+    // Flow field sources
+    pub fn set_flow_field_uniform(&mut self, wind_x: f32, wind_y: f32) {
+        if !self.record(JournalEvent::SetFlowFieldUniform { wind_x, wind_y }) {
+            return;
+        }
+        self.flow_field.set_source(FlowSource::Uniform {
+            wind: Vector2::new(wind_x, wind_y),
+        });
+    }
+
+    pub fn set_flow_field_vortex(&mut self, center_x: f32, center_y: f32, strength: f32, radius: f32) {
+        if !self.record(JournalEvent::SetFlowFieldVortex {
+            center_x,
+            center_y,
+            strength,
+            radius,
+        }) {
+            return;
+        }
+        self.flow_field.set_source(FlowSource::Vortex {
+            center: Vector2::new(center_x, center_y),
+            strength,
+            radius,
+        });
+    }
+
+    /**
+     * Pulls boids towards the point. Use a negative strength for a source.
+     */
+    pub fn set_flow_field_sink(&mut self, center_x: f32, center_y: f32, strength: f32, radius: f32) {
+        if !self.record(JournalEvent::SetFlowFieldSink {
+            center_x,
+            center_y,
+            strength,
+            radius,
+        }) {
+            return;
+        }
+        self.flow_field.set_source(FlowSource::Sink {
+            center: Vector2::new(center_x, center_y),
+            strength,
+            radius,
+        });
+    }
+
+    /**
+     * Procedural curl noise field that evolves over time. `scale` is the noise frequency
+     * per world unit, `speed` how fast it changes per unit of `dt`.
+     */
+    pub fn set_flow_field_curl_noise(&mut self, scale: f32, strength: f32, speed: f32, seed: u32) {
+        if !self.record(JournalEvent::SetFlowFieldCurlNoise {
+            scale,
+            strength,
+            speed,
+            seed,
+        }) {
+            return;
+        }
+        self.flow_field.set_source(FlowSource::CurlNoise {
+            noise: Box::new(Perlin::new(seed as u64)),
+            scale,
+            strength,
+            speed,
+        });
+    }
+
+    pub fn clear_flow_field(&mut self) {
+        self.record(JournalEvent::ClearFlowField);
+        self.flow_field.set_source(FlowSource::None);
+    }
+
+    pub fn set_flow_field_cell_size(&mut self, cell_size: f32) {
+        if !self.record(JournalEvent::SetFlowFieldCellSize { cell_size }) {
+            return;
+        }
+        self.flow_field.set_cell_size(
+            cell_size,
+            self.world_settings.world_width,
+            self.world_settings.world_height,
+        );
+    }
+
+    pub fn get_flow_field_cell_size(&self) -> f32 {
+        self.flow_field.cell_size()
+    }
+
+    pub fn get_flow_field_columns(&self) -> u32 {
+        self.flow_field.columns() as u32
+    }
+
+    pub fn get_flow_field_rows(&self) -> u32 {
+        self.flow_field.rows() as u32
+    }
+
+    /**
+     * Pointer to the flow vectors, as x, y pairs in row major order (columns * rows * 2 floats).
+     */
+    pub fn get_flow_field_ptr(&self) -> *const f32 {
+        self.flow_field.vectors().as_ptr()
+    }
+
+    pub fn get_flow_field_velocity_x(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_flow_field_velocity(boid).x,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_flow_field_velocity_y(&self, boid_id: usize) -> f32 {
+        match self.get_boid(boid_id) {
+            Some(boid) => self.get_flow_field_velocity(boid).y,
+            None => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for BoidOrchestrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.boids)?;
+        Ok(())
+    }
+}
+
+impl BoidOrchestrator {
+    /**
+     * Builds an orchestrator around an existing set of boids, without touching any of the
+     * JS random number sources. Used by the native tests.
+     */
+    pub fn from_boids(world_settings: WorldSettings, boids: Vec<Boid>, seed: u32) -> BoidOrchestrator {
+        let mut transfer_array = vec![0.0; boids.len() * Boid::NUM_ELEMENTS];
+        serialize_boids(&boids, &mut transfer_array);
+        let flow_field = FlowField::new(
+            world_settings.world_width,
+            world_settings.world_height,
+            FlowField::DEFAULT_CELL_SIZE,
+        );
+        let journal = Journal::new(seed, &world_settings, &boids);
+        let next_id = boids.iter().map(|boid| boid.id + 1).max().unwrap_or(0);
+        let mut orchestrator = BoidOrchestrator {
+            boids,
+            transfer_array,
+            world_settings,
+            flow_field,
+            seed,
+            rng: SimRng::new(seed),
+            wander_noise: Perlin::new(seed as u64 ^ WANDER_NOISE_SALT),
+            path: BoidPath::default(),
+            leaders: BTreeSet::new(),
+            obstacles: Vec::new(),
+            zones: Vec::new(),
+            mask: None,
+            formation: Formation::default(),
+            next_id,
+            ecosystem: Ecosystem::default(),
+            spatial_index: SpatialGrid::new(),
+            trails: TrailHistory::default(),
+            recorder: TrajectoryRecorder::default(),
+            events: EventMonitor::default(),
+            journal,
+            history: TickHistory::default(),
+            culled_view: CulledView::default(),
+            transfer_generation: BufferGeneration::default(),
+            traits_array: Vec::new(),
+            traits_generation: BufferGeneration::default(),
+            visible_generation: BufferGeneration::default(),
+            invariant_checks: false,
+            invariant_violations: Vec::new(),
+            tick_start_positions: HashMap::new(),
+            tick_leaders: Vec::new(),
+            tick_count: 0,
+            time: 0.0,
+        };
+        orchestrator.sync_traits_array();
+        orchestrator.rebuild_spatial_index();
+        orchestrator
+    }
+
+    pub fn boids(&self) -> &[Boid] {
+        &self.boids
+    }
+
+    pub fn traits_array(&self) -> &[f32] {
+        &self.traits_array
+    }
+
+    /**
+     * Checks the world right now, in any build.
+     */
+    pub fn check_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let violations = check_invariants(
+            &self.boids,
+            &self.transfer_array,
+            &self.traits_array,
+            &self.world_settings,
+            &self.zones,
+            &self.tick_start_positions,
+        );
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    pub fn drain_events(&mut self) -> Vec<SimEvent> {
+        self.events.drain()
+    }
+
+    pub fn invariant_violations(&self) -> &[InvariantViolation] {
+        &self.invariant_violations
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    pub fn from_journal(journal: &Journal) -> BoidOrchestrator {
+        let boids = journal.initial_boids.iter().map(Boid::from).collect();
+        let mut orchestrator =
+            BoidOrchestrator::from_boids(journal.settings.clone(), boids, journal.seed);
+        for event in &journal.events {
+            orchestrator.apply_journal_event(event);
+        }
+        orchestrator
+    }
+
+    /**
+     * Replays one journaled call. Goes through the public methods, so the rebuilt
+     * simulation ends up with the same journal as the original.
+     */
+    fn apply_journal_event(&mut self, event: &JournalEvent) {
+        match event.clone() {
+            JournalEvent::Tick { dt, count } => {
+                for _ in 0..count {
+                    self.tick(dt);
+                }
+            }
+            JournalEvent::AddBoid { count } => {
+                for _ in 0..count {
+                    self.add_boid();
+                }
+            }
+            JournalEvent::RemoveLastBoid => self.remove_last_boid(),
+            JournalEvent::SetWorldWidth { width } => self.set_world_width(width),
+            JournalEvent::SetWorldHeight { height } => self.set_world_height(height),
+            JournalEvent::SetAvoidanceRange { range } => self.set_avoidance_range(range),
+            JournalEvent::SetAvoidanceModifier { modifier } => self.set_avoidance_modifier(modifier),
+            JournalEvent::SetPCenterModifier { modifier } => self.set_p_center_modifier(modifier),
+            JournalEvent::SetVelocityMatchingModifier { modifier } => {
+                self.set_velocity_matching_modifier(modifier)
+            }
+            JournalEvent::SetBorderConstraintModifier { modifier } => {
+                self.set_border_constraint_modifier(modifier)
+            }
+            JournalEvent::SetFlowFieldModifier { modifier } => self.set_flow_field_modifier(modifier),
+            JournalEvent::SetWanderModifier { modifier } => self.set_wander_modifier(modifier),
+            JournalEvent::SetWanderFrequency { frequency } => self.set_wander_frequency(frequency),
+            JournalEvent::SetPathFollowingModifier { modifier } => {
+                self.set_path_following_modifier(modifier)
+            }
+            JournalEvent::SetLeaderFollowingModifier { modifier } => {
+                self.set_leader_following_modifier(modifier)
+            }
+            JournalEvent::SetFollowDistance { distance } => self.set_follow_distance(distance),
+            JournalEvent::SetArrivalRadius { radius } => self.set_arrival_radius(radius),
+            JournalEvent::SetNeighborMode { mode } => self.set_neighbor_mode(mode),
+            JournalEvent::SetPerceptionRange { range } => self.set_perception_range(range),
+            JournalEvent::SetKNearest { k } => self.set_k_nearest(k),
+            JournalEvent::SetCollisionsEnabled { enabled } => self.set_collisions_enabled(enabled),
+            JournalEvent::SetBoidRadius { radius } => self.set_boid_radius(radius),
+            JournalEvent::SetCollisionIterations { iterations } => {
+                self.set_collision_iterations(iterations)
+            }
+            JournalEvent::SetWallCollisions { walls } => self.set_wall_collisions(walls),
+            JournalEvent::SetBoidCollisionRadius { boid_id, radius } => {
+                self.set_boid_collision_radius(boid_id, radius)
+            }
+            JournalEvent::AddObstacle { x, y, radius } => {
+                self.add_obstacle(x, y, radius);
+            }
+            JournalEvent::RemoveObstacle { index } => self.remove_obstacle(index),
+            JournalEvent::ClearObstacles => self.clear_obstacles(),
+            JournalEvent::AddZoneRect {
+                name,
+                x,
+                y,
+                width,
+                height,
+                effect,
+                strength,
+            } => self.add_zone_rect(&name, x, y, width, height, effect, strength),
+            JournalEvent::AddZoneCircle {
+                name,
+                x,
+                y,
+                radius,
+                effect,
+                strength,
+            } => self.add_zone_circle(&name, x, y, radius, effect, strength),
+            JournalEvent::RemoveZone { name } => {
+                self.remove_zone(&name);
+            }
+            JournalEvent::ClearZones => self.clear_zones(),
+            JournalEvent::SetWorldMaskPolygon { points } => self.set_world_mask_polygon(&points),
+            JournalEvent::SetWorldMaskBitmap { width, height, pixels } => {
+                self.set_world_mask_bitmap(width, height, &pixels)
+            }
+            JournalEvent::ClearWorldMask => self.clear_world_mask(),
+            JournalEvent::SetFormationPoints { points } => self.set_formation_points(&points),
+            JournalEvent::SetFormationPolygon { points, outline } => self.set_formation_polygon(&points, outline),
+            JournalEvent::SetFormationBitmap { width, height, pixels } => {
+                self.set_formation_bitmap(width, height, &pixels)
+            }
+            JournalEvent::ReassignFormation => self.reassign_formation(),
+            JournalEvent::ClearFormation => self.clear_formation(),
+            JournalEvent::SetPath { points, looping } => self.set_path(&points, looping),
+            JournalEvent::ClearPath => self.clear_path(),
+            JournalEvent::PromoteLeader { boid_id } => self.promote_leader(boid_id),
+            JournalEvent::DemoteLeader { boid_id } => self.demote_leader(boid_id),
+            JournalEvent::SetFlowFieldUniform { wind_x, wind_y } => {
+                self.set_flow_field_uniform(wind_x, wind_y)
+            }
+            JournalEvent::SetFlowFieldVortex {
+                center_x,
+                center_y,
+                strength,
+                radius,
+            } => self.set_flow_field_vortex(center_x, center_y, strength, radius),
+            JournalEvent::SetFlowFieldSink {
+                center_x,
+                center_y,
+                strength,
+                radius,
+            } => self.set_flow_field_sink(center_x, center_y, strength, radius),
+            JournalEvent::SetFlowFieldCurlNoise {
+                scale,
+                strength,
+                speed,
+                seed,
+            } => self.set_flow_field_curl_noise(scale, strength, speed, seed),
+            JournalEvent::ClearFlowField => self.clear_flow_field(),
+            JournalEvent::SetFlowFieldCellSize { cell_size } => self.set_flow_field_cell_size(cell_size),
+            JournalEvent::Seek { tick } => {
+                self.seek(tick);
+            }
+            JournalEvent::SetHistoryLimits {
+                keyframe_interval,
+                max_bytes,
+            } => self.set_history_limits(keyframe_interval, max_bytes),
+            JournalEvent::SetEcosystemEnabled { enabled } => self.set_ecosystem_enabled(enabled),
+            JournalEvent::SetEnergyDrain {
+                base_drain,
+                speed_drain,
+            } => self.set_energy_drain(base_drain, speed_drain),
+            JournalEvent::SetEatRate { eat_rate } => self.set_eat_rate(eat_rate),
+            JournalEvent::SetReproductionEnergy { energy } => self.set_reproduction_energy(energy),
+            JournalEvent::SetMutation { mutation } => self.set_mutation(mutation),
+            JournalEvent::SetMaxPopulation { max_population } => self.set_max_population(max_population),
+            JournalEvent::AddFoodPatch {
+                x,
+                y,
+                radius,
+                capacity,
+                regrowth,
+            } => {
+                self.add_food_patch(x, y, radius, capacity, regrowth);
+            }
+            JournalEvent::ClearFoodPatches => self.clear_food_patches(),
+            JournalEvent::SetTraitSpread { spread } => self.set_trait_spread(spread),
+            JournalEvent::SetFormationBlend { blend } => self.set_formation_blend(blend),
+            JournalEvent::SetFormationModifier { modifier } => self.set_formation_modifier(modifier),
+            JournalEvent::SetFormationArrivalRadius { radius } => self.set_formation_arrival_radius(radius),
+            JournalEvent::ResizeWorld {
+                width,
+                height,
+                mode,
+            } => self.resize_world(width, height, mode),
+        }
+    }
+
+    pub fn transfer_array(&self) -> &[f32] {
+        &self.transfer_array
+    }
+
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn world_mask(&self) -> Option<&WorldMask> {
+        self.mask.as_deref()
+    }
+
+    fn set_mask(&mut self, source: MaskSource) {
+        self.mask = Some(Arc::new(WorldMask::new(
+            source,
+            self.world_settings.world_width,
+            self.world_settings.world_height,
+        )));
+    }
+
+    /**
+     * Rebakes the mask for the current world size, moving polygon points with `map`.
+     */
+    fn rebuild_mask(&mut self, map: impl Fn(Vector2<f32>) -> Vector2<f32>) {
+        let source = match self.mask.as_ref().map(|mask| mask.source()) {
+            Some(MaskSource::Polygon(points)) => MaskSource::Polygon(points.iter().map(|&point| map(point)).collect()),
+            Some(source) => source.clone(),
+            None => return,
+        };
+        self.set_mask(source);
+    }
+
+    pub fn formation(&self) -> &Formation {
+        &self.formation
+    }
+
+    fn set_formation(&mut self, targets: Vec<Vector2<f32>>) {
+        self.formation = Formation::new(targets);
+        self.formation.reassign(&self.boids);
+    }
+
+    fn put_zone(&mut self, mut zone: Zone) {
+        zones::update_counts(std::slice::from_mut(&mut zone), &self.boids);
+        match self.zones.iter_mut().find(|existing| existing.name == zone.name) {
+            Some(existing) => *existing = zone,
+            None => self.zones.push(zone),
+        }
+    }
+
+    pub fn world_settings(&self) -> &WorldSettings {
+        &self.world_settings
+    }
+
+    pub fn culled_view(&self) -> &CulledView {
+        &self.culled_view
+    }
+
+    pub fn trails(&self) -> &TrailHistory {
+        &self.trails
+    }
+
+    pub fn recorder(&self) -> &TrajectoryRecorder {
+        &self.recorder
+    }
+
+    /**
+     * Same as `tick`, but always evaluates the boids on the current thread, even when the
+     * `parallel` feature is on. The two paths must produce bit-identical results.
+     */
+    pub fn tick_single_threaded(&mut self, dt: f32) {
+        if !self.record(JournalEvent::Tick { dt, count: 1 }) {
+            return;
+        }
+        self.push_history(dt);
+        self.prepare_tick(dt);
+        let (next_boids, rules) = self.compute_next_boids(dt, true);
+        self.commit_tick(next_boids, rules, dt);
+    }
+
+    /**
+     * Journals a state changing call. Anything but a tick edits the world, which makes
+     * the history after the current tick stale. Calls with a non-finite number are
+     * refused and return false, the caller must then leave the world alone.
+     */
+    fn record(&mut self, event: JournalEvent) -> bool {
+        if !event.is_finite() {
+            return false;
+        }
+        match event {
+            JournalEvent::Tick { .. } | JournalEvent::Seek { .. } | JournalEvent::SetHistoryLimits { .. } => {}
+            _ => self.history.invalidate(self.tick_count),
+        }
+        self.journal.push(event);
+        true
+    }
+
+    /**
+     * Adds the tick about to run to the history, starting a new keyframe if needed.
+     */
+    fn push_history(&mut self, dt: f32) {
+        if !self.history.is_enabled() {
+            return;
+        }
+        // Ticking after stepping back starts a new future.
+        self.history.truncate(self.tick_count);
+        if self.history.wants_keyframe() {
+            let keyframe = self.keyframe();
+            self.history.push_keyframe(keyframe);
+        }
+        self.history.push_tick(dt);
+    }
+
+    fn keyframe(&self) -> Keyframe {
+        Keyframe {
+            tick_count: self.tick_count,
+            time: self.time,
+            boids: self.boids.clone(),
+            world_settings: self.world_settings.clone(),
+            flow_field: self.flow_field.clone(),
+            rng: self.rng.clone(),
+            path: self.path.clone(),
+            leaders: self.leaders.clone(),
+            obstacles: self.obstacles.clone(),
+            zones: self.zones.clone(),
+            mask: self.mask.clone(),
+            formation: self.formation.clone(),
+            next_id: self.next_id,
+            ecosystem: self.ecosystem.clone(),
+        }
+    }
+
+    fn restore_keyframe(&mut self, keyframe: Keyframe) {
+        self.tick_count = keyframe.tick_count;
+        self.time = keyframe.time;
+        self.boids = keyframe.boids;
+        self.world_settings = keyframe.world_settings;
+        self.flow_field = keyframe.flow_field;
+        self.rng = keyframe.rng;
+        self.path = keyframe.path;
+        self.leaders = keyframe.leaders;
+        self.obstacles = keyframe.obstacles;
+        self.zones = keyframe.zones;
+        self.mask = keyframe.mask;
+        self.formation = keyframe.formation;
+        self.tick_start_positions.clear();
+        self.next_id = keyframe.next_id;
+        self.ecosystem = keyframe.ecosystem;
+        self.transfer_array.resize(self.boids.len() * Boid::NUM_ELEMENTS, 0.0);
+        serialize_boids(&self.boids, &mut self.transfer_array);
+        self.rebuild_spatial_index();
+        self.update_culled_view();
+        self.sync_traits_array();
+        self.trails.clear();
+    }
+
+    /**
+     * The boids after this tick's rules, plus the rule vectors of the boids the recorder
+     * wants them for. Those come out of the same evaluation that moved the boids.
+     */
+    fn compute_next_boids(&self, dt: f32, single_threaded: bool) -> (Vec<Boid>, Vec<(u32, RuleVelocities)>) {
+        if !self.recorder.wants_rules(self.tick_count) {
+            let next_boids = self.map_boids(single_threaded, |index| {
+                self.apply_rules(index, &self.rules_with_leaders(index, &self.tick_leaders), dt)
+            });
+            return (next_boids, Vec::new());
+        }
+        let (next_boids, rules): (Vec<Boid>, Vec<Option<(u32, RuleVelocities)>>) = self
+            .map_boids(single_threaded, |index| {
+                let rules = self.rules_with_leaders(index, &self.tick_leaders);
+                let id = self.boids[index].id;
+                let recorded = self.recorder.wants_boid(id).then_some((id, rules));
+                (self.apply_rules(index, &rules, dt), recorded)
+            })
+            .into_iter()
+            .unzip();
+        (next_boids, rules.into_iter().flatten().collect())
+    }
+
+    #[cfg(parallel_tick)]
+    fn map_boids<T: Send>(&self, single_threaded: bool, f: impl Fn(usize) -> T + Sync + Send) -> Vec<T> {
+        if single_threaded {
+            return (0..self.boids.len()).map(f).collect();
+        }
+        (0..self.boids.len()).into_par_iter().map(f).collect()
+    }
+
+    #[cfg(not(parallel_tick))]
+    fn map_boids<T>(&self, _single_threaded: bool, f: impl Fn(usize) -> T) -> Vec<T> {
+        (0..self.boids.len()).map(f).collect()
+    }
+
+    /**
+     * Updates the shared world state the rules read from. Runs before any boid is evaluated.
+     */
+    fn prepare_tick(&mut self, dt: f32) {
+        self.time += dt;
+        self.tick_count += 1;
+        self.flow_field.advance(dt);
+        self.formation.sync(&self.boids);
+        self.tick_start_positions.clear();
+        if !self.zones.is_empty() {
+            self.tick_start_positions
+                .extend(self.boids.iter().map(|boid| (boid.id, boid.position)));
+        }
+        self.tick_leaders = self.leader_states();
+    }
+
+    /**
+     * Position and velocity of every leader, in the order the boids are stored.
+     */
+    fn leader_states(&self) -> Vec<(Vector2<f32>, Vector2<f32>)> {
+        if self.leaders.is_empty() {
+            return Vec::new();
+        }
+        self.boids
+            .iter()
+            .filter(|boid| self.leaders.contains(&boid.id))
+            .map(|boid| (boid.position, boid.velocity))
+            .collect()
+    }
+
+    fn commit_tick(&mut self, mut next_boids: Vec<Boid>, rules: Vec<(u32, RuleVelocities)>, dt: f32) {
+        // The spatial index is rebuilt below anyway, so the collision pass can borrow it.
+        resolve_collisions(
+            &mut next_boids,
+            &self.world_settings.collision,
+            &self.obstacles,
+            self.world_settings.world_width as f32,
+            self.world_settings.world_height as f32,
+            &mut self.spatial_index,
+        );
+        if self.world_settings.ecosystem.enabled
+            && self
+                .ecosystem
+                .step(
+                    &mut next_boids,
+                    &self.world_settings.ecosystem,
+                    dt,
+                    &mut self.rng,
+                    &mut self.next_id,
+                )
+        {
+            let alive: HashSet<u32> = next_boids.iter().map(|boid| boid.id).collect();
+            self.leaders.retain(|id| alive.contains(id));
+            self.transfer_array
+                .resize(next_boids.len() * Boid::NUM_ELEMENTS, 0.0);
+            self.boids = next_boids;
+            self.sync_traits_array();
+        } else {
+            self.boids = next_boids;
+        }
+        // Last, so neither collision impulses nor newborns end up inside a no-go zone.
+        for boid in self.boids.iter_mut() {
+            keep_out(&self.zones, boid);
+        }
+        serialize_boids(&self.boids, &mut self.transfer_array);
+        self.rebuild_spatial_index();
+        self.update_culled_view();
+        zones::update_counts(&mut self.zones, &self.boids);
+        if self.events.is_active() {
+            let world = self.world_size();
+            self.events
+                .observe(self.tick_count, &self.boids, &self.spatial_index, world, true);
+        }
+        self.trails.record(&self.boids);
+        self.recorder.record(self.tick_count, &self.boids, rules);
+        if cfg!(debug_assertions) && self.invariant_checks {
+            self.invariant_violations = check_invariants(
+                &self.boids,
+                &self.transfer_array,
+                &self.traits_array,
+                &self.world_settings,
+                &self.zones,
+                &self.tick_start_positions,
+            );
+        }
+    }
+
+    /**
+     * Traits only change when boids are born, added or removed, so the traits array is
+     * rewritten then rather than every tick.
+     */
+    fn sync_traits_array(&mut self) {
+        self.traits_array
+            .resize(self.boids.len() * BoidTraits::NUM_ELEMENTS, 0.0);
+        serialize_traits(&self.boids, &mut self.traits_array);
+    }
+
+    fn in_world_rect(&self, position: Vector2<f32>) -> bool {
+        position.x >= 0.0
+            && position.y >= 0.0
+            && position.x <= self.world_settings.world_width as f32
+            && position.y <= self.world_settings.world_height as f32
+    }
+
+    fn world_size(&self) -> Vector2<f32> {
+        Vector2::new(
+            self.world_settings.world_width as f32,
+            self.world_settings.world_height as f32,
+        )
+    }
+
+    fn update_culled_view(&mut self) {
+        self.culled_view.update(&self.boids, &self.transfer_array);
+    }
+
+    fn rebuild_spatial_index(&mut self) {
+        let cell_size = self
+            .world_settings
+            .neighbors
+            .perception_range
+            .max(self.world_settings.avoidance.avoidance_range);
+        self.spatial_index.build(&self.boids, cell_size);
+    }
+
+    /**
+     * Indices (into `boids`) of the boids the flocking rules consider for the boid at
+     * `index`, picked according to the neighbor mode in the world settings.
+     */
+    fn neighbor_indices(&self, index: usize) -> Vec<usize> {
+        let settings = &self.world_settings.neighbors;
+        let boid = &self.boids[index];
+        match settings.mode {
+            NeighborMode::All => (0..self.boids.len()).filter(|&i| i != index).collect(),
+            NeighborMode::Metric => {
+                let mut neighbors = Vec::new();
+                self.spatial_index.for_each_in_radius(
+                    &self.boids,
+                    boid.position,
+                    settings.perception_range * boid.traits.perception,
+                    |i| {
+                        if i != index {
+                            neighbors.push(i);
+                        }
+                    },
+                );
+                neighbors
+            }
+            NeighborMode::KNearest => {
+                self.spatial_index
+                    .k_nearest(&self.boids, boid.position, settings.k as usize, Some(index))
+            }
+        }
+    }
+
+    /**
+     * Ids aren't indices once boids can die, and aren't reused either: trails, events,
+     * the recorder and formations all track boids by id.
+     */
+    fn next_boid_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn get_boid(&self, boid_id: usize) -> Option<&Boid> {
+        self.boids
+            .iter()
+            .find(|boid| boid.id == boid_id as u32)
+    }
+
+    fn boid_index(&self, boid_id: usize) -> Option<usize> {
+        self.boids.iter().position(|boid| boid.id == boid_id as u32)
+    }
+    
+    /**
+     * Times the neighbor lookup and each rule on its own over the whole flock, in
+     * seconds, for the native benchmark. `clock` returns the current time in seconds;
+     * it's passed in since `std::time` doesn't work on wasm. Rules are unweighted here,
+     * so a rule with a zero modifier still shows up.
+     */
+    pub fn profile_rules(&self, clock: impl Fn() -> f64) -> Vec<(&'static str, f64)> {
+        let start = clock();
+        let neighbors: Vec<Vec<usize>> = (0..self.boids.len()).map(|i| self.neighbor_indices(i)).collect();
+        let neighbor_time = clock() - start;
+        let leaders = self.leader_states();
+
+        let time = |rule: &dyn Fn(&Boid, &[usize]) -> Vector2<f32>| {
+            let start = clock();
+            for (boid, neighbors) in self.boids.iter().zip(&neighbors) {
+                std::hint::black_box(rule(boid, neighbors));
+            }
+            clock() - start
+        };
+        let [cohesion, separation, alignment, border, flow_field, wander, path, formation] = RuleVelocities::NAMES;
+        vec![
+            ("neighbors", neighbor_time),
+            (cohesion, time(&|boid, neighbors| self.get_velocity_to_perceived_center(boid, neighbors))),
+            (separation, time(&|boid, neighbors| self.get_avoidance_velocity(boid, neighbors))),
+            (alignment, time(&|boid, neighbors| self.get_match_percived_velocity(boid, neighbors))),
+            (border, time(&|boid, _| self.get_border_velocity(boid))),
+            (flow_field, time(&|boid, _| self.get_flow_field_velocity(boid))),
+            (wander, time(&|boid, _| self.get_wander_velocity(boid))),
+            (
+                path,
+                time(&|boid, _| {
+                    self.get_path_following_velocity(boid) + self.get_leader_following_velocity(boid, &leaders)
+                }),
+            ),
+            (formation, time(&|boid, _| self.get_formation_velocity(boid))),
+        ]
+    }
+
+    /**
+     * Every rule's weighted contribution to the velocity of the boid at `index` this tick.
+     */
+    pub fn rule_velocities(&self, index: usize) -> RuleVelocities {
+        self.rules_with_leaders(index, &self.leader_states())
+    }
+
+    /**
+     * `rule_velocities` with the leaders gathered up front, so a tick only collects them
+     * once instead of once per boid.
+     */
+    fn rules_with_leaders(&self, index: usize, leaders: &[(Vector2<f32>, Vector2<f32>)]) -> RuleVelocities {
+        let boid = &self.boids[index];
+        let neighbors = self.neighbor_indices(index);
+        let traits = &boid.traits;
+        // A boid flying into formation lets go of the flock as the blend goes up.
+        // Separation and the border stay, so boids don't pile up or leave the world.
+        let blend = match self.formation.target(boid.id) {
+            Some(_) => self.world_settings.formation.blend,
+            None => 0.0,
+        };
+        let free = 1.0 - blend;
+        RuleVelocities {
+            cohesion: self.get_velocity_to_perceived_center(boid, &neighbors)
+                * (self.world_settings.pc.p_center_modifier * traits.cohesion * free),
+            separation: self.get_avoidance_velocity(boid, &neighbors)
+                * (self.world_settings.avoidance.avoidance_modifier * traits.separation),
+            alignment: self.get_match_percived_velocity(boid, &neighbors)
+                * (self
+                    .world_settings
+                    .velocity_matching
+                    .velocity_matching_modifier
+                    * traits.alignment
+                    * free),
+            border: self.get_border_velocity(boid)
+                * (self
+                    .world_settings
+                    .border_constraint
+                    .border_constraint_modifier
+                    * traits.border),
+            flow_field: self.get_flow_field_velocity(boid)
+                * (self.world_settings.flow_field.flow_field_modifier * free),
+            wander: self.get_wander_velocity(boid) * (self.world_settings.wander.wander_modifier * free),
+            path: (self.get_path_following_velocity(boid)
+                * self.world_settings.path.path_following_modifier
+                + self.get_leader_following_velocity(boid, leaders)
+                    * self.world_settings.path.leader_following_modifier)
+                * free,
+            formation: self.get_formation_velocity(boid) * (self.world_settings.formation.formation_modifier * blend),
+        }
+    }
+
+    fn apply_rules(&self, index: usize, rules: &RuleVelocities, dt: f32) -> Boid {
+        let boid = &self.boids[index];
+        // log("Id: ");
+        // log(&boid.id.to_string());
+        // log("oldVel:");
+        // log(&boid.velocity.x.to_string());
+        // log(&boid.velocity.y.to_string());
+        // add them to the old vel to get the new vel.
+        let mut new_velocity = rules.apply_to(boid.velocity);
+
+        let vel_limit: f32 =
+            speed_limit(&self.zones, boid.position, self.world_settings.velocity_limit) * boid.traits.max_speed; //25.0
+        if new_velocity.magnitude() > (vel_limit) {
+            new_velocity = new_velocity.normalize_to(vel_limit)
+        }
+
+        // log("newVel: ");
+        // log(&new_velocity.x.to_string());
+        // log(&new_velocity.y.to_string());
+        // create the new position, with the dt
+        let new_position = Vector2 {
+            x: boid.position.x + (new_velocity.x * dt),
+            y: boid.position.y + (new_velocity.y * dt),
+        };
+        // log("oldPosX");
+        // log(&boid.position.x.to_string());
+        // boid.position = new_position;
+        // log("setNewPos:");
+        // log(&new_position.x.to_string());
+        // boid.position.x = boid.position.x + (new_velocity.x * dt);
+        // boid.position.y = boid.position.y + (new_velocity.y * dt);
+        // boid.velocity = new_velocity;
+        // boid.velocity.x = new_velocity.x;
+        // boid.velocity.y = new_velocity.y;
+
+        // log(format!("boid: {:?}", boid).as_ref());
+        Boid {
+            velocity: new_velocity,
+            position: new_position,
+            ..*boid
+        }
+    }
+
+    /**
+     * Rule 1. The boid is attracted to the percived center of its neighbors.
+     * get average boid position, then get a vector from the boid pos to that.  
+     */
+    fn get_velocity_to_perceived_center(&self, boid: &Boid, neighbors: &[usize]) -> Vector2<f32> {
+        if neighbors.is_empty() {
+            return Vector2::new(0.0, 0.0);
+        }
+        let sum_of_positions: Vector2<f32> = neighbors
+            .iter()
+            .map(|&i| &self.boids[i])
+            .fold(Vector2 { x: 0.0, y: 0.0 }, |acc, other_boid| {
+                acc + other_boid.position
+            });
+
+        // log(format!("sum_of_positions: {:?}", sum_of_positions).as_ref());
+        let center = sum_of_positions / neighbors.len() as f32;
+        // log(format!("center: {:?}", center).as_ref());
+        // log(format!("boid position: {:?}", boid.position).as_ref());
+        (center - boid.position) / 100.0
+    }
+
+    /**
+     * Rule 2. Boids want to avoid each other.  
+     * get all boids within a min distance, then get a vec between the boid and them
+     */
+    fn get_avoidance_velocity(&self, boid: &Boid, neighbors: &[usize]) -> Vector2<f32> {
+        let sum_of_avoidance_vector: Vector2<f32> = neighbors
+            .iter()
+            .map(|&i| &self.boids[i])
+            // only boids less than avoidance_distance TODO: if also within sightline.
+            // Boids right on top of each other have no direction to push in, skip them
+            // rather than divide by zero.
+            .filter(|other_boids| {
+                let distance = (other_boids.position - boid.position).magnitude();
+                distance > f32::EPSILON
+                    && distance < self.world_settings.avoidance.avoidance_range * boid.traits.separation_distance
+            })
+            .fold(Vector2::<f32> { x: 0.0, y: 0.0 }, |acc, other_boid| {
+                // acc - (other_boid.position - boid.position)
+                
+                let diff = other_boid.position - boid.position;
+                let distance = diff.magnitude();
+                // Weight by inverse distance (closer boids have more influence)
+                let accu=  diff * (4.0 / distance);
+                // if other boid is almost on top of this one (within 0.1), then move away from it
+                // didn't work super well, was jittery
+                // if distance < 0.1 {
+                //     return acc - diff;
+                // }
+                acc - accu
+            });
+        sum_of_avoidance_vector
+    }
+    
+    fn get_match_percived_velocity(&self, boid: &Boid, neighbors: &[usize]) -> Vector2<f32> {
+        if neighbors.is_empty() {
+            return Vector2::new(0.0, 0.0);
+        }
+        let sum_of_velocity: Vector2<f32> = neighbors
+            .iter()
+            .map(|&i| &self.boids[i])
+            .fold(Vector2 { x: 0.0, y: 0.0 }, |acc, other_boid| {
+                acc + other_boid.velocity
+            });
+
+        // log(format!("sum_of_velocity: {:?}", sum_of_velocity).as_ref());
+        let center = sum_of_velocity / neighbors.len() as f32;
+        // log(format!("center: {:?}", center).as_ref());
+        // log(format!("boid vel: {:?}", boid.velocity).as_ref());
+        (center - boid.velocity) / 8.0
+    }
+
+    fn get_border_velocity(&self, boid: &Boid) -> Vector2<f32> {
+        // Inside the world rect a mask takes over, pushing boids straight in along its
+        // distance gradient. The push starts at `EDGE_MARGIN` from the edge and is at full
+        // strength on the edge and beyond.
+        if let Some(mask) = self.mask.as_ref().filter(|_| self.in_world_rect(boid.position)) {
+            let distance = mask.distance(boid.position);
+            if distance >= WorldMask::EDGE_MARGIN {
+                return Vector2::new(0.0, 0.0);
+            }
+            let push = if distance <= 0.0 {
+                1.0
+            } else {
+                1.0 - distance / WorldMask::EDGE_MARGIN
+            };
+            return mask.inward(boid.position) * (10.0 * push);
+        }
+        let mut border_velocity_vec: Vector2<f32> = Vector2::new(0.0, 0.0);
+        if boid.position.x < 0.0 {
+            border_velocity_vec.x = 10.0;
+        } else if boid.position.x > self.world_settings.world_width as f32 {
+            border_velocity_vec.x = -10.0;
+        }
+        if boid.position.y < 0.0 {
+            border_velocity_vec.y = 10.0;
+        } else if boid.position.y > self.world_settings.world_height as f32 {
+            border_velocity_vec.y = -10.0;
+        }
+        border_velocity_vec
+    }
+
+    /**
+     * Rule 5. Boids get carried along by the flow field (wind, currents).
+     */
+    fn get_flow_field_velocity(&self, boid: &Boid) -> Vector2<f32> {
+        self.flow_field.sample(boid.position)
+    }
+
+    /**
+     * Rule 6. Boids wander a little on their own, so small flocks don't settle into
+     * straight lines. The heading comes from coherent noise sampled along each boid's own
+     * track through time, so it turns smoothly and only depends on the seed. x and y come
+     * from two separate slices of the noise: Perlin values bunch up around 0, so using one
+     * of them as an angle would keep every boid drifting towards angle 0. The slices sit
+     * at whole numbers, where the noise averages out to zero.
+     */
+    fn get_wander_velocity(&self, boid: &Boid) -> Vector2<f32> {
+        let track = boid.id as f32 * 7.31 + 0.5;
+        let t = self.time * self.world_settings.wander.wander_frequency;
+        let heading = Vector2::new(
+            self.wander_noise.get(track, t, 0.0),
+            self.wander_noise.get(track, t, 100.0),
+        );
+        if heading.magnitude2() > f32::EPSILON {
+            heading.normalize()
+        } else {
+            Vector2::new(0.0, 0.0)
+        }
+    }
+
+    /**
+     * Rule 7. Leaders steer towards a point a little further along the path than the
+     * closest one, arriving gently at the end of an open path. Everyone else gets nothing.
+     */
+    fn get_path_following_velocity(&self, boid: &Boid) -> Vector2<f32> {
+        if !self.is_leader(boid.id) {
+            return Vector2::new(0.0, 0.0);
+        }
+        let look_ahead = self.world_settings.velocity_limit * 2.0;
+        match self.path.target_ahead(boid.position, look_ahead) {
+            Some((target, at_end)) => {
+                let slowing_radius = if at_end {
+                    self.world_settings.path.arrival_radius
+                } else {
+                    0.0
+                };
+                arrive(
+                    boid.position,
+                    boid.velocity,
+                    target,
+                    self.world_settings.velocity_limit,
+                    slowing_radius,
+                )
+            }
+            None => Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /**
+     * Rule 8. Ordinary boids arrive at a spot behind the closest leader, slowing down as
+     * they get close so they don't overshoot it.
+     */
+    fn get_leader_following_velocity(&self, boid: &Boid, leaders: &[(Vector2<f32>, Vector2<f32>)]) -> Vector2<f32> {
+        if leaders.is_empty() || self.is_leader(boid.id) {
+            return Vector2::new(0.0, 0.0);
+        }
+        let closest_leader = leaders.iter().min_by(|(a, _), (b, _)| {
+            let a = (a - boid.position).magnitude2();
+            let b = (b - boid.position).magnitude2();
+            a.total_cmp(&b)
+        });
+        match closest_leader {
+            Some(&(position, velocity)) => {
+                let heading = if velocity.magnitude2() > f32::EPSILON {
+                    velocity.normalize()
+                } else {
+                    Vector2::new(0.0, 0.0)
+                };
+                let behind = position - heading * self.world_settings.path.follow_distance;
+                arrive(
+                    boid.position,
+                    boid.velocity,
+                    behind,
+                    self.world_settings.velocity_limit,
+                    self.world_settings.path.arrival_radius,
+                )
+            }
+            None => Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /**
+     * Rule 9. Boids in a formation arrive at their own target point, slowing down as
+     * they get close so they settle on it instead of circling.
+     */
+    fn get_formation_velocity(&self, boid: &Boid) -> Vector2<f32> {
+        match self.formation.target(boid.id) {
+            Some(target) => arrive(
+                boid.position,
+                boid.velocity,
+                target,
+                self.world_settings.velocity_limit * boid.traits.max_speed,
+                self.world_settings.formation.arrival_radius,
+            ),
+            None => Vector2::new(0.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::settings::{test_settings, FormationSettings};
+
+    fn test_flock(num_boids: u32) -> Vec<Boid> {
+        // Small LCG so the test doesn't depend on the JS random source.
+        let mut state: u32 = 12345;
+        let mut next = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..num_boids)
+            .map(|id| {
+                Boid::new(
+                    Vector2::new(next() * 800.0, next() * 400.0),
+                    Vector2::new(next() * 10.0 - 5.0, next() * 10.0 - 5.0),
+                    id,
+                )
+            })
+            .collect()
+    }
+
+    fn test_orchestrator(boids: Vec<Boid>) -> BoidOrchestrator {
+        let settings = test_settings(800, 400);
+        BoidOrchestrator::from_boids(settings, boids, 1)
+    }
+
+    fn assert_bit_identical(a: &[Boid], b: &[Boid]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.position.x.to_bits(), b.position.x.to_bits());
+            assert_eq!(a.position.y.to_bits(), b.position.y.to_bits());
+            assert_eq!(a.velocity.x.to_bits(), b.velocity.x.to_bits());
+            assert_eq!(a.velocity.y.to_bits(), b.velocity.y.to_bits());
+        }
+    }
+
+    // Without the parallel tick both sides would run the same serial code.
+    #[cfg(parallel_tick)]
+    #[test]
+    fn tick_matches_single_threaded_tick() {
+        let mut threaded = test_orchestrator(test_flock(200));
+        let mut single = test_orchestrator(test_flock(200));
+        // Wander, curl noise and collisions read the shared noise and RNG state.
+        for orchestrator in [&mut threaded, &mut single] {
+            orchestrator.set_wander_modifier(1.5);
+            orchestrator.set_flow_field_curl_noise(0.01, 2.0, 0.5, 4);
+            orchestrator.set_collisions_enabled(true);
+        }
+        for _ in 0..50 {
+            threaded.tick(1.0 / 8.0);
+            single.tick_single_threaded(1.0 / 8.0);
+        }
+        assert_bit_identical(threaded.boids(), single.boids());
+        assert_eq!(threaded.transfer_array(), single.transfer_array());
+    }
+
+    #[test]
+    fn wander_is_deterministic_for_a_seed() {
+        let run = |seed: u32| {
+            let mut settings = test_settings(800, 400);
+            settings.set_wander_modifier(2.0);
+            let mut orchestrator = BoidOrchestrator::with_seed(settings, 30, seed);
+            for _ in 0..40 {
+                orchestrator.tick(1.0 / 8.0);
+            }
+            orchestrator.boids().to_vec()
+        };
+        assert_bit_identical(&run(42), &run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn leaders_follow_the_path() {
+        let mut orchestrator = test_orchestrator(test_flock(20));
+        orchestrator.set_path(&[100.0, 200.0, 700.0, 200.0], false);
+        orchestrator.set_path_following_modifier(1.0);
+        orchestrator.promote_leader(7);
+        orchestrator.promote_leader(0);
+        orchestrator.promote_leader(0);
+        assert_eq!(orchestrator.get_leader_ids(), vec![0, 7]);
+        orchestrator.demote_leader(7);
+        assert_eq!(orchestrator.get_leader_ids(), vec![0]);
+
+        for _ in 0..800 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+        let leader = orchestrator.boids()[0];
+        assert!((leader.position - Vector2::new(700.0, 200.0)).magnitude() < 60.0);
+
+        orchestrator.demote_leader(0);
+        assert!(!orchestrator.is_leader(0));
+    }
+
+    #[test]
+    fn wander_has_no_preferred_direction() {
+        let mut orchestrator = test_orchestrator(test_flock(200));
+        let mut sum = Vector2::new(0.0, 0.0);
+        let mut samples = 0;
+        for step in 0..400 {
+            orchestrator.time = step as f32 * 0.37;
+            for boid in orchestrator.boids() {
+                sum += orchestrator.get_wander_velocity(boid);
+                samples += 1;
+            }
+        }
+        let mean = sum / samples as f32;
+        assert!(mean.magnitude() < 0.02, "mean wander {:?}", mean);
+    }
+
+    #[test]
+    fn neighbor_modes_pick_the_expected_boids() {
+        let boids = test_flock(60);
+        let mut orchestrator = test_orchestrator(boids.clone());
+        let boid = boids[10];
+        assert_eq!(orchestrator.neighbor_indices(10).len(), 59);
+
+        orchestrator.set_neighbor_mode(NeighborMode::Metric);
+        orchestrator.set_perception_range(120.0);
+        let mut metric = orchestrator.neighbor_indices(10);
+        metric.sort();
+        let expected: Vec<usize> = (0..boids.len())
+            .filter(|&i| i != 10 && (boids[i].position - boid.position).magnitude() <= 120.0)
+            .collect();
+        assert_eq!(metric, expected);
+
+        orchestrator.set_neighbor_mode(NeighborMode::KNearest);
+        orchestrator.set_k_nearest(7);
+        let nearest = orchestrator.neighbor_indices(10);
+        assert_eq!(nearest.len(), 7);
+        assert!(!nearest.contains(&10));
+        let farthest_kept = (boids[nearest[6]].position - boid.position).magnitude();
+        let closer_than_farthest = (0..boids.len())
+            .filter(|&i| i != 10 && (boids[i].position - boid.position).magnitude() < farthest_kept)
+            .count();
+        assert!(closer_than_farthest <= 6);
+
+        for _ in 0..10 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+    }
+
+    #[test]
+    fn spatial_queries_return_boid_ids() {
+        let boids = vec![
+            Boid::new(Vector2::new(10.0, 10.0), Vector2::new(0.0, 0.0), 5),
+            Boid::new(Vector2::new(14.0, 10.0), Vector2::new(0.0, 0.0), 6),
+            Boid::new(Vector2::new(300.0, 200.0), Vector2::new(0.0, 0.0), 7),
+            Boid::new(Vector2::new(600.0, 50.0), Vector2::new(0.0, 0.0), 8),
+        ];
+        let orchestrator = test_orchestrator(boids);
+
+        assert_eq!(orchestrator.boid_at(13.0, 11.0, 5.0), Some(6));
+        assert_eq!(orchestrator.boid_at(100.0, 100.0, 5.0), None);
+        let mut in_rect = orchestrator.boids_in_rect(400.0, 300.0, 0.0, 0.0);
+        in_rect.sort();
+        assert_eq!(in_rect, vec![5, 6, 7]);
+        assert_eq!(orchestrator.k_nearest(590.0, 60.0, 2), vec![8, 7]);
+        assert_eq!(orchestrator.neighbors_of(7), vec![5, 6, 8]);
+        assert!(orchestrator.neighbors_of(42).is_empty());
+    }
+
+    #[test]
+    fn collisions_keep_boids_apart_and_in_the_world() {
+        let mut orchestrator = test_orchestrator(test_flock(80));
+        orchestrator.set_collisions_enabled(true);
+        orchestrator.set_wall_collisions(true);
+        orchestrator.set_boid_radius(6.0);
+        orchestrator.set_collision_iterations(8);
+        orchestrator.add_obstacle(400.0, 200.0, 50.0);
+        for _ in 0..100 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+        let boids = orchestrator.boids();
+        for boid in boids {
+            assert!(boid.position.x >= 6.0 - 1e-3 && boid.position.x <= 794.0 + 1e-3);
+            assert!(boid.position.y >= 6.0 - 1e-3 && boid.position.y <= 394.0 + 1e-3);
+            assert!((boid.position - Vector2::new(400.0, 200.0)).magnitude() >= 56.0 - 1e-3);
+        }
+        let worst_overlap = boids
+            .iter()
+            .flat_map(|a| boids.iter().filter(move |b| b.id > a.id).map(move |b| 12.0 - (a.position - b.position).magnitude()))
+            .fold(0.0, f32::max);
+        assert!(worst_overlap < 3.0, "worst overlap {}", worst_overlap);
+    }
+
+    #[test]
+    fn replaying_a_journal_reproduces_the_simulation() {
+        let settings = test_settings(800, 400);
+        let mut original = BoidOrchestrator::with_seed(settings, 25, 99);
+        for _ in 0..10 {
+            original.tick(1.0 / 8.0);
+        }
+        original.set_wander_modifier(1.5);
+        original.add_boid();
+        original.add_boid();
+        original.set_neighbor_mode(NeighborMode::KNearest);
+        original.set_flow_field_curl_noise(0.01, 2.0, 0.5, 4);
+        original.add_obstacle(200.0, 200.0, 30.0);
+        original.set_collisions_enabled(true);
+        for _ in 0..5 {
+            original.tick(1.0 / 16.0);
+        }
+        original.remove_last_boid();
+        // Boid 26 is gone, the next one gets a fresh id instead of its old one.
+        original.add_boid();
+        assert_eq!(original.boids().last().unwrap().id, 27);
+        original.set_world_width(600);
+        for _ in 0..10 {
+            original.tick(1.0 / 8.0);
+        }
+        // NaN and infinity would be exported as null and not parse back, so they're refused.
+        let ticks = original.get_tick_count();
+        original.set_wander_modifier(f32::NAN);
+        original.tick(f32::INFINITY);
+        assert_eq!(original.add_obstacle(f32::NAN, 0.0, 10.0), None);
+        assert_eq!(original.get_wander_modifier(), 1.5);
+        assert_eq!(original.get_tick_count(), ticks);
+        assert_eq!(original.get_obstacle_count(), 1);
+
+        let json = original.export_journal();
+        let journal = Journal::from_json(&json).unwrap();
+        assert_eq!(&journal, original.journal());
+        assert_eq!(journal.events[0], JournalEvent::AddBoid { count: 25 });
+        assert_eq!(journal.events[1], JournalEvent::Tick { dt: 0.125, count: 10 });
+
+        let replayed = BoidOrchestrator::from_journal(&journal);
+        assert_bit_identical(replayed.boids(), original.boids());
+        assert_eq!(replayed.transfer_array(), original.transfer_array());
+        assert_eq!(replayed.journal(), original.journal());
+        assert!(Journal::from_json("{not a journal").is_err());
+    }
+
+    #[test]
+    fn stepping_back_restores_earlier_ticks() {
+        let settings = test_settings(800, 400);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 30, 5);
+        orchestrator.set_history_limits(8, u32::MAX);
+        orchestrator.set_wander_modifier(1.0);
+        let mut states = vec![orchestrator.boids().to_vec()];
+        for tick in 0..40 {
+            if tick == 20 {
+                orchestrator.add_obstacle(400.0, 200.0, 50.0);
+                orchestrator.set_collisions_enabled(true);
+            }
+            orchestrator.tick(1.0 / 8.0);
+            states.push(orchestrator.boids().to_vec());
+        }
+
+        assert!(orchestrator.step_back());
+        assert_eq!(orchestrator.get_tick_count(), 39);
+        assert_bit_identical(orchestrator.boids(), &states[39]);
+        for tick in [3, 20, 21, 0, 40, 17] {
+            assert!(orchestrator.seek(tick));
+            assert_eq!(orchestrator.get_tick_count(), tick);
+            assert_bit_identical(orchestrator.boids(), &states[tick as usize]);
+        }
+        assert!(orchestrator.step_forward());
+        assert_bit_identical(orchestrator.boids(), &states[18]);
+        assert!(!orchestrator.seek(41));
+
+        // Ticking from the past replaces the old future.
+        orchestrator.tick(1.0 / 8.0);
+        assert_eq!(orchestrator.get_history_end_tick(), 19);
+        assert!(!orchestrator.seek(25));
+
+        let replayed = BoidOrchestrator::from_journal(orchestrator.journal());
+        assert_bit_identical(replayed.boids(), orchestrator.boids());
+    }
+
+    #[test]
+    fn events_fire_as_boids_move_but_not_on_seek() {
+        let boid = Boid::new(Vector2::new(50.0, 200.0), Vector2::new(10.0, 0.0), 0);
+        let mut orchestrator = test_orchestrator(vec![boid]);
+        // From the bottom right corner, so the size is negative.
+        let region = orchestrator.add_event_region_rect(200.0, 300.0, -100.0, -200.0);
+        for _ in 0..8 {
+            orchestrator.tick(1.0);
+        }
+        let entered = match orchestrator.drain_events().as_slice() {
+            [SimEvent::EnteredRegion { tick, id: 0, region: r }] if *r == region => *tick,
+            other => panic!("unexpected events {:?}", other),
+        };
+        assert!(entered > 4);
+
+        assert!(orchestrator.seek(2));
+        assert!(orchestrator.seek(8));
+        assert_eq!(orchestrator.get_event_count(), 0);
+        assert!(orchestrator.seek(2));
+        for _ in 2..8 {
+            orchestrator.tick(1.0);
+        }
+        assert_eq!(
+            orchestrator.drain_events(),
+            vec![SimEvent::EnteredRegion { tick: entered, id: 0, region }]
+        );
+    }
+
+    #[test]
+    fn zones_count_slow_and_keep_out_boids() {
+        let mut orchestrator = test_orchestrator(test_flock(60));
+        orchestrator.add_zone_rect("wall", 300.0, 100.0, 200.0, 200.0, ZoneEffect::NoGo, 0.0);
+        orchestrator.add_zone_circle("mud", 150.0, 200.0, 120.0, ZoneEffect::SlowDown, 0.2);
+        orchestrator.add_zone_rect("all", 0.0, 0.0, 800.0, 400.0, ZoneEffect::Counter, 0.0);
+        orchestrator.set_check_invariants(true);
+        for _ in 0..30 {
+            orchestrator.tick(1.0 / 8.0);
+            assert_eq!(orchestrator.invariant_violations(), &[]);
+            for boid in orchestrator.boids() {
+                let (x, y) = (boid.position.x, boid.position.y);
+                assert!(!(x > 300.0 && x < 500.0 && y > 100.0 && y < 300.0), "{:?} in the wall", boid);
+            }
+        }
+        // Boids that started the tick in the mud are held to 0.2 * 15.
+        let in_mud: Vec<u32> = orchestrator
+            .boids()
+            .iter()
+            .filter(|boid| (boid.position - Vector2::new(150.0, 200.0)).magnitude() < 120.0)
+            .map(|boid| boid.id)
+            .collect();
+        assert!(!in_mud.is_empty());
+        orchestrator.tick(1.0 / 8.0);
+        for id in in_mud {
+            let boid = orchestrator.get_boid(id as usize).unwrap();
+            assert!(boid.velocity.magnitude() <= 3.0 + 1e-4, "{:?} too fast", boid);
+        }
+        let expected = orchestrator
+            .boids()
+            .iter()
+            .filter(|boid| (0.0..=800.0).contains(&boid.position.x) && (0.0..=400.0).contains(&boid.position.y))
+            .count() as u32;
+        assert_eq!(orchestrator.get_zone_boid_count("all"), expected);
+        assert!(orchestrator.get_zones().contains(r#""name":"mud""#));
+
+        let replayed = BoidOrchestrator::from_journal(orchestrator.journal());
+        assert_bit_identical(replayed.boids(), orchestrator.boids());
+        assert_eq!(replayed.zones(), orchestrator.zones());
+        assert!(orchestrator.remove_zone("mud"));
+        assert_eq!(orchestrator.get_zone_count(), 2);
+    }
+
+    #[test]
+    fn collisions_dont_push_boids_into_no_go_zones() {
+        let settings = test_settings(400, 400);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 300, 8);
+        orchestrator.add_zone_rect("wall", 100.0, 100.0, 200.0, 200.0, ZoneEffect::NoGo, 0.0);
+        orchestrator.set_collisions_enabled(true);
+        orchestrator.set_boid_radius(8.0);
+        for _ in 0..200 {
+            orchestrator.tick(1.0 / 8.0);
+            for boid in orchestrator.boids() {
+                let (x, y) = (boid.position.x, boid.position.y);
+                assert!(!(x > 100.0 && x < 300.0 && y > 100.0 && y < 300.0), "{:?} in the wall", boid);
+            }
+        }
+    }
+
+    #[test]
+    fn boids_spawn_and_stay_inside_a_world_mask() {
+        let settings = test_settings(800, 400);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 0, 9);
+        // A triangle, so the edges aren't aligned with the world.
+        orchestrator.set_world_mask_polygon(&[100.0, 350.0, 700.0, 350.0, 400.0, 50.0]);
+        for _ in 0..80 {
+            orchestrator.add_boid();
+        }
+        assert!(orchestrator.boids().iter().all(|boid| orchestrator.world_mask_contains(boid.position.x, boid.position.y)));
+
+        for _ in 0..200 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+        let mask = orchestrator.world_mask().unwrap();
+        let outside = orchestrator
+            .boids()
+            .iter()
+            .filter(|boid| mask.distance(boid.position) < -10.0)
+            .count();
+        assert_eq!(outside, 0);
+
+        orchestrator.resize_world(1600, 800, ResizeMode::Scale);
+        assert!(orchestrator.world_mask_contains(800.0, 500.0));
+        assert!(!orchestrator.world_mask_contains(400.0, 200.0));
+        // A line isn't a shape, the triangle stays.
+        orchestrator.set_world_mask_polygon(&[0.0, 0.0, 1600.0, 800.0]);
+        assert!(orchestrator.world_mask_contains(800.0, 500.0));
+        let replayed = BoidOrchestrator::from_journal(orchestrator.journal());
+        assert_bit_identical(replayed.boids(), orchestrator.boids());
+
+        // Left half inside. The bitmap goes into the journal as one base64 string.
+        orchestrator.set_world_mask_bitmap(2, 1, &[255, 0]);
+        assert!(orchestrator.world_mask_contains(400.0, 400.0));
+        assert!(!orchestrator.world_mask_contains(1200.0, 400.0));
+        // A pixel short, so it's not a 2x1 bitmap and the mask stays.
+        orchestrator.set_world_mask_bitmap(2, 1, &[0]);
+        assert!(orchestrator.world_mask_contains(400.0, 400.0));
+        let json = orchestrator.export_journal();
+        assert!(json.contains(r#""pixels":"/wA=""#));
+        assert_eq!(&Journal::from_json(&json).unwrap(), orchestrator.journal());
+    }
+
+    #[test]
+    fn flock_morphs_into_a_formation_and_back() {
+        let mut orchestrator = test_orchestrator(test_flock(40));
+        // The outline of a rectangle, one target every 30 units.
+        orchestrator.set_formation_polygon(&[200.0, 100.0, 600.0, 100.0, 600.0, 300.0, 200.0, 300.0], true);
+        assert_eq!(orchestrator.formation().assigned(), 40);
+        let off_target = |orchestrator: &BoidOrchestrator| {
+            orchestrator
+                .boids()
+                .iter()
+                .map(|boid| {
+                    let target = orchestrator.formation().target(boid.id).unwrap();
+                    (boid.position - target).magnitude()
+                })
+                .fold(0.0, f32::max)
+        };
+        let scattered = off_target(&orchestrator);
+
+        orchestrator.set_formation_blend(1.0);
+        for _ in 0..300 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+        let settled = off_target(&orchestrator);
+        assert!(scattered > 100.0 && settled < 10.0, "{} from a target", settled);
+        // Separation stays on, so they don't bunch up on the way in.
+        let boids = orchestrator.boids();
+        let closest = boids
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| boids[i + 1..].iter().map(move |b| (a.position - b.position).magnitude()))
+            .fold(f32::MAX, f32::min);
+        assert!(closest > 20.0, "two boids {} apart", closest);
+        let replayed = BoidOrchestrator::from_journal(orchestrator.journal());
+        assert_bit_identical(replayed.boids(), orchestrator.boids());
+        assert_eq!(replayed.formation(), orchestrator.formation());
+
+        orchestrator.set_formation_blend(0.0);
+        for _ in 0..300 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+        assert!(off_target(&orchestrator) > 50.0);
+        orchestrator.clear_formation();
+        assert!(!orchestrator.has_formation());
+        assert_eq!(orchestrator.get_formation_target(0), Vec::<f32>::new());
+
+        // However the blend comes in, it ends up between 0 and 1.
+        assert_eq!(FormationSettings::new(1.5, 0.5, 60.0).blend, 1.0);
+        let json = r#"{"blend":-2.0,"formation_modifier":0.5,"arrival_radius":60.0}"#;
+        assert_eq!(serde_json::from_str::<FormationSettings>(json).unwrap().blend, 0.0);
+    }
+
+    #[test]
+    fn viewport_buffer_follows_the_flock() {
+        let settings = test_settings(2000, 2000);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 200, 11);
+        orchestrator.set_viewport(500.0, 500.0, 800.0, 600.0, 2.0);
+        orchestrator.set_viewport_margin(20.0);
+        orchestrator.set_cluster_cell_size(250.0);
+        for _ in 0..5 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+
+        assert_eq!(orchestrator.get_viewport_bounds(), vec![280.0, 330.0, 720.0, 670.0]);
+        let mut expected = orchestrator.boids_in_rect(280.0, 330.0, 720.0, 670.0);
+        expected.sort();
+        assert!(!expected.is_empty());
+        assert_eq!(orchestrator.culled_view().visible_ids(), expected.as_slice());
+        let clustered: f32 = orchestrator.culled_view().clusters().chunks(3).map(|c| c[2]).sum();
+        assert_eq!(clustered as usize + expected.len(), 200);
+
+        orchestrator.clear_viewport();
+        orchestrator.tick(1.0 / 8.0);
+        assert_eq!(orchestrator.get_visible_count(), 0);
+        assert_eq!(orchestrator.get_cluster_count(), 0);
+    }
+
+    #[test]
+    fn transfer_generation_tracks_reallocations() {
+        let mut orchestrator = test_orchestrator(test_flock(10));
+        let generation = orchestrator.get_transfer_generation();
+        orchestrator.tick(1.0 / 8.0);
+        assert_eq!(orchestrator.get_transfer_generation(), generation);
+        orchestrator.add_boid();
+        assert_ne!(orchestrator.get_transfer_generation(), generation);
+    }
+
+    #[test]
+    fn ecosystem_population_grows_around_food_and_replays() {
+        let settings = test_settings(600, 600);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 40, 21);
+        orchestrator.set_ecosystem_enabled(true);
+        orchestrator.set_reproduction_energy(60.0);
+        orchestrator.add_food_patch(300.0, 300.0, 250.0, 2000.0, 50.0);
+        for _ in 0..200 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+
+        let history = orchestrator.get_population_history();
+        assert_eq!(history.len(), 200);
+        assert_eq!(*history.last().unwrap(), orchestrator.get_population());
+        assert!(orchestrator.get_births() > 0);
+        assert_eq!(
+            orchestrator.get_population(),
+            40 + orchestrator.get_births() - orchestrator.get_deaths()
+        );
+        assert_eq!(
+            orchestrator.transfer_array().len(),
+            orchestrator.boids().len() * Boid::NUM_ELEMENTS
+        );
+        let mut ids: Vec<u32> = orchestrator.boids().iter().map(|boid| boid.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), orchestrator.boids().len());
+
+        let replayed = BoidOrchestrator::from_journal(orchestrator.journal());
+        assert_bit_identical(replayed.boids(), orchestrator.boids());
+    }
+
+    #[test]
+    fn spawned_boids_get_varied_traits() {
+        let mut settings = test_settings(800, 600);
+        settings.set_trait_spread(0.25);
+        let mut orchestrator = BoidOrchestrator::with_seed(settings, 50, 17);
+        for _ in 0..100 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+
+        let traits = orchestrator.traits_array();
+        assert_eq!(traits.len(), 50 * BoidTraits::NUM_ELEMENTS);
+        for (boid, channel) in orchestrator.boids().iter().zip(traits.chunks(BoidTraits::NUM_ELEMENTS)) {
+            assert_eq!(channel, boid.traits.as_array());
+            assert!(channel.iter().all(|&value| (0.75..=1.25).contains(&value)));
+            let limit = 15.0 * boid.traits.max_speed;
+            assert!(boid.velocity.magnitude() <= limit * 1.0001);
+        }
+        let speeds: Vec<f32> = orchestrator.boids().iter().map(|boid| boid.traits.max_speed).collect();
+        assert!(speeds.iter().any(|&speed| speed != speeds[0]));
+    }
+
+    #[test]
+    fn resize_world_moves_everything_with_it() {
+        let settings = test_settings(400, 200);
+        let boids = vec![
+            Boid::new(Vector2::new(300.0, 150.0), Vector2::new(0.0, 0.0), 0),
+            Boid::new(Vector2::new(100.0, 50.0), Vector2::new(0.0, 0.0), 1),
+        ];
+        let mut orchestrator = BoidOrchestrator::from_boids(settings, boids, 1);
+        orchestrator.add_obstacle(200.0, 100.0, 20.0);
+        orchestrator.set_path(&[0.0, 0.0, 400.0, 200.0], false);
+
+        orchestrator.resize_world(200, 100, ResizeMode::Scale);
+        assert_eq!(orchestrator.boids()[0].position, Vector2::new(150.0, 75.0));
+        assert_eq!(&orchestrator.transfer_array()[..2], &[150.0, 75.0]);
+        assert_eq!(orchestrator.get_obstacles(), vec![100.0, 50.0, 10.0]);
+        assert_eq!(orchestrator.get_path_points(), vec![0.0, 0.0, 200.0, 100.0]);
+        assert_eq!(orchestrator.boid_at(150.0, 75.0, 1.0), Some(0));
+        assert_eq!(
+            (orchestrator.get_flow_field_columns(), orchestrator.get_flow_field_rows()),
+            (4, 2)
+        );
+
+        // Past the edge lands within the avoidance range inside it.
+        orchestrator.resize_world(100, 100, ResizeMode::Clamp);
+        let clamped = orchestrator.boids()[0].position;
+        assert!(clamped.x > 75.0 && clamped.x <= 100.0 && clamped.y == 75.0, "{:?}", clamped);
+        orchestrator.resize_world(60, 60, ResizeMode::Wrap);
+        let wrapped = Vector2::new(clamped.x % 60.0, 15.0);
+        assert_eq!(orchestrator.boids()[0].position, wrapped);
+        orchestrator.resize_world(10, 10, ResizeMode::Keep);
+        assert_eq!(orchestrator.boids()[0].position, wrapped);
+        assert_eq!(orchestrator.get_world_width(), 10);
+        orchestrator.resize_world(0, 10, ResizeMode::Scale);
+        assert_eq!(orchestrator.boids()[0].position, wrapped);
+        assert_eq!(orchestrator.get_world_width(), 10);
+
+        orchestrator.set_flow_field_vortex(5.0, 5.0, 2.0, 4.0);
+        orchestrator.resize_world(40, 20, ResizeMode::Scale);
+        assert!(matches!(
+            orchestrator.flow_field.source(),
+            FlowSource::Vortex { center, radius, .. } if *center == Vector2::new(20.0, 10.0) && *radius == 8.0
+        ));
+    }
+
+    #[test]
+    fn every_resize_mode_leaves_the_flock_finite() {
+        for mode in [ResizeMode::Scale, ResizeMode::Clamp, ResizeMode::Wrap, ResizeMode::Keep] {
+            let mut orchestrator = BoidOrchestrator::with_seed(test_settings(400, 200), 60, 4);
+            orchestrator.set_wander_modifier(1.0);
+            orchestrator.resize_world(50, 25, mode);
+            for _ in 0..5 {
+                orchestrator.tick(0.1);
+            }
+            let finite = |v: Vector2<f32>| v.x.is_finite() && v.y.is_finite();
+            assert!(
+                orchestrator.boids().iter().all(|boid| finite(boid.position) && finite(boid.velocity)),
+                "{:?} left boids non-finite",
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn uniform_wind_pushes_the_flock() {
+        let mut calm = test_orchestrator(test_flock(20));
+        let mut windy = test_orchestrator(test_flock(20));
+        windy.set_flow_field_uniform(5.0, 0.0);
+        for _ in 0..10 {
+            calm.tick(1.0 / 8.0);
+            windy.tick(1.0 / 8.0);
+        }
+        let mean_x = |o: &BoidOrchestrator| {
+            o.boids().iter().map(|b| b.position.x).sum::<f32>() / o.boids().len() as f32
+        };
+        assert!(mean_x(&windy) > mean_x(&calm));
+    }
+
+    #[test]
+    fn tick_does_not_depend_on_boid_order() {
+        let mut forward = test_orchestrator(test_flock(50));
+        let mut reversed_boids = test_flock(50);
+        reversed_boids.reverse();
+        let mut reversed = test_orchestrator(reversed_boids);
+
+        forward.tick(1.0 / 8.0);
+        reversed.tick(1.0 / 8.0);
+
+        let mut reversed_result = reversed.boids().to_vec();
+        reversed_result.reverse();
+        for (a, b) in forward.boids().iter().zip(&reversed_result) {
+            assert_eq!(a.id, b.id);
+            assert!((a.position - b.position).magnitude() < 1e-3);
         }
-        border_velocity_vec
     }
 }
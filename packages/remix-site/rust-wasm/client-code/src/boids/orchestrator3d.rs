@@ -0,0 +1,475 @@
+use cgmath::{InnerSpace, Vector3};
+use js_sys::Float32Array;
+#[cfg(parallel_tick)]
+use rayon::prelude::*;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+use crate::boids::boid3d::{serialize_boids_3d, Boid3D};
+use crate::boids::rng::{random_seed, SimRng};
+use crate::boids::settings::{NeighborMode, WorldSettings};
+use crate::boids::utils::LinearSerializable;
+use crate::utils::{float32_view, BufferGeneration};
+
+/**
+ * The 3D version of `BoidOrchestrator`: the same cohesion, separation, alignment and
+ * border rules and the same `WorldSettings`, in a `world_width` x `world_height` x
+ * `world_depth` box. The transfer array holds [x, y, z, forward_x, forward_y, forward_z]
+ * per boid.
+ *
+ * Neighbors are found by brute force, there's no 3D spatial index yet, so this is meant
+ * for flocks of a few thousand boids at most.
+ */
+#[wasm_bindgen]
+pub struct BoidOrchestrator3D {
+    boids: Vec<Boid3D>,
+    transfer_array: Vec<f32>,
+    world_settings: WorldSettings,
+    world_depth: u32,
+    seed: u32,
+    rng: SimRng,
+    transfer_generation: BufferGeneration,
+    tick_count: u32,
+}
+
+#[wasm_bindgen]
+impl BoidOrchestrator3D {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        world_width: u32,
+        world_height: u32,
+        world_depth: u32,
+        num_boids: u32,
+        velocity_limit: f32,
+        pc_modifier: f32,
+        avoidance_modifier: f32,
+        avoidance_range: f32,
+        velocity_matching_modifier: f32,
+        border_constraint_modifier: f32,
+    ) -> BoidOrchestrator3D {
+        let settings = WorldSettings::new(
+            world_width,
+            world_height,
+            velocity_limit,
+            pc_modifier,
+            avoidance_modifier,
+            avoidance_range,
+            velocity_matching_modifier,
+            border_constraint_modifier,
+        );
+        BoidOrchestrator3D::with_seed(settings, world_depth, num_boids, random_seed())
+    }
+
+    pub fn with_seed(
+        world_settings: WorldSettings,
+        world_depth: u32,
+        num_boids: u32,
+        seed: u32,
+    ) -> BoidOrchestrator3D {
+        let mut orchestrator =
+            BoidOrchestrator3D::from_boids(world_settings, world_depth, Vec::new(), seed);
+        for _ in 0..num_boids {
+            orchestrator.add_boid();
+        }
+        orchestrator
+    }
+
+    pub fn get_seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn get_tick_count(&self) -> u32 {
+        self.tick_count
+    }
+
+    /**
+     * Advances the simulation by `dt`, double buffered like the 2D tick.
+     */
+    pub fn tick(&mut self, dt: f32) {
+        self.tick_count += 1;
+        self.boids = self.compute_next_boids(dt, false);
+        serialize_boids_3d(&self.boids, &mut self.transfer_array);
+    }
+
+    /**
+     * Same as `tick`, but always evaluates the boids on the current thread, even when the
+     * `parallel` feature is on. The two paths must produce bit-identical results.
+     */
+    pub fn tick_single_threaded(&mut self, dt: f32) {
+        self.tick_count += 1;
+        self.boids = self.compute_next_boids(dt, true);
+        serialize_boids_3d(&self.boids, &mut self.transfer_array);
+    }
+
+    pub fn get_transfer_array_ptr(&self) -> *const f32 {
+        self.transfer_array.as_ptr()
+    }
+
+    pub fn get_transfer_array_view(&self) -> Float32Array {
+        float32_view(&self.transfer_array)
+    }
+
+    pub fn get_transfer_generation(&self) -> u32 {
+        self.transfer_generation.current(&self.transfer_array)
+    }
+
+    pub fn length(&self) -> u32 {
+        self.transfer_array.len() as u32
+    }
+
+    pub fn add_boid(&mut self) {
+        let boid = Boid3D::new_random_boid_in_world(
+            self.world_settings.world_width,
+            self.world_settings.world_height,
+            self.world_depth,
+            self.boids.len() as u32,
+            &mut self.rng,
+        );
+        self.transfer_array
+            .resize(self.transfer_array.len() + Boid3D::NUM_ELEMENTS, 0.0);
+        boid.serialize_to_array(&mut self.transfer_array, self.boids.len());
+        self.boids.push(boid);
+    }
+
+    pub fn remove_last_boid(&mut self) {
+        if self.boids.pop().is_some() {
+            self.transfer_array
+                .truncate(self.boids.len() * Boid3D::NUM_ELEMENTS);
+        }
+    }
+
+    // World settings getters and setters
+    pub fn get_world_width(&self) -> u32 {
+        self.world_settings.world_width
+    }
+
+    pub fn get_world_height(&self) -> u32 {
+        self.world_settings.world_height
+    }
+
+    pub fn get_world_depth(&self) -> u32 {
+        self.world_depth
+    }
+
+    pub fn set_world_width(&mut self, width: u32) {
+        self.world_settings.set_world_width(width);
+    }
+
+    pub fn set_world_height(&mut self, height: u32) {
+        self.world_settings.set_world_height(height);
+    }
+
+    pub fn set_world_depth(&mut self, depth: u32) {
+        self.world_depth = depth;
+    }
+
+    pub fn get_avoidance_range(&self) -> f32 {
+        self.world_settings.avoidance.avoidance_range
+    }
+
+    pub fn set_avoidance_range(&mut self, range: f32) {
+        self.world_settings.set_avoidance_range(range);
+    }
+
+    pub fn get_avoidance_modifier(&self) -> f32 {
+        self.world_settings.avoidance.avoidance_modifier
+    }
+
+    pub fn set_avoidance_modifier(&mut self, modifier: f32) {
+        self.world_settings.set_avoidance_modifier(modifier);
+    }
+
+    pub fn get_p_center_modifier(&self) -> f32 {
+        self.world_settings.pc.p_center_modifier
+    }
+
+    pub fn set_p_center_modifier(&mut self, modifier: f32) {
+        self.world_settings.set_p_center_modifier(modifier);
+    }
+
+    pub fn get_velocity_matching_modifier(&self) -> f32 {
+        self.world_settings.velocity_matching.velocity_matching_modifier
+    }
+
+    pub fn set_velocity_matching_modifier(&mut self, modifier: f32) {
+        self.world_settings.set_velocity_matching_modifier(modifier);
+    }
+
+    pub fn get_border_constraint_modifier(&self) -> f32 {
+        self.world_settings.border_constraint.border_constraint_modifier
+    }
+
+    pub fn set_border_constraint_modifier(&mut self, modifier: f32) {
+        self.world_settings.set_border_constraint_modifier(modifier);
+    }
+
+    pub fn get_neighbor_mode(&self) -> NeighborMode {
+        self.world_settings.neighbors.mode
+    }
+
+    pub fn set_neighbor_mode(&mut self, mode: NeighborMode) {
+        self.world_settings.set_neighbor_mode(mode);
+    }
+
+    pub fn get_perception_range(&self) -> f32 {
+        self.world_settings.neighbors.perception_range
+    }
+
+    pub fn set_perception_range(&mut self, range: f32) {
+        self.world_settings.set_perception_range(range);
+    }
+
+    pub fn get_k_nearest(&self) -> u32 {
+        self.world_settings.neighbors.k
+    }
+
+    pub fn set_k_nearest(&mut self, k: u32) {
+        self.world_settings.set_k_nearest(k);
+    }
+}
+
+impl fmt::Display for BoidOrchestrator3D {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for boid in &self.boids {
+            writeln!(f, "{}", boid)?;
+        }
+        Ok(())
+    }
+}
+
+impl BoidOrchestrator3D {
+    pub fn from_boids(
+        world_settings: WorldSettings,
+        world_depth: u32,
+        boids: Vec<Boid3D>,
+        seed: u32,
+    ) -> BoidOrchestrator3D {
+        let mut transfer_array = vec![0.0; boids.len() * Boid3D::NUM_ELEMENTS];
+        serialize_boids_3d(&boids, &mut transfer_array);
+        BoidOrchestrator3D {
+            boids,
+            transfer_array,
+            world_settings,
+            world_depth,
+            seed,
+            rng: SimRng::new(seed),
+            transfer_generation: BufferGeneration::default(),
+            tick_count: 0,
+        }
+    }
+
+    pub fn boids(&self) -> &[Boid3D] {
+        &self.boids
+    }
+
+    pub fn transfer_array(&self) -> &[f32] {
+        &self.transfer_array
+    }
+
+    #[cfg(parallel_tick)]
+    fn compute_next_boids(&self, dt: f32, single_threaded: bool) -> Vec<Boid3D> {
+        if single_threaded {
+            return self.boids.iter().map(|boid| self.apply_rules(boid, dt)).collect();
+        }
+        self.boids
+            .par_iter()
+            .map(|boid| self.apply_rules(boid, dt))
+            .collect()
+    }
+
+    #[cfg(not(parallel_tick))]
+    fn compute_next_boids(&self, dt: f32, _single_threaded: bool) -> Vec<Boid3D> {
+        self.boids
+            .iter()
+            .map(|boid| self.apply_rules(boid, dt))
+            .collect()
+    }
+
+    /**
+     * Same neighbor modes as the 2D world, with distances measured in 3D.
+     */
+    fn neighbor_indices(&self, boid: &Boid3D) -> Vec<usize> {
+        let settings = &self.world_settings.neighbors;
+        let others = (0..self.boids.len()).filter(|&i| self.boids[i].id != boid.id);
+        match settings.mode {
+            NeighborMode::All => others.collect(),
+            NeighborMode::Metric => {
+                let range2 = settings.perception_range * settings.perception_range;
+                others
+                    .filter(|&i| (self.boids[i].position - boid.position).magnitude2() <= range2)
+                    .collect()
+            }
+            NeighborMode::KNearest => {
+                let mut by_distance: Vec<(f32, usize)> = others
+                    .map(|i| ((self.boids[i].position - boid.position).magnitude2(), i))
+                    .collect();
+                // Ties go to the lower index so the result doesn't depend on the sort.
+                by_distance.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                by_distance
+                    .into_iter()
+                    .take(settings.k as usize)
+                    .map(|(_, i)| i)
+                    .collect()
+            }
+        }
+    }
+
+    fn apply_rules(&self, boid: &Boid3D, dt: f32) -> Boid3D {
+        let neighbors = self.neighbor_indices(boid);
+        let settings = &self.world_settings;
+        let mut new_velocity = boid.velocity
+            + self.get_velocity_to_perceived_center(boid, &neighbors) * settings.pc.p_center_modifier
+            + self.get_avoidance_velocity(boid, &neighbors) * settings.avoidance.avoidance_modifier
+            + self.get_match_percived_velocity(boid, &neighbors)
+                * settings.velocity_matching.velocity_matching_modifier
+            + self.get_border_velocity(boid) * settings.border_constraint.border_constraint_modifier;
+
+        let vel_limit: f32 = settings.velocity_limit;
+        if new_velocity.magnitude() > vel_limit {
+            new_velocity = new_velocity.normalize_to(vel_limit)
+        }
+
+        Boid3D {
+            velocity: new_velocity,
+            position: boid.position + new_velocity * dt,
+            ..*boid
+        }
+    }
+
+    /**
+     * Rule 1. The boid is attracted to the percived center of its neighbors.
+     */
+    fn get_velocity_to_perceived_center(&self, boid: &Boid3D, neighbors: &[usize]) -> Vector3<f32> {
+        if neighbors.is_empty() {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        let sum_of_positions = neighbors
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |acc, &i| acc + self.boids[i].position);
+        let center = sum_of_positions / neighbors.len() as f32;
+        (center - boid.position) / 100.0
+    }
+
+    /**
+     * Rule 2. Boids want to avoid each other, closer boids push harder.
+     */
+    fn get_avoidance_velocity(&self, boid: &Boid3D, neighbors: &[usize]) -> Vector3<f32> {
+        neighbors
+            .iter()
+            .map(|&i| self.boids[i].position - boid.position)
+            // A boid on top of another has no direction to be pushed in, dividing by its
+            // zero distance would only make NaNs.
+            .filter(|diff| {
+                let distance = diff.magnitude();
+                distance > f32::EPSILON && distance < self.world_settings.avoidance.avoidance_range
+            })
+            .fold(Vector3::new(0.0, 0.0, 0.0), |acc, diff| {
+                acc - diff * (4.0 / diff.magnitude())
+            })
+    }
+
+    /**
+     * Rule 3. Boids try to match the velocity of their neighbors.
+     */
+    fn get_match_percived_velocity(&self, boid: &Boid3D, neighbors: &[usize]) -> Vector3<f32> {
+        if neighbors.is_empty() {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        let sum_of_velocity = neighbors
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |acc, &i| acc + self.boids[i].velocity);
+        let center = sum_of_velocity / neighbors.len() as f32;
+        (center - boid.velocity) / 8.0
+    }
+
+    /**
+     * Rule 4. Boids outside the world box get pushed back in, per axis.
+     */
+    fn get_border_velocity(&self, boid: &Boid3D) -> Vector3<f32> {
+        let size = [
+            self.world_settings.world_width as f32,
+            self.world_settings.world_height as f32,
+            self.world_depth as f32,
+        ];
+        let mut border_velocity_vec = Vector3::new(0.0, 0.0, 0.0);
+        for (axis, size) in size.into_iter().enumerate() {
+            if boid.position[axis] < 0.0 {
+                border_velocity_vec[axis] = 10.0;
+            } else if boid.position[axis] > size {
+                border_velocity_vec[axis] = -10.0;
+            }
+        }
+        border_velocity_vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::settings::test_settings;
+
+    #[test]
+    fn flock_stays_in_the_box_and_faces_its_velocity() {
+        let settings = test_settings(400, 300);
+        let mut orchestrator = BoidOrchestrator3D::with_seed(settings, 200, 60, 8);
+        for _ in 0..400 {
+            orchestrator.tick(1.0 / 8.0);
+        }
+
+        let margin = 50.0;
+        for (boid, data) in orchestrator
+            .boids()
+            .iter()
+            .zip(orchestrator.transfer_array().chunks(Boid3D::NUM_ELEMENTS))
+        {
+            let p = boid.position;
+            assert!(p.x > -margin && p.x < 400.0 + margin, "{}", boid);
+            assert!(p.y > -margin && p.y < 300.0 + margin, "{}", boid);
+            assert!(p.z > -margin && p.z < 200.0 + margin, "{}", boid);
+            assert_eq!(&data[..3], &[p.x, p.y, p.z]);
+            let forward = Vector3::new(data[3], data[4], data[5]);
+            assert!((forward.magnitude() - 1.0).abs() < 1e-4);
+            assert!(forward.dot(boid.velocity) >= 0.0);
+        }
+        // The flock actually uses the third axis.
+        let depths: Vec<f32> = orchestrator.boids().iter().map(|boid| boid.position.z).collect();
+        let spread = depths.iter().cloned().fold(f32::MIN, f32::max)
+            - depths.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(spread > 1.0);
+    }
+
+    #[test]
+    fn coincident_boids_stay_finite() {
+        let position = Vector3::new(50.0, 50.0, 50.0);
+        let boids = vec![
+            Boid3D::new(position, Vector3::new(1.0, 0.0, 0.0), 0),
+            Boid3D::new(position, Vector3::new(0.0, 1.0, 0.0), 1),
+        ];
+        let mut orchestrator = BoidOrchestrator3D::from_boids(test_settings(100, 100), 100, boids, 1);
+        orchestrator.tick(1.0 / 8.0);
+        for boid in orchestrator.boids() {
+            assert!(boid.position.magnitude().is_finite() && boid.velocity.magnitude().is_finite(), "{}", boid);
+        }
+    }
+
+    // Without the parallel tick both sides would run the same serial code.
+    #[cfg(parallel_tick)]
+    #[test]
+    fn tick_matches_single_threaded_tick() {
+        let mut threaded = BoidOrchestrator3D::with_seed(test_settings(400, 300), 200, 200, 8);
+        let mut single = BoidOrchestrator3D::with_seed(test_settings(400, 300), 200, 200, 8);
+        for _ in 0..50 {
+            threaded.tick(1.0 / 8.0);
+            single.tick_single_threaded(1.0 / 8.0);
+        }
+        for (a, b) in threaded.boids().iter().zip(single.boids()) {
+            assert_eq!(a.id, b.id);
+            for axis in 0..3 {
+                assert_eq!(a.position[axis].to_bits(), b.position[axis].to_bits());
+                assert_eq!(a.velocity[axis].to_bits(), b.velocity[axis].to_bits());
+            }
+        }
+        assert_eq!(threaded.transfer_array(), single.transfer_array());
+    }
+}
@@ -0,0 +1,221 @@
+use cgmath::{InnerSpace, Vector2};
+
+/**
+ * A polyline through the world that leader boids follow. When `looping` is set the last
+ * point connects back to the first.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct BoidPath {
+    points: Vec<Vector2<f32>>,
+    looping: bool,
+}
+
+impl BoidPath {
+    /**
+     * Builds a path from a flat x, y array (the layout JS hands us). Repeated points are
+     * dropped so no segment has zero length, and a path with no length at all (a single
+     * point, or the same point clicked twice) is None.
+     */
+    pub fn from_flat(points: &[f32], looping: bool) -> Option<BoidPath> {
+        let mut path: Vec<Vector2<f32>> = Vec::new();
+        for point in points.chunks_exact(2).map(|point| Vector2::new(point[0], point[1])) {
+            if path.last() != Some(&point) {
+                path.push(point);
+            }
+        }
+        if looping && path.len() > 1 && path.first() == path.last() {
+            path.pop();
+        }
+        let path = BoidPath { points: path, looping };
+        if path.length() > 0.0 {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    pub fn points(&self) -> &[Vector2<f32>] {
+        &self.points
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn to_flat(&self) -> Vec<f32> {
+        self.points.iter().flat_map(|point| [point.x, point.y]).collect()
+    }
+
+    /**
+     * Applies `f` to every point, used when the world is rescaled.
+     */
+    pub fn map_points(&mut self, f: impl Fn(Vector2<f32>) -> Vector2<f32>) {
+        for point in self.points.iter_mut() {
+            *point = f(*point);
+        }
+    }
+
+    fn segment_count(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 0,
+            n if self.looping => n,
+            n => n - 1,
+        }
+    }
+
+    fn segment(&self, i: usize) -> (Vector2<f32>, Vector2<f32>) {
+        (self.points[i], self.points[(i + 1) % self.points.len()])
+    }
+
+    fn length(&self) -> f32 {
+        (0..self.segment_count())
+            .map(|i| {
+                let (a, b) = self.segment(i);
+                (b - a).magnitude()
+            })
+            .sum()
+    }
+
+    /**
+     * Finds the closest point on the path to `position`, returning the segment index and
+     * how far along that segment (0..1) the point is.
+     */
+    fn closest(&self, position: Vector2<f32>) -> (usize, f32) {
+        let mut best = (0, 0.0);
+        let mut best_distance = f32::MAX;
+        for i in 0..self.segment_count() {
+            let (a, b) = self.segment(i);
+            let ab = b - a;
+            let length2 = ab.magnitude2();
+            let t = if length2 > 0.0 {
+                ((position - a).dot(ab) / length2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = (a + ab * t - position).magnitude2();
+            if distance < best_distance {
+                best_distance = distance;
+                best = (i, t);
+            }
+        }
+        best
+    }
+
+    /**
+     * The point `distance` further along the path from the closest point to `position`.
+     * Open paths stop at their last point, looping paths wrap around. Also returns whether
+     * the target is the end of an open path, so callers can slow down to arrive there.
+     * None for a path without length, which a looping walk would never get through.
+     */
+    pub fn target_ahead(&self, position: Vector2<f32>, distance: f32) -> Option<(Vector2<f32>, bool)> {
+        let total = self.length();
+        if !total.is_finite() || total <= 0.0 || !distance.is_finite() {
+            return None;
+        }
+        let (mut segment, t) = self.closest(position);
+        let (a, b) = self.segment(segment);
+        let mut remaining = distance + (b - a).magnitude() * t;
+        if self.looping {
+            // Whole laps don't move the target, and a huge distance would never shrink
+            // by subtracting segment lengths from it.
+            remaining %= total;
+        }
+        loop {
+            let (a, b) = self.segment(segment);
+            let length = (b - a).magnitude();
+            if remaining <= length {
+                let t = if length > 0.0 { remaining / length } else { 0.0 };
+                return Some((a + (b - a) * t, false));
+            }
+            remaining -= length;
+            segment += 1;
+            if segment >= self.segment_count() {
+                if self.looping {
+                    segment = 0;
+                } else {
+                    return Some((self.points[self.points.len() - 1], true));
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Reynolds' arrival: steer towards `target` at full speed, easing off once inside
+ * `slowing_radius` so the boid doesn't overshoot.
+ */
+pub fn arrive(
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    target: Vector2<f32>,
+    max_speed: f32,
+    slowing_radius: f32,
+) -> Vector2<f32> {
+    let offset = target - position;
+    let distance = offset.magnitude();
+    if distance < f32::EPSILON {
+        return -velocity;
+    }
+    let speed = if slowing_radius > 0.0 && distance < slowing_radius {
+        max_speed * (distance / slowing_radius)
+    } else {
+        max_speed
+    };
+    offset * (speed / distance) - velocity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector2<f32>, b: Vector2<f32>) {
+        assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn target_ahead_walks_along_the_path_and_loops() {
+        let square = [0.0, 0.0, 100.0, 0.0, 100.0, 100.0, 0.0, 100.0];
+        let open = BoidPath::from_flat(&square, false).unwrap();
+        let looping = BoidPath::from_flat(&square, true).unwrap();
+
+        let (target, end) = open.target_ahead(Vector2::new(50.0, 5.0), 80.0).unwrap();
+        assert_close(target, Vector2::new(100.0, 30.0));
+        assert!(!end);
+
+        // Near the end of the open path we clamp to the last point.
+        let (target, end) = open.target_ahead(Vector2::new(5.0, 100.0), 50.0).unwrap();
+        assert_close(target, Vector2::new(0.0, 100.0));
+        assert!(end);
+
+        // The looping path carries on along the closing segment.
+        let (target, end) = looping.target_ahead(Vector2::new(5.0, 100.0), 50.0).unwrap();
+        assert_close(target, Vector2::new(0.0, 55.0));
+        assert!(!end);
+    }
+
+    #[test]
+    fn paths_without_length_are_rejected() {
+        assert!(BoidPath::from_flat(&[10.0, 10.0, 10.0, 10.0], true).is_none());
+        assert!(BoidPath::from_flat(&[10.0, 10.0], false).is_none());
+        // Repeated points are dropped, the rest of the path still works.
+        let path = BoidPath::from_flat(&[0.0, 0.0, 0.0, 0.0, 10.0, 0.0, 10.0, 0.0, 0.0, 0.0], true).unwrap();
+        assert_eq!(path.points(), &[Vector2::new(0.0, 0.0), Vector2::new(10.0, 0.0)]);
+        let (target, _) = path.target_ahead(Vector2::new(0.0, 0.0), 25.0).unwrap();
+        assert_close(target, Vector2::new(5.0, 0.0));
+        assert_eq!(path.target_ahead(Vector2::new(0.0, 0.0), f32::INFINITY), None);
+        assert!(path.target_ahead(Vector2::new(0.0, 0.0), 1e30).is_some());
+        assert_eq!(BoidPath::default().target_ahead(Vector2::new(0.0, 0.0), 5.0), None);
+    }
+
+    #[test]
+    fn arrive_slows_down_near_the_target() {
+        let far = arrive(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0), Vector2::new(500.0, 0.0), 10.0, 100.0);
+        let near = arrive(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0), Vector2::new(20.0, 0.0), 10.0, 100.0);
+        assert_eq!(far, Vector2::new(10.0, 0.0));
+        assert_eq!(near, Vector2::new(2.0, 0.0));
+    }
+}
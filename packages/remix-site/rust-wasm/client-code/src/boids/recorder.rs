@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::boids::boid::Boid;
+use crate::boids::rules::RuleVelocities;
+
+/**
+ * One boid on one tick.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrajectoryRow {
+    pub tick: u32,
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub rules: Option<RuleVelocities>,
+}
+
+/**
+ * Captures per-tick boid state for a window of ticks, for exporting datasets. Memory is
+ * bounded by `max_rows`: once full, the oldest rows are dropped.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TrajectoryRecorder {
+    active: bool,
+    from_tick: u32,
+    // 0 means "until stopped".
+    to_tick: u32,
+    max_rows: usize,
+    include_rules: bool,
+    // Empty means every boid.
+    boid_ids: Vec<u32>,
+    rows: VecDeque<TrajectoryRow>,
+    dropped: usize,
+}
+
+impl TrajectoryRecorder {
+    pub fn start(&mut self, from_tick: u32, to_tick: u32, max_rows: usize, include_rules: bool) {
+        self.active = true;
+        self.from_tick = from_tick;
+        self.to_tick = to_tick;
+        self.max_rows = max_rows.max(1);
+        self.include_rules = include_rules;
+        while self.rows.len() > self.max_rows {
+            self.rows.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.dropped = 0;
+    }
+
+    pub fn set_boids(&mut self, mut boid_ids: Vec<u32>) {
+        boid_ids.sort_unstable();
+        boid_ids.dedup();
+        self.boid_ids = boid_ids;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn rows(&self) -> &VecDeque<TrajectoryRow> {
+        &self.rows
+    }
+
+    pub fn wants_tick(&self, tick: u32) -> bool {
+        self.active && tick >= self.from_tick && (self.to_tick == 0 || tick <= self.to_tick)
+    }
+
+    pub fn wants_rules(&self, tick: u32) -> bool {
+        self.include_rules && self.wants_tick(tick)
+    }
+
+    pub fn wants_boid(&self, boid_id: u32) -> bool {
+        self.boid_ids.is_empty() || self.boid_ids.binary_search(&boid_id).is_ok()
+    }
+
+    /**
+     * Records the state a tick ended in. `rules` are the vectors that were integrated to
+     * get there, matched up with the boids by id.
+     */
+    pub fn record(&mut self, tick: u32, boids: &[Boid], mut rules: Vec<(u32, RuleVelocities)>) {
+        if !self.wants_tick(tick) {
+            return;
+        }
+        rules.sort_unstable_by_key(|(id, _)| *id);
+        for boid in boids {
+            if !self.wants_boid(boid.id) {
+                continue;
+            }
+            let boid_rules = rules
+                .binary_search_by_key(&boid.id, |(id, _)| *id)
+                .ok()
+                .map(|i| rules[i].1);
+            if self.rows.len() == self.max_rows {
+                self.rows.pop_front();
+                self.dropped += 1;
+            }
+            self.rows.push_back(TrajectoryRow {
+                tick,
+                id: boid.id,
+                x: boid.position.x,
+                y: boid.position.y,
+                vx: boid.velocity.x,
+                vy: boid.velocity.y,
+                rules: boid_rules,
+            });
+        }
+        if self.to_tick != 0 && tick >= self.to_tick {
+            self.active = false;
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("tick,id,x,y,vx,vy");
+        if self.include_rules {
+            for name in RuleVelocities::NAMES {
+                let _ = write!(csv, ",{}_x,{}_y", name, name);
+            }
+        }
+        csv.push('\n');
+        for row in &self.rows {
+            let _ = write!(
+                csv,
+                "{},{},{},{},{},{}",
+                row.tick, row.id, row.x, row.y, row.vx, row.vy
+            );
+            if self.include_rules {
+                match row.rules {
+                    Some(rules) => {
+                        for vector in rules.as_array() {
+                            let _ = write!(csv, ",{},{}", vector.x, vector.y);
+                        }
+                    }
+                    None => csv.push_str(&",".repeat(RuleVelocities::NAMES.len() * 2)),
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json_lines(&self) -> String {
+        let mut lines = String::new();
+        for row in &self.rows {
+            let _ = write!(
+                lines,
+                r#"{{"tick":{},"id":{},"x":{},"y":{},"vx":{},"vy":{}"#,
+                row.tick,
+                row.id,
+                json_number(row.x),
+                json_number(row.y),
+                json_number(row.vx),
+                json_number(row.vy)
+            );
+            if let Some(rules) = row.rules {
+                lines.push_str(r#","rules":{"#);
+                for (i, (name, vector)) in RuleVelocities::NAMES
+                    .iter()
+                    .zip(rules.as_array())
+                    .enumerate()
+                {
+                    if i > 0 {
+                        lines.push(',');
+                    }
+                    let _ = write!(
+                        lines,
+                        r#""{}":[{},{}]"#,
+                        name,
+                        json_number(vector.x),
+                        json_number(vector.y)
+                    );
+                }
+                lines.push('}');
+            }
+            lines.push_str("}\n");
+        }
+        lines
+    }
+}
+
+/**
+ * JSON has no NaN or infinity, so those become null.
+ */
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Vector2};
+    use crate::boids::orchestrator::BoidOrchestrator;
+    use crate::boids::settings::test_settings;
+
+    fn world() -> BoidOrchestrator {
+        let settings = test_settings(400, 200);
+        BoidOrchestrator::with_seed(settings, 5, 3)
+    }
+
+    #[test]
+    fn records_a_window_of_ticks_for_a_subset() {
+        let mut world = world();
+        world.start_recording(3, 5, 100, false);
+        world.set_recorded_boids(vec![1, 4]);
+        for _ in 0..8 {
+            world.tick(0.125);
+        }
+        assert_eq!(world.get_recorded_row_count(), 6);
+        assert!(!world.is_recording());
+
+        let csv = world.export_recording_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "tick,id,x,y,vx,vy");
+        assert_eq!(lines.len(), 7);
+        assert!(lines[1].starts_with("3,1,"));
+        assert!(lines[6].starts_with("5,4,"));
+    }
+
+    #[test]
+    fn row_limit_drops_the_oldest_rows() {
+        let mut world = world();
+        // Wander changes with time, so rules worked out between ticks would be off.
+        world.set_wander_modifier(1.5);
+        world.start_recording(0, 0, 12, true);
+        for _ in 0..3 {
+            world.tick(0.125);
+        }
+        let before = world.boids()[4];
+        let stale = world.rule_velocities(4);
+        world.tick(0.125);
+
+        // Rows carry the rules the tick integrated, so they add up to the new velocity.
+        let row = world.recorder().rows().back().unwrap();
+        let rules = row.rules.unwrap();
+        assert_ne!(rules, stale);
+        let mut velocity = rules.apply_to(before.velocity);
+        let limit = world.world_settings().velocity_limit * before.traits.max_speed;
+        if velocity.magnitude() > limit {
+            velocity = velocity.normalize_to(limit);
+        }
+        assert!((velocity - Vector2::new(row.vx, row.vy)).magnitude() < 1e-4);
+        assert_eq!(world.get_recorded_row_count(), 12);
+        assert_eq!(world.get_dropped_row_count(), 8);
+
+        let json = world.export_recording_json_lines();
+        let first = json.lines().next().unwrap();
+        assert!(first.starts_with(r#"{"tick":2,"id":3,"#));
+        assert!(first.contains(r#""rules":{"cohesion":["#));
+        assert!(first.ends_with("]}}"));
+        assert_eq!(
+            world.export_recording_csv().lines().next().unwrap().split(',').count(),
+            6 + RuleVelocities::NAMES.len() * 2
+        );
+    }
+}
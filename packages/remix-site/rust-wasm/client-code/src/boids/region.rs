@@ -0,0 +1,121 @@
+use cgmath::{InnerSpace, Vector2};
+
+/**
+ * An area of the world, in world coordinates.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionShape {
+    Rect { min: Vector2<f32>, max: Vector2<f32> },
+    Circle { center: Vector2<f32>, radius: f32 },
+}
+
+impl RegionShape {
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        match *self {
+            RegionShape::Rect { min, max } => {
+                point.x >= min.x && point.x <= max.x && point.y >= min.y && point.y <= max.y
+            }
+            RegionShape::Circle { center, radius } => (point - center).magnitude2() <= radius * radius,
+        }
+    }
+
+    pub fn center(&self) -> Vector2<f32> {
+        match *self {
+            RegionShape::Rect { min, max } => (min + max) * 0.5,
+            RegionShape::Circle { center, .. } => center,
+        }
+    }
+
+    /**
+     * For a point inside the shape, the closest point on its edge and the outward
+     * normal there. None when the point is outside.
+     */
+    pub fn exit(&self, point: Vector2<f32>) -> Option<(Vector2<f32>, Vector2<f32>)> {
+        if !self.contains(point) {
+            return None;
+        }
+        match *self {
+            RegionShape::Rect { min, max } => {
+                let exits = [
+                    (point.x - min.x, Vector2::new(min.x, point.y), Vector2::new(-1.0, 0.0)),
+                    (max.x - point.x, Vector2::new(max.x, point.y), Vector2::new(1.0, 0.0)),
+                    (point.y - min.y, Vector2::new(point.x, min.y), Vector2::new(0.0, -1.0)),
+                    (max.y - point.y, Vector2::new(point.x, max.y), Vector2::new(0.0, 1.0)),
+                ];
+                // First of the closest edges, so ties always go the same way.
+                let (_, edge, normal) = exits
+                    .into_iter()
+                    .fold(None, |best: Option<(f32, _, _)>, exit| match best {
+                        Some(best) if best.0 <= exit.0 => Some(best),
+                        _ => Some(exit),
+                    })?;
+                Some((edge, normal))
+            }
+            RegionShape::Circle { center, radius } => {
+                let offset = point - center;
+                let normal = if offset.magnitude2() > f32::EPSILON {
+                    offset.normalize()
+                } else {
+                    Vector2::new(1.0, 0.0)
+                };
+                Some((center + normal * radius, normal))
+            }
+        }
+    }
+
+    /**
+     * The shape moved along with the world: `map` moves a point, `scale_length` resizes
+     * a length. Corners are only mapped separately when the world is stretched, other
+     * modes move the shape by where its center ends up.
+     */
+    pub fn mapped(
+        &self,
+        map: impl Fn(Vector2<f32>) -> Vector2<f32>,
+        scale_length: impl Fn(f32) -> f32,
+        stretch: bool,
+    ) -> RegionShape {
+        match *self {
+            RegionShape::Rect { min, max } if stretch => RegionShape::Rect {
+                min: map(min),
+                max: map(max),
+            },
+            RegionShape::Rect { min, max } => {
+                let shift = map(self.center()) - self.center();
+                RegionShape::Rect {
+                    min: min + shift,
+                    max: max + shift,
+                }
+            }
+            RegionShape::Circle { center, radius } => RegionShape::Circle {
+                center: map(center),
+                radius: scale_length(radius),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_exit_through_the_nearest_edge() {
+        let rect = RegionShape::Rect {
+            min: Vector2::new(0.0, 0.0),
+            max: Vector2::new(10.0, 20.0),
+        };
+        assert_eq!(rect.exit(Vector2::new(12.0, 5.0)), None);
+        assert_eq!(
+            rect.exit(Vector2::new(8.0, 5.0)),
+            Some((Vector2::new(10.0, 5.0), Vector2::new(1.0, 0.0)))
+        );
+        let circle = RegionShape::Circle {
+            center: Vector2::new(0.0, 0.0),
+            radius: 5.0,
+        };
+        assert_eq!(
+            circle.exit(Vector2::new(0.0, -1.0)),
+            Some((Vector2::new(0.0, -5.0), Vector2::new(0.0, -1.0)))
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use cgmath::{InnerSpace, Vector2};
+use js_sys::Uint8Array;
+use wasm_bindgen::prelude::*;
+
+use crate::boids::orchestrator::BoidOrchestrator;
+use crate::utils::{uint8_view, BufferGeneration};
+
+/**
+ * Software renderer that draws the boid world into an RGBA8 framebuffer living in wasm
+ * memory. JS can wrap the pixels in an `ImageData` and `putImageData` them, and native
+ * code (tests, tooling) can use the same buffer to make thumbnails.
+ *
+ * Colors are passed as 0xRRGGBBAA.
+ */
+#[wasm_bindgen]
+pub struct BoidRenderer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    pixels_generation: BufferGeneration,
+    // Pixels per world unit.
+    scale: f32,
+    // Length of a boid triangle, in world units.
+    boid_size: f32,
+    background: u32,
+    boid_color: u32,
+    obstacle_color: u32,
+    vector_color: u32,
+    anti_aliasing: bool,
+    trails: bool,
+    // How much of the previous frame is faded out each render when trails are on (0..1).
+    trail_fade: f32,
+    debug_vectors: bool,
+}
+
+#[wasm_bindgen]
+impl BoidRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32) -> BoidRenderer {
+        let mut renderer = BoidRenderer {
+            width,
+            height,
+            pixels: vec![0; buffer_len(width, height)],
+            pixels_generation: BufferGeneration::default(),
+            scale: 1.0,
+            boid_size: 8.0,
+            background: 0x1E1E1EFF,
+            boid_color: 0xF0F0F0FF,
+            obstacle_color: 0x806060FF,
+            vector_color: 0xFF4040FF,
+            anti_aliasing: true,
+            trails: false,
+            trail_fade: 0.15,
+            debug_vectors: false,
+        };
+        renderer.clear();
+        renderer
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; buffer_len(width, height)];
+        self.clear();
+    }
+
+    /**
+     * Pointer to the RGBA pixels (width * height * 4 bytes).
+     */
+    pub fn get_pixels_ptr(&self) -> *const u8 {
+        self.pixels.as_ptr()
+    }
+
+    pub fn get_pixels_len(&self) -> u32 {
+        self.pixels.len() as u32
+    }
+
+    /**
+     * A fresh `Uint8Array` over the RGBA pixels, without copying, e.g. for an
+     * `ImageData`. `resize` and wasm memory growth invalidate it, so keep it together
+     * with `get_pixels_generation` and make a new one when the generation changes.
+     */
+    pub fn get_pixels_view(&self) -> Uint8Array {
+        uint8_view(&self.pixels)
+    }
+
+    /**
+     * Bumped whenever the pixels moved since this was last asked.
+     */
+    pub fn get_pixels_generation(&self) -> u32 {
+        self.pixels_generation.current(&self.pixels)
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    pub fn set_boid_size(&mut self, boid_size: f32) {
+        self.boid_size = boid_size.max(0.0);
+    }
+
+    pub fn set_background(&mut self, color: u32) {
+        self.background = color;
+    }
+
+    pub fn set_boid_color(&mut self, color: u32) {
+        self.boid_color = color;
+    }
+
+    pub fn set_obstacle_color(&mut self, color: u32) {
+        self.obstacle_color = color;
+    }
+
+    pub fn set_vector_color(&mut self, color: u32) {
+        self.vector_color = color;
+    }
+
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: bool) {
+        self.anti_aliasing = anti_aliasing;
+    }
+
+    /**
+     * With trails on, the previous frame is faded towards the background by `fade`
+     * instead of being cleared, leaving streaks behind moving boids.
+     */
+    pub fn set_trails(&mut self, trails: bool, fade: f32) {
+        self.trails = trails;
+        self.trail_fade = fade.clamp(0.0, 1.0);
+    }
+
+    /**
+     * Draws each boid's velocity as a line.
+     */
+    pub fn set_debug_vectors(&mut self, debug_vectors: bool) {
+        self.debug_vectors = debug_vectors;
+    }
+
+    pub fn clear(&mut self) {
+        let color = unpack(self.background);
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    pub fn render(&mut self, orchestrator: &BoidOrchestrator) {
+        if self.trails {
+            self.fade(self.trail_fade);
+        } else {
+            self.clear();
+        }
+
+        for obstacle in orchestrator.obstacles() {
+            self.fill_circle(obstacle.center, obstacle.radius, self.obstacle_color);
+        }
+        for boid in orchestrator.boids() {
+            let heading = if boid.velocity.magnitude2() > f32::EPSILON {
+                boid.velocity.normalize()
+            } else {
+                Vector2::new(1.0, 0.0)
+            };
+            let side = Vector2::new(-heading.y, heading.x);
+            let size = self.boid_size * boid.traits.size;
+            let tip = boid.position + heading * (size * 0.6);
+            let left = boid.position - heading * (size * 0.4) + side * (size * 0.3);
+            let right = boid.position - heading * (size * 0.4) - side * (size * 0.3);
+            self.fill_triangle(tip, left, right, self.boid_color);
+
+            if self.debug_vectors {
+                self.draw_line(boid.position, boid.position + boid.velocity, 1.0, self.vector_color);
+            }
+        }
+    }
+}
+
+impl BoidRenderer {
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.index(x, y);
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    fn to_screen(&self, point: Vector2<f32>) -> Vector2<f32> {
+        point * self.scale
+    }
+
+    /**
+     * Moves every channel `amount` of the way to the background. Rounds towards the
+     * background, so a channel always moves at least one step and the fade finishes
+     * instead of stalling a few values short.
+     */
+    fn fade(&mut self, amount: f32) {
+        let background = unpack(self.background);
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            for (current, &target) in pixel.iter_mut().zip(&background) {
+                let faded = *current as f32 + (target as f32 - *current as f32) * amount;
+                *current = if target > *current { faded.ceil() } else { faded.floor() } as u8;
+            }
+        }
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: [u8; 4], coverage: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 || coverage <= 0.0 {
+            return;
+        }
+        let alpha = coverage.min(1.0) * color[3] as f32 / 255.0;
+        let i = self.index(x as u32, y as u32);
+        for (dst, &src) in self.pixels[i..i + 3].iter_mut().zip(&color) {
+            *dst = (*dst as f32 + (src as f32 - *dst as f32) * alpha).round() as u8;
+        }
+        let dst_alpha = self.pixels[i + 3] as f32;
+        self.pixels[i + 3] = (dst_alpha + (255.0 - dst_alpha) * alpha).round() as u8;
+    }
+
+    /**
+     * Turns a signed distance (negative inside the shape, in pixels) into pixel coverage.
+     */
+    fn coverage(&self, signed_distance: f32) -> f32 {
+        if self.anti_aliasing {
+            (0.5 - signed_distance).clamp(0.0, 1.0)
+        } else if signed_distance <= 0.0 {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * Calls `f` with every pixel center in the (clipped) bounding box.
+     */
+    fn for_each_pixel(&mut self, min: Vector2<f32>, max: Vector2<f32>, mut f: impl FnMut(&mut Self, i32, i32, Vector2<f32>)) {
+        if [min.x, min.y, max.x, max.y].iter().any(|value| value.is_nan()) {
+            return;
+        }
+        // Clipped while still floats, a shape far off screen would overflow as an i32.
+        let min_x = (min.x.floor() - 1.0).max(0.0) as i32;
+        let min_y = (min.y.floor() - 1.0).max(0.0) as i32;
+        let max_x = (max.x.ceil() + 1.0).min(self.width as f32 - 1.0) as i32;
+        let max_y = (max.y.ceil() + 1.0).min(self.height as f32 - 1.0) as i32;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                f(self, x, y, Vector2::new(x as f32 + 0.5, y as f32 + 0.5));
+            }
+        }
+    }
+
+    fn fill_triangle(&mut self, a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>, color: u32) {
+        let (a, b, c) = (self.to_screen(a), self.to_screen(b), self.to_screen(c));
+        // Make the winding consistent so "inside" is always on the same side of each edge.
+        let (b, c) = if cross(b - a, c - a) < 0.0 { (c, b) } else { (b, c) };
+        let color = unpack(color);
+        let min = Vector2::new(a.x.min(b.x).min(c.x), a.y.min(b.y).min(c.y));
+        let max = Vector2::new(a.x.max(b.x).max(c.x), a.y.max(b.y).max(c.y));
+        self.for_each_pixel(min, max, |renderer, x, y, p| {
+            let distance = edge_distance(a, b, p)
+                .max(edge_distance(b, c, p))
+                .max(edge_distance(c, a, p));
+            let coverage = renderer.coverage(distance);
+            renderer.blend(x, y, color, coverage);
+        });
+    }
+
+    fn fill_circle(&mut self, center: Vector2<f32>, radius: f32, color: u32) {
+        let center = self.to_screen(center);
+        let radius = radius * self.scale;
+        let color = unpack(color);
+        let extent = Vector2::new(radius, radius);
+        self.for_each_pixel(center - extent, center + extent, |renderer, x, y, p| {
+            let coverage = renderer.coverage((p - center).magnitude() - radius);
+            renderer.blend(x, y, color, coverage);
+        });
+    }
+
+    fn draw_line(&mut self, from: Vector2<f32>, to: Vector2<f32>, width: f32, color: u32) {
+        let (from, to) = (self.to_screen(from), self.to_screen(to));
+        let half_width = width * 0.5;
+        let color = unpack(color);
+        let min = Vector2::new(from.x.min(to.x), from.y.min(to.y)) - Vector2::new(half_width, half_width);
+        let max = Vector2::new(from.x.max(to.x), from.y.max(to.y)) + Vector2::new(half_width, half_width);
+        self.for_each_pixel(min, max, |renderer, x, y, p| {
+            let coverage = renderer.coverage(segment_distance(from, to, p) - half_width);
+            renderer.blend(x, y, color, coverage);
+        });
+    }
+}
+
+// In usize, since width * height * 4 overflows u32 for large canvases.
+fn buffer_len(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 4
+}
+
+fn unpack(color: u32) -> [u8; 4] {
+    color.to_be_bytes()
+}
+
+fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/**
+ * Signed distance from `p` to the line through a -> b, negative on the inside (left) of
+ * a counter-clockwise triangle.
+ */
+fn edge_distance(a: Vector2<f32>, b: Vector2<f32>, p: Vector2<f32>) -> f32 {
+    let edge = b - a;
+    let length = edge.magnitude();
+    if length < f32::EPSILON {
+        return 0.0;
+    }
+    -cross(edge, p - a) / length
+}
+
+fn segment_distance(a: Vector2<f32>, b: Vector2<f32>, p: Vector2<f32>) -> f32 {
+    let ab = b - a;
+    let length2 = ab.magnitude2();
+    let t = if length2 > 0.0 {
+        ((p - a).dot(ab) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t - p).magnitude()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::boid::Boid;
+    use crate::boids::settings::test_settings;
+
+    fn thumbnail_world() -> BoidOrchestrator {
+        let settings = test_settings(64, 32);
+        let boids = vec![
+            Boid::new(Vector2::new(16.0, 16.0), Vector2::new(1.0, 0.0), 0),
+            Boid::new(Vector2::new(48.0, 16.0), Vector2::new(0.0, 1.0), 1),
+        ];
+        BoidOrchestrator::from_boids(settings, boids, 1)
+    }
+
+    #[test]
+    fn renders_boids_over_the_background() {
+        let world = thumbnail_world();
+        let mut renderer = BoidRenderer::new(64, 32);
+        renderer.set_anti_aliasing(false);
+        renderer.render(&world);
+
+        assert_eq!(renderer.pixels().len(), 64 * 32 * 4);
+        assert_eq!(renderer.pixel(16, 16), [0xF0, 0xF0, 0xF0, 0xFF]);
+        assert_eq!(renderer.pixel(48, 16), [0xF0, 0xF0, 0xF0, 0xFF]);
+        assert_eq!(renderer.pixel(2, 2), [0x1E, 0x1E, 0x1E, 0xFF]);
+        // Pointing right: there's body behind the center but nothing far in front of it.
+        assert_eq!(renderer.pixel(22, 16), [0x1E, 0x1E, 0x1E, 0xFF]);
+
+        // Way off screen is clipped, not overflowed.
+        let mut world = thumbnail_world();
+        world.add_obstacle(3.0e9, 16.0, 10.0);
+        world.add_obstacle(-3.0e9, 16.0, 10.0);
+        renderer.render(&world);
+        assert_eq!(renderer.pixel(2, 2), [0x1E, 0x1E, 0x1E, 0xFF]);
+
+        let generation = renderer.get_pixels_generation();
+        assert_eq!(renderer.get_pixels_generation(), generation);
+        renderer.resize(32, 16);
+        assert_ne!(renderer.get_pixels_generation(), generation);
+    }
+
+    #[test]
+    fn anti_aliasing_blends_edges_and_scale_moves_boids() {
+        let world = thumbnail_world();
+        let mut renderer = BoidRenderer::new(128, 64);
+        renderer.set_scale(2.0);
+        renderer.render(&world);
+
+        assert_eq!(renderer.pixel(32, 32), [0xF0, 0xF0, 0xF0, 0xFF]);
+        let partially_covered = renderer
+            .pixels()
+            .chunks_exact(4)
+            .any(|pixel| pixel[0] > 0x1E && pixel[0] < 0xF0);
+        assert!(partially_covered);
+    }
+
+    #[test]
+    fn trails_fade_instead_of_clearing() {
+        let mut world = thumbnail_world();
+        let mut renderer = BoidRenderer::new(64, 32);
+        renderer.set_trails(true, 0.5);
+        renderer.render(&world);
+        let before = renderer.pixel(16, 16);
+        world.tick(4.0);
+        renderer.render(&world);
+        let after = renderer.pixel(16, 16);
+        assert!(after[0] < before[0] && after[0] > 0x1E);
+
+        // Even a slow fade gets all the way back to the background.
+        renderer.set_trails(true, 0.05);
+        world.tick(4.0);
+        for _ in 0..200 {
+            renderer.render(&world);
+        }
+        assert_eq!(renderer.pixel(16, 16), [0x1E, 0x1E, 0x1E, 0xFF]);
+    }
+}
@@ -0,0 +1,115 @@
+use cgmath::Vector2;
+
+use crate::boids::rng::SimRng;
+use crate::boids::settings::ResizeMode;
+
+/**
+ * Where a point at `position` in an `old_size` world ends up after the world becomes
+ * `new_size`.
+ */
+pub fn resize_position(
+    position: Vector2<f32>,
+    old_size: Vector2<f32>,
+    new_size: Vector2<f32>,
+    mode: ResizeMode,
+) -> Vector2<f32> {
+    match mode {
+        ResizeMode::Scale => Vector2::new(
+            scale_axis(position.x, old_size.x, new_size.x),
+            scale_axis(position.y, old_size.y, new_size.y),
+        ),
+        ResizeMode::Clamp => Vector2::new(
+            position.x.clamp(0.0, new_size.x),
+            position.y.clamp(0.0, new_size.y),
+        ),
+        ResizeMode::Wrap => Vector2::new(
+            wrap_axis(position.x, new_size.x),
+            wrap_axis(position.y, new_size.y),
+        ),
+        ResizeMode::Keep => position,
+    }
+}
+
+/**
+ * Clamp mode for boids: a coordinate past the edge lands somewhere within `margin` inside
+ * it instead of on it, so boids that were past the same corner don't end up stacked on
+ * one point. Coordinates already inside stay.
+ */
+pub fn clamp_inward(position: Vector2<f32>, new_size: Vector2<f32>, margin: f32, rng: &mut SimRng) -> Vector2<f32> {
+    let mut axis = |value: f32, size: f32| {
+        let margin = margin.min(size);
+        if value > size {
+            size - rng.range(0.0, margin)
+        } else if value < 0.0 {
+            rng.range(0.0, margin)
+        } else {
+            value
+        }
+    };
+    Vector2::new(axis(position.x, new_size.x), axis(position.y, new_size.y))
+}
+
+/**
+ * How much a length (an obstacle or food radius) grows with the world. Only scale mode
+ * stretches things, and by the smaller factor so circles don't swallow the new world.
+ */
+pub fn resize_length(length: f32, old_size: Vector2<f32>, new_size: Vector2<f32>, mode: ResizeMode) -> f32 {
+    match mode {
+        ResizeMode::Scale => {
+            let factor_x = if old_size.x > 0.0 { new_size.x / old_size.x } else { 1.0 };
+            let factor_y = if old_size.y > 0.0 { new_size.y / old_size.y } else { 1.0 };
+            length * factor_x.min(factor_y)
+        }
+        _ => length,
+    }
+}
+
+fn scale_axis(value: f32, old_size: f32, new_size: f32) -> f32 {
+    if old_size > 0.0 {
+        value * (new_size / old_size)
+    } else {
+        value
+    }
+}
+
+fn wrap_axis(value: f32, size: f32) -> f32 {
+    if size > 0.0 {
+        value.rem_euclid(size)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_mode_maps_points_into_the_new_world() {
+        let old_size = Vector2::new(400.0, 200.0);
+        let new_size = Vector2::new(200.0, 100.0);
+        let point = Vector2::new(300.0, 50.0);
+        let resize = |mode| resize_position(point, old_size, new_size, mode);
+
+        assert_eq!(resize(ResizeMode::Scale), Vector2::new(150.0, 25.0));
+        assert_eq!(resize(ResizeMode::Clamp), Vector2::new(200.0, 50.0));
+        assert_eq!(resize(ResizeMode::Wrap), Vector2::new(100.0, 50.0));
+        assert_eq!(resize(ResizeMode::Keep), point);
+        assert_eq!(resize_length(10.0, old_size, new_size, ResizeMode::Scale), 5.0);
+        assert_eq!(resize_length(10.0, old_size, new_size, ResizeMode::Wrap), 10.0);
+    }
+
+    #[test]
+    fn clamped_boids_spread_out_inside_the_edge() {
+        let mut rng = SimRng::new(3);
+        let new_size = Vector2::new(50.0, 25.0);
+        let points: Vec<Vector2<f32>> = (0..20)
+            .map(|_| clamp_inward(Vector2::new(300.0, 150.0), new_size, 10.0, &mut rng))
+            .collect();
+        for (i, point) in points.iter().enumerate() {
+            assert!(point.x > 40.0 && point.x <= 50.0 && point.y > 15.0 && point.y <= 25.0);
+            assert!(points[i + 1..].iter().all(|other| other != point));
+        }
+        assert_eq!(clamp_inward(Vector2::new(20.0, 150.0), new_size, 10.0, &mut rng).x, 20.0);
+    }
+}
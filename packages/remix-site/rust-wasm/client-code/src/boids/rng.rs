@@ -0,0 +1,60 @@
+/**
+ * Small seeded random number generator (splitmix64). Everything random in the boid world
+ * goes through this, so a simulation can be reproduced from its seed on any platform.
+ */
+#[derive(Clone, Debug)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u32) -> SimRng {
+        SimRng { state: seed as u64 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        splitmix64(&mut self.state)
+    }
+
+    /**
+     * Uniform float in [0, 1).
+     */
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /**
+     * Uniform float in [min, max).
+     */
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + self.next_f32() * (max - min)
+    }
+}
+
+/**
+ * One step of the splitmix64 generator. Cheap, and good enough to scramble seeds.
+ */
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/**
+ * A fresh seed for when the caller didn't ask for one.
+ */
+#[cfg(target_arch = "wasm32")]
+pub fn random_seed() -> u32 {
+    (js_sys::Math::random() * u32::MAX as f64) as u32
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn random_seed() -> u32 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() ^ duration.as_secs() as u32)
+        .unwrap_or(0)
+}
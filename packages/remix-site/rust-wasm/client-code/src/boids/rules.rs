@@ -0,0 +1,61 @@
+use cgmath::Vector2;
+
+/**
+ * What each rule added to a boid's velocity on one tick, already scaled by the rule's
+ * modifier from the world settings.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RuleVelocities {
+    pub cohesion: Vector2<f32>,
+    pub separation: Vector2<f32>,
+    pub alignment: Vector2<f32>,
+    pub border: Vector2<f32>,
+    pub flow_field: Vector2<f32>,
+    pub wander: Vector2<f32>,
+    pub path: Vector2<f32>,
+    pub formation: Vector2<f32>,
+}
+
+impl RuleVelocities {
+    pub const NAMES: [&'static str; 8] = [
+        "cohesion",
+        "separation",
+        "alignment",
+        "border",
+        "flow_field",
+        "wander",
+        "path",
+        "formation",
+    ];
+
+    /**
+     * The rule vectors in the same order as `NAMES`.
+     */
+    pub fn as_array(&self) -> [Vector2<f32>; 8] {
+        [
+            self.cohesion,
+            self.separation,
+            self.alignment,
+            self.border,
+            self.flow_field,
+            self.wander,
+            self.path,
+            self.formation,
+        ]
+    }
+
+    /**
+     * Adds every rule to `velocity`. The order is fixed so results are reproducible.
+     */
+    pub fn apply_to(&self, velocity: Vector2<f32>) -> Vector2<f32> {
+        velocity
+            + self.cohesion
+            + self.separation
+            + self.alignment
+            + self.border
+            + self.flow_field
+            + self.wander
+            + self.path
+            + self.formation
+    }
+}
@@ -1,8 +1,8 @@
-use cgmath::Vector2;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AvoidanceSettings {
     pub avoidance_range: f32,
     pub avoidance_modifier: f32,
@@ -27,8 +27,8 @@ impl AvoidanceSettings {
     }
 }
 
-impl AvoidanceSettings {
-    pub fn default() -> Self {
+impl Default for AvoidanceSettings {
+    fn default() -> Self {
         Self {
             avoidance_range: 25.0,
             avoidance_modifier: 1.0,
@@ -37,7 +37,7 @@ impl AvoidanceSettings {
 }
 
 #[wasm_bindgen]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PerceivedCenterSettings {
     pub p_center_modifier: f32,
 }
@@ -54,8 +54,8 @@ impl PerceivedCenterSettings {
     }
 }
 
-impl PerceivedCenterSettings {
-    pub fn default() -> Self {
+impl Default for PerceivedCenterSettings {
+    fn default() -> Self {
         Self {
             p_center_modifier: 1.0,
         }
@@ -63,7 +63,7 @@ impl PerceivedCenterSettings {
 }
 
 #[wasm_bindgen]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct VelocityMatchingSettings {
     pub velocity_matching_modifier: f32,
 }
@@ -82,8 +82,8 @@ impl VelocityMatchingSettings {
     }
 }
 
-impl VelocityMatchingSettings {
-    pub fn default() -> Self {
+impl Default for VelocityMatchingSettings {
+    fn default() -> Self {
         Self {
             velocity_matching_modifier: 1.0,
         }
@@ -91,7 +91,7 @@ impl VelocityMatchingSettings {
 }
 
 #[wasm_bindgen]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BorderConstraintSettings {
     pub border_constraint_modifier: f32,
 }
@@ -110,8 +110,8 @@ impl BorderConstraintSettings {
     }
 }
 
-impl BorderConstraintSettings {
-    pub fn default() -> Self {
+impl Default for BorderConstraintSettings {
+    fn default() -> Self {
         Self {
             border_constraint_modifier: 1.0,
         }
@@ -119,6 +119,413 @@ impl BorderConstraintSettings {
 }
 
 #[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlowFieldSettings {
+    pub flow_field_modifier: f32,
+}
+
+#[wasm_bindgen]
+impl FlowFieldSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(flow_field_modifier: f32) -> Self {
+        Self {
+            flow_field_modifier,
+        }
+    }
+
+    pub fn set_flow_field_modifier(&mut self, flow_field_modifier: f32) {
+        self.flow_field_modifier = flow_field_modifier;
+    }
+}
+
+impl Default for FlowFieldSettings {
+    fn default() -> Self {
+        Self {
+            flow_field_modifier: 1.0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WanderSettings {
+    pub wander_modifier: f32,
+    pub wander_frequency: f32,
+}
+
+#[wasm_bindgen]
+impl WanderSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(wander_modifier: f32, wander_frequency: f32) -> Self {
+        Self {
+            wander_modifier,
+            wander_frequency,
+        }
+    }
+
+    pub fn set_wander_modifier(&mut self, wander_modifier: f32) {
+        self.wander_modifier = wander_modifier;
+    }
+
+    pub fn set_wander_frequency(&mut self, wander_frequency: f32) {
+        self.wander_frequency = wander_frequency;
+    }
+}
+
+impl Default for WanderSettings {
+    fn default() -> Self {
+        // Off by default, so existing flocks behave the same until the slider is touched.
+        Self {
+            wander_modifier: 0.0,
+            wander_frequency: 0.5,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PathSettings {
+    pub path_following_modifier: f32,
+    pub leader_following_modifier: f32,
+    // How far behind its leader a follower aims to sit.
+    pub follow_distance: f32,
+    // Distance from a target at which followers start to slow down.
+    pub arrival_radius: f32,
+}
+
+#[wasm_bindgen]
+impl PathSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        path_following_modifier: f32,
+        leader_following_modifier: f32,
+        follow_distance: f32,
+        arrival_radius: f32,
+    ) -> Self {
+        Self {
+            path_following_modifier,
+            leader_following_modifier,
+            follow_distance,
+            arrival_radius,
+        }
+    }
+
+    pub fn set_path_following_modifier(&mut self, path_following_modifier: f32) {
+        self.path_following_modifier = path_following_modifier;
+    }
+
+    pub fn set_leader_following_modifier(&mut self, leader_following_modifier: f32) {
+        self.leader_following_modifier = leader_following_modifier;
+    }
+
+    pub fn set_follow_distance(&mut self, follow_distance: f32) {
+        self.follow_distance = follow_distance;
+    }
+
+    pub fn set_arrival_radius(&mut self, arrival_radius: f32) {
+        self.arrival_radius = arrival_radius;
+    }
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        Self {
+            path_following_modifier: 0.1,
+            leader_following_modifier: 0.05,
+            follow_distance: 30.0,
+            arrival_radius: 100.0,
+        }
+    }
+}
+
+/**
+ * How a boid picks the neighbors its flocking rules look at.
+ */
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NeighborMode {
+    // Every other boid in the world.
+    All = 0,
+    // Every boid within `perception_range`.
+    Metric = 1,
+    // The `k` closest boids, however far away (topological, like starlings).
+    KNearest = 2,
+}
+
+/**
+ * What happens to everything in the world when it's resized.
+ */
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeMode {
+    // Stretch every position with the world, so the flock keeps its layout.
+    Scale = 0,
+    // Move anything outside the new bounds onto the nearest edge.
+    Clamp = 1,
+    // Anything outside the new bounds re-enters from the opposite side.
+    Wrap = 2,
+    // Leave positions alone, the border rule brings boids back in over time.
+    Keep = 3,
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NeighborSettings {
+    pub mode: NeighborMode,
+    pub perception_range: f32,
+    pub k: u32,
+}
+
+#[wasm_bindgen]
+impl NeighborSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(mode: NeighborMode, perception_range: f32, k: u32) -> Self {
+        Self {
+            mode,
+            perception_range,
+            k,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: NeighborMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_perception_range(&mut self, perception_range: f32) {
+        self.perception_range = perception_range;
+    }
+
+    pub fn set_k(&mut self, k: u32) {
+        self.k = k;
+    }
+}
+
+impl Default for NeighborSettings {
+    fn default() -> Self {
+        Self {
+            mode: NeighborMode::All,
+            perception_range: 100.0,
+            k: 7,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionSettings {
+    pub enabled: bool,
+    // Radius used for boids that don't have their own.
+    pub boid_radius: f32,
+    // More iterations settle dense piles better, at the cost of time.
+    pub iterations: u32,
+    // Also collide with the edges of the world instead of only steering back in.
+    pub walls: bool,
+}
+
+#[wasm_bindgen]
+impl CollisionSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(enabled: bool, boid_radius: f32, iterations: u32, walls: bool) -> Self {
+        Self {
+            enabled,
+            boid_radius,
+            iterations,
+            walls,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_boid_radius(&mut self, boid_radius: f32) {
+        self.boid_radius = boid_radius;
+    }
+
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.iterations = iterations;
+    }
+
+    pub fn set_walls(&mut self, walls: bool) {
+        self.walls = walls;
+    }
+}
+
+impl Default for CollisionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            boid_radius: 4.0,
+            iterations: 2,
+            walls: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EcosystemSettings {
+    pub enabled: bool,
+    // Energy every boid burns per second, moving or not.
+    pub base_drain: f32,
+    // Extra energy burned per second for each unit of speed.
+    pub speed_drain: f32,
+    // Energy a boid can take from a food patch per second.
+    pub eat_rate: f32,
+    // A boid with this much energy splits in two.
+    pub reproduction_energy: f32,
+    // How far (as a fraction) a child's traits can drift from its parent's.
+    pub mutation: f32,
+    // No more births once the flock is this big.
+    pub max_population: u32,
+}
+
+#[wasm_bindgen]
+impl EcosystemSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        enabled: bool,
+        base_drain: f32,
+        speed_drain: f32,
+        eat_rate: f32,
+        reproduction_energy: f32,
+        mutation: f32,
+        max_population: u32,
+    ) -> Self {
+        Self {
+            enabled,
+            base_drain,
+            speed_drain,
+            eat_rate,
+            reproduction_energy,
+            mutation,
+            max_population,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_base_drain(&mut self, base_drain: f32) {
+        self.base_drain = base_drain;
+    }
+
+    pub fn set_speed_drain(&mut self, speed_drain: f32) {
+        self.speed_drain = speed_drain;
+    }
+
+    pub fn set_eat_rate(&mut self, eat_rate: f32) {
+        self.eat_rate = eat_rate;
+    }
+
+    pub fn set_reproduction_energy(&mut self, reproduction_energy: f32) {
+        self.reproduction_energy = reproduction_energy;
+    }
+
+    pub fn set_mutation(&mut self, mutation: f32) {
+        self.mutation = mutation;
+    }
+
+    pub fn set_max_population(&mut self, max_population: u32) {
+        self.max_population = max_population;
+    }
+}
+
+impl Default for EcosystemSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            base_drain: 1.0,
+            speed_drain: 0.05,
+            eat_rate: 20.0,
+            reproduction_energy: 100.0,
+            mutation: 0.05,
+            max_population: 2000,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraitSettings {
+    // How far each trait of a newly spawned boid can be from 1.0, as a fraction.
+    // 0 gives every boid the same traits.
+    pub spread: f32,
+}
+
+#[wasm_bindgen]
+impl TraitSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(spread: f32) -> Self {
+        Self { spread }
+    }
+
+    pub fn set_spread(&mut self, spread: f32) {
+        self.spread = spread;
+    }
+}
+
+impl Default for TraitSettings {
+    fn default() -> Self {
+        Self { spread: 0.0 }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FormationSettings {
+    // 0 is free flocking, 1 flies straight into formation. Cohesion, alignment, wander
+    // and path following fade out as it goes up, separation stays.
+    #[serde(deserialize_with = "deserialize_blend")]
+    pub blend: f32,
+    pub formation_modifier: f32,
+    // Distance from its target at which a boid starts slowing down.
+    pub arrival_radius: f32,
+}
+
+#[wasm_bindgen]
+impl FormationSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new(blend: f32, formation_modifier: f32, arrival_radius: f32) -> Self {
+        Self {
+            blend: blend.clamp(0.0, 1.0),
+            formation_modifier,
+            arrival_radius,
+        }
+    }
+
+    pub fn set_blend(&mut self, blend: f32) {
+        self.blend = blend.clamp(0.0, 1.0);
+    }
+
+    pub fn set_formation_modifier(&mut self, formation_modifier: f32) {
+        self.formation_modifier = formation_modifier;
+    }
+
+    pub fn set_arrival_radius(&mut self, arrival_radius: f32) {
+        self.arrival_radius = arrival_radius;
+    }
+}
+
+fn deserialize_blend<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    f32::deserialize(deserializer).map(|blend| blend.clamp(0.0, 1.0))
+}
+
+impl Default for FormationSettings {
+    fn default() -> Self {
+        Self {
+            blend: 0.0,
+            formation_modifier: 0.5,
+            arrival_radius: 60.0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WorldSettings {
     // pub world_size: Vector2<u32>,
     pub world_width: u32,
@@ -128,11 +535,20 @@ pub struct WorldSettings {
     pub pc: PerceivedCenterSettings,
     pub velocity_matching: VelocityMatchingSettings,
     pub border_constraint: BorderConstraintSettings,
+    pub flow_field: FlowFieldSettings,
+    pub wander: WanderSettings,
+    pub path: PathSettings,
+    pub neighbors: NeighborSettings,
+    pub collision: CollisionSettings,
+    pub ecosystem: EcosystemSettings,
+    pub traits: TraitSettings,
+    pub formation: FormationSettings,
 }
 
 #[wasm_bindgen]
 impl WorldSettings {
     #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         world_width: u32,
         world_height: u32,
@@ -161,6 +577,14 @@ impl WorldSettings {
             pc,
             velocity_matching,
             border_constraint,
+            flow_field: FlowFieldSettings::default(),
+            wander: WanderSettings::default(),
+            path: PathSettings::default(),
+            neighbors: NeighborSettings::default(),
+            collision: CollisionSettings::default(),
+            ecosystem: EcosystemSettings::default(),
+            traits: TraitSettings::default(),
+            formation: FormationSettings::default(),
         }
     }
 
@@ -196,6 +620,111 @@ impl WorldSettings {
     pub fn set_border_constraint_modifier(&mut self, modifier: f32) {
         self.border_constraint.set_border_constraint_modifier(modifier);
     }
+
+    // Setter for flow field modifier
+    pub fn set_flow_field_modifier(&mut self, modifier: f32) {
+        self.flow_field.set_flow_field_modifier(modifier);
+    }
+
+    // Setters for wander settings
+    pub fn set_wander_modifier(&mut self, modifier: f32) {
+        self.wander.set_wander_modifier(modifier);
+    }
+
+    pub fn set_wander_frequency(&mut self, frequency: f32) {
+        self.wander.set_wander_frequency(frequency);
+    }
+
+    // Setters for path and leader following
+    pub fn set_path_following_modifier(&mut self, modifier: f32) {
+        self.path.set_path_following_modifier(modifier);
+    }
+
+    pub fn set_leader_following_modifier(&mut self, modifier: f32) {
+        self.path.set_leader_following_modifier(modifier);
+    }
+
+    pub fn set_follow_distance(&mut self, distance: f32) {
+        self.path.set_follow_distance(distance);
+    }
+
+    pub fn set_arrival_radius(&mut self, radius: f32) {
+        self.path.set_arrival_radius(radius);
+    }
+
+    // Setters for neighbor selection
+    pub fn set_neighbor_mode(&mut self, mode: NeighborMode) {
+        self.neighbors.set_mode(mode);
+    }
+
+    pub fn set_perception_range(&mut self, range: f32) {
+        self.neighbors.set_perception_range(range);
+    }
+
+    pub fn set_k_nearest(&mut self, k: u32) {
+        self.neighbors.set_k(k);
+    }
+
+    // Setters for collision settings
+    pub fn set_collisions_enabled(&mut self, enabled: bool) {
+        self.collision.set_enabled(enabled);
+    }
+
+    pub fn set_boid_radius(&mut self, radius: f32) {
+        self.collision.set_boid_radius(radius);
+    }
+
+    pub fn set_collision_iterations(&mut self, iterations: u32) {
+        self.collision.set_iterations(iterations);
+    }
+
+    pub fn set_wall_collisions(&mut self, walls: bool) {
+        self.collision.set_walls(walls);
+    }
+
+    // Setters for ecosystem settings
+    pub fn set_ecosystem_enabled(&mut self, enabled: bool) {
+        self.ecosystem.set_enabled(enabled);
+    }
+
+    pub fn set_energy_drain(&mut self, base_drain: f32, speed_drain: f32) {
+        self.ecosystem.set_base_drain(base_drain);
+        self.ecosystem.set_speed_drain(speed_drain);
+    }
+
+    pub fn set_eat_rate(&mut self, eat_rate: f32) {
+        self.ecosystem.set_eat_rate(eat_rate);
+    }
+
+    pub fn set_reproduction_energy(&mut self, energy: f32) {
+        self.ecosystem.set_reproduction_energy(energy);
+    }
+
+    pub fn set_mutation(&mut self, mutation: f32) {
+        self.ecosystem.set_mutation(mutation);
+    }
+
+    pub fn set_max_population(&mut self, max_population: u32) {
+        self.ecosystem.set_max_population(max_population);
+    }
+
+    // Setters for trait settings
+    pub fn set_trait_spread(&mut self, spread: f32) {
+        self.traits.set_spread(spread);
+    }
+
+    // Setters for formation settings
+    pub fn set_formation_blend(&mut self, blend: f32) {
+        self.formation.set_blend(blend);
+    }
+
+    pub fn set_formation_modifier(&mut self, modifier: f32) {
+        self.formation.set_formation_modifier(modifier);
+    }
+
+    pub fn set_formation_arrival_radius(&mut self, radius: f32) {
+        self.formation.set_arrival_radius(radius);
+    }
 }
 
 impl WorldSettings {
@@ -208,6 +737,22 @@ impl WorldSettings {
             pc: PerceivedCenterSettings::default(),
             velocity_matching: VelocityMatchingSettings::default(),
             border_constraint: BorderConstraintSettings::default(),
+            flow_field: FlowFieldSettings::default(),
+            wander: WanderSettings::default(),
+            path: PathSettings::default(),
+            neighbors: NeighborSettings::default(),
+            collision: CollisionSettings::default(),
+            ecosystem: EcosystemSettings::default(),
+            traits: TraitSettings::default(),
+            formation: FormationSettings::default(),
         }
     }
 }
+
+/**
+ * The settings the tests run with, in a `world_width` x `world_height` world.
+ */
+#[cfg(test)]
+pub(crate) fn test_settings(world_width: u32, world_height: u32) -> WorldSettings {
+    WorldSettings::new(world_width, world_height, 15.0, 3.0, 0.05, 25.0, 0.02, 0.15)
+}
@@ -0,0 +1,298 @@
+use cgmath::{InnerSpace, Vector2};
+
+use crate::boids::boid::Boid;
+
+/**
+ * Uniform grid over the boids, rebuilt every tick. Stores indices into the boid slice it
+ * was built from, bucketed by cell with a counting sort so lookups don't allocate.
+ */
+#[derive(Clone, Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    min: Vector2<f32>,
+    columns: usize,
+    rows: usize,
+    // cell_starts[c]..cell_starts[c + 1] is the range of `entries` in cell c.
+    cell_starts: Vec<usize>,
+    entries: Vec<usize>,
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        SpatialGrid::new()
+    }
+}
+
+impl SpatialGrid {
+    pub fn new() -> SpatialGrid {
+        SpatialGrid {
+            cell_size: 1.0,
+            min: Vector2::new(0.0, 0.0),
+            columns: 0,
+            rows: 0,
+            cell_starts: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /**
+     * Rebuckets `boids`. The grid covers their bounding box, and the cell size is grown if
+     * a few stray boids would otherwise blow up the number of cells. Boids with a
+     * non-finite position don't count towards the box, they end up in a corner cell.
+     */
+    pub fn build(&mut self, boids: &[Boid], cell_size: f32) {
+        self.entries.clear();
+        self.cell_starts.clear();
+        if boids.is_empty() {
+            self.columns = 0;
+            self.rows = 0;
+            return;
+        }
+
+        let mut bounds: Option<(Vector2<f32>, Vector2<f32>)> = None;
+        for boid in boids {
+            let p = boid.position;
+            if !p.x.is_finite() || !p.y.is_finite() {
+                continue;
+            }
+            bounds = Some(match bounds {
+                Some((min, max)) => (
+                    Vector2::new(min.x.min(p.x), min.y.min(p.y)),
+                    Vector2::new(max.x.max(p.x), max.y.max(p.y)),
+                ),
+                None => (p, p),
+            });
+        }
+        let (min, max) = bounds.unwrap_or((Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0)));
+        let mut cell_size = cell_size.max(1.0);
+        let max_cells = boids.len() * 4 + 64;
+        loop {
+            // Spans near f32::MAX saturate the cast, so the + 1 has to as well.
+            let columns = (((max.x - min.x) / cell_size) as usize).saturating_add(1);
+            let rows = (((max.y - min.y) / cell_size) as usize).saturating_add(1);
+            if columns.saturating_mul(rows) <= max_cells {
+                self.columns = columns;
+                self.rows = rows;
+                break;
+            }
+            cell_size *= 2.0;
+        }
+        self.cell_size = cell_size;
+        self.min = min;
+
+        let cell_count = self.columns * self.rows;
+        self.cell_starts.resize(cell_count + 1, 0);
+        for boid in boids {
+            let cell = self.cell_index(boid.position);
+            self.cell_starts[cell + 1] += 1;
+        }
+        for cell in 0..cell_count {
+            self.cell_starts[cell + 1] += self.cell_starts[cell];
+        }
+        self.entries.resize(boids.len(), 0);
+        let mut next_slot = self.cell_starts.clone();
+        for (index, boid) in boids.iter().enumerate() {
+            let cell = self.cell_index(boid.position);
+            self.entries[next_slot[cell]] = index;
+            next_slot[cell] += 1;
+        }
+    }
+
+    fn cell_coords(&self, position: Vector2<f32>) -> (usize, usize) {
+        let column = ((position.x - self.min.x) / self.cell_size).max(0.0) as usize;
+        let row = ((position.y - self.min.y) / self.cell_size).max(0.0) as usize;
+        (column.min(self.columns - 1), row.min(self.rows - 1))
+    }
+
+    fn cell_index(&self, position: Vector2<f32>) -> usize {
+        let (column, row) = self.cell_coords(position);
+        row * self.columns + column
+    }
+
+    fn cell(&self, column: usize, row: usize) -> &[usize] {
+        let cell = row * self.columns + column;
+        &self.entries[self.cell_starts[cell]..self.cell_starts[cell + 1]]
+    }
+
+    /**
+     * Calls `f` with the index of every boid within `radius` of `position`, in a stable
+     * order (cell by cell, then insertion order).
+     */
+    pub fn for_each_in_radius(
+        &self,
+        boids: &[Boid],
+        position: Vector2<f32>,
+        radius: f32,
+        mut f: impl FnMut(usize),
+    ) {
+        if self.columns == 0 {
+            return;
+        }
+        let radius2 = radius * radius;
+        let (min_column, min_row) = self.cell_coords(position - Vector2::new(radius, radius));
+        let (max_column, max_row) = self.cell_coords(position + Vector2::new(radius, radius));
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                for &index in self.cell(column, row) {
+                    if (boids[index].position - position).magnitude2() <= radius2 {
+                        f(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Calls `f` with the index of every boid whose position is inside the rectangle.
+     */
+    pub fn for_each_in_rect(
+        &self,
+        boids: &[Boid],
+        min: Vector2<f32>,
+        max: Vector2<f32>,
+        mut f: impl FnMut(usize),
+    ) {
+        if self.columns == 0 {
+            return;
+        }
+        let (min_column, min_row) = self.cell_coords(min);
+        let (max_column, max_row) = self.cell_coords(max);
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                for &index in self.cell(column, row) {
+                    let p = boids[index].position;
+                    if p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y {
+                        f(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * The `k` boids closest to `position`, nearest first, skipping `exclude`. Searches the
+     * grid in growing rings of cells and stops as soon as no unvisited cell can hold
+     * anything closer than the current k-th candidate.
+     */
+    pub fn k_nearest(
+        &self,
+        boids: &[Boid],
+        position: Vector2<f32>,
+        k: usize,
+        exclude: Option<usize>,
+    ) -> Vec<usize> {
+        // (distance squared, index), kept sorted.
+        let mut best: Vec<(f32, usize)> = Vec::with_capacity(k + 1);
+        if self.columns == 0 || k == 0 {
+            return Vec::new();
+        }
+        let (center_column, center_row) = self.cell_coords(position);
+        let max_ring = self.columns.max(self.rows);
+        for ring in 0..=max_ring {
+            let min_column = center_column as isize - ring as isize;
+            let max_column = center_column as isize + ring as isize;
+            let min_row = center_row as isize - ring as isize;
+            let max_row = center_row as isize + ring as isize;
+            for row in min_row..=max_row {
+                if row < 0 || row >= self.rows as isize {
+                    continue;
+                }
+                for column in min_column..=max_column {
+                    if column < 0 || column >= self.columns as isize {
+                        continue;
+                    }
+                    let on_ring = row == min_row || row == max_row || column == min_column || column == max_column;
+                    if !on_ring {
+                        continue;
+                    }
+                    for &index in self.cell(column as usize, row as usize) {
+                        if Some(index) == exclude {
+                            continue;
+                        }
+                        let distance = (boids[index].position - position).magnitude2();
+                        if best.len() == k && distance >= best[k - 1].0 {
+                            continue;
+                        }
+                        let slot = best
+                            .iter()
+                            .position(|&(d, i)| distance < d || (distance == d && index < i))
+                            .unwrap_or(best.len());
+                        best.insert(slot, (distance, index));
+                        best.truncate(k);
+                    }
+                }
+            }
+            // Everything in the next ring is at least `ring` cells away.
+            let reach = ring as f32 * self.cell_size;
+            if best.len() == k && best[k - 1].0 <= reach * reach {
+                break;
+            }
+        }
+        best.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_of_boids() -> Vec<Boid> {
+        (0..100)
+            .map(|i| {
+                Boid::new(
+                    Vector2::new((i % 10) as f32 * 10.0, (i / 10) as f32 * 10.0),
+                    Vector2::new(0.0, 0.0),
+                    i,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn k_nearest_matches_a_full_sort() {
+        let boids = grid_of_boids();
+        let mut grid = SpatialGrid::new();
+        grid.build(&boids, 15.0);
+        for (query, k) in [(Vector2::new(33.0, 41.0), 7), (Vector2::new(-50.0, 200.0), 5), (Vector2::new(90.0, 0.0), 12)] {
+            let mut expected: Vec<usize> = (0..boids.len()).collect();
+            expected.sort_by(|&a, &b| {
+                let da = (boids[a].position - query).magnitude2();
+                let db = (boids[b].position - query).magnitude2();
+                da.total_cmp(&db).then(a.cmp(&b))
+            });
+            expected.truncate(k);
+            assert_eq!(grid.k_nearest(&boids, query, k, None), expected);
+        }
+    }
+
+    #[test]
+    fn non_finite_positions_dont_size_the_grid() {
+        let mut boids = grid_of_boids();
+        boids[3].position = Vector2::new(f32::INFINITY, 0.0);
+        boids[4].position = Vector2::new(f32::NAN, f32::NEG_INFINITY);
+        boids[5].position = Vector2::new(-f32::MAX, f32::MAX);
+        boids[6].position = Vector2::new(f32::MAX, 0.0);
+        let mut grid = SpatialGrid::new();
+        grid.build(&boids, 15.0);
+        assert!(grid.columns * grid.rows <= boids.len() * 4 + 64);
+        let mut found = Vec::new();
+        grid.for_each_in_radius(&boids, Vector2::new(50.0, 50.0), 10.0, |i| found.push(i));
+        found.sort();
+        assert_eq!(found, vec![45, 54, 55, 56, 65]);
+    }
+
+    #[test]
+    fn radius_query_finds_only_boids_in_range() {
+        let boids = grid_of_boids();
+        let mut grid = SpatialGrid::new();
+        grid.build(&boids, 15.0);
+        let mut found = Vec::new();
+        grid.for_each_in_radius(&boids, Vector2::new(50.0, 50.0), 10.0, |i| found.push(i));
+        found.sort();
+        assert_eq!(found, vec![45, 54, 55, 56, 65]);
+    }
+}
@@ -0,0 +1,281 @@
+use cgmath::InnerSpace;
+use std::fmt::Write;
+use wasm_bindgen::prelude::*;
+
+use crate::boids::orchestrator::BoidOrchestrator;
+
+/**
+ * Options for `BoidOrchestrator::to_svg`. A view width or height of 0 means "the whole
+ * world". Colors are 0xRRGGBBAA, like the `BoidRenderer`.
+ */
+#[wasm_bindgen]
+#[derive(Copy, Clone)]
+pub struct SvgOptions {
+    pub view_x: f32,
+    pub view_y: f32,
+    pub view_width: f32,
+    pub view_height: f32,
+    // Output size in pixels per world unit.
+    pub scale: f32,
+    pub boid_size: f32,
+    pub background: u32,
+    pub boid_color: u32,
+    pub leader_color: u32,
+    pub obstacle_color: u32,
+    pub path_color: u32,
+    pub trail_color: u32,
+    pub include_obstacles: bool,
+    pub include_path: bool,
+    pub include_trails: bool,
+}
+
+#[wasm_bindgen]
+impl SvgOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SvgOptions {
+        SvgOptions {
+            view_x: 0.0,
+            view_y: 0.0,
+            view_width: 0.0,
+            view_height: 0.0,
+            scale: 1.0,
+            boid_size: 8.0,
+            background: 0x1E1E1EFF,
+            boid_color: 0xF0F0F0FF,
+            leader_color: 0xFFB020FF,
+            obstacle_color: 0x806060FF,
+            path_color: 0x4080FFFF,
+            trail_color: 0xF0F0F060,
+            include_obstacles: true,
+            include_path: true,
+            include_trails: true,
+        }
+    }
+
+    pub fn set_view_box(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.view_x = x;
+        self.view_y = y;
+        self.view_width = width;
+        self.view_height = height;
+    }
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions::new()
+    }
+}
+
+/**
+ * Writes the current frame as a standalone SVG document.
+ */
+pub fn frame_to_svg(orchestrator: &BoidOrchestrator, options: &SvgOptions) -> String {
+    let settings = orchestrator.world_settings();
+    let (view_width, view_height) = if options.view_width > 0.0 && options.view_height > 0.0 {
+        (options.view_width, options.view_height)
+    } else {
+        (settings.world_width as f32, settings.world_height as f32)
+    };
+    let mut svg = String::new();
+    // Writing into a String can't fail, so the fmt::Results are ignored below.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        number(view_width * options.scale),
+        number(view_height * options.scale),
+        number(options.view_x),
+        number(options.view_y),
+        number(view_width),
+        number(view_height),
+    );
+    let size = options.boid_size;
+    let _ = writeln!(
+        svg,
+        r#"<defs><path id="boid" d="M{} 0 L{} {} L{} {} Z"/></defs>"#,
+        number(size * 0.6),
+        number(-size * 0.4),
+        number(size * 0.3),
+        number(-size * 0.4),
+        number(-size * 0.3),
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+        number(options.view_x),
+        number(options.view_y),
+        number(view_width),
+        number(view_height),
+        fill(options.background),
+    );
+
+    if options.include_obstacles {
+        for obstacle in orchestrator.obstacles() {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                number(obstacle.center.x),
+                number(obstacle.center.y),
+                number(obstacle.radius),
+                fill(options.obstacle_color),
+            );
+        }
+    }
+
+    let path = orchestrator.get_path_points();
+    if options.include_path && path.len() >= 4 {
+        let points: Vec<String> = path
+            .chunks_exact(2)
+            .map(|point| format!("{},{}", number(point[0]), number(point[1])))
+            .collect();
+        let element = if orchestrator.is_path_looping() {
+            "polygon"
+        } else {
+            "polyline"
+        };
+        let _ = writeln!(
+            svg,
+            r#"<{} points="{}" fill="none" {} stroke-width="1"/>"#,
+            element,
+            points.join(" "),
+            stroke(options.path_color),
+        );
+    }
+
+    if options.include_trails {
+        let _ = writeln!(svg, r#"<g fill="none" {} stroke-width="1">"#, stroke(options.trail_color));
+        for boid in orchestrator.boids() {
+            if let Some(trail) = orchestrator.trails().trail(boid.id) {
+                if trail.len() < 2 {
+                    continue;
+                }
+                let points: Vec<String> = trail
+                    .iter()
+                    .map(|point| format!("{},{}", number(point.x), number(point.y)))
+                    .collect();
+                let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points.join(" "));
+            }
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    let _ = writeln!(svg, r#"<g {}>"#, fill(options.boid_color));
+    for boid in orchestrator.boids() {
+        let angle = if boid.velocity.magnitude2() > f32::EPSILON {
+            boid.velocity.y.atan2(boid.velocity.x).to_degrees()
+        } else {
+            0.0
+        };
+        let leader_fill = if orchestrator.is_leader(boid.id) {
+            format!(" {}", fill(options.leader_color))
+        } else {
+            String::new()
+        };
+        // Boids with a size trait get scaled, the rest share the plain transform.
+        let scale = if boid.traits.size != 1.0 {
+            format!(" scale({})", number(boid.traits.size))
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            svg,
+            r##"<use href="#boid" transform="translate({} {}) rotate({}){}"{}/>"##,
+            number(boid.position.x),
+            number(boid.position.y),
+            number(angle),
+            scale,
+            leader_fill,
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/**
+ * Two decimals is plenty at screen scale, and trailing zeros are trimmed to keep the
+ * document small.
+ */
+fn number(value: f32) -> String {
+    let formatted = format!("{:.2}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn color_attributes(name: &str, color: u32) -> String {
+    let [r, g, b, a] = color.to_be_bytes();
+    if a == 0xFF {
+        format!(r##"{}="#{:02x}{:02x}{:02x}""##, name, r, g, b)
+    } else {
+        format!(
+            r##"{}="#{:02x}{:02x}{:02x}" {}-opacity="{}""##,
+            name,
+            r,
+            g,
+            b,
+            name,
+            number(a as f32 / 255.0)
+        )
+    }
+}
+
+fn fill(color: u32) -> String {
+    color_attributes("fill", color)
+}
+
+fn stroke(color: u32) -> String {
+    color_attributes("stroke", color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::boid::Boid;
+    use crate::boids::settings::test_settings;
+    use cgmath::Vector2;
+
+    #[test]
+    fn exports_a_standalone_document() {
+        let settings = test_settings(200, 100);
+        let boids = vec![
+            Boid::new(Vector2::new(20.0, 30.0), Vector2::new(0.0, 2.0), 0),
+            Boid::new(Vector2::new(150.0, 60.0), Vector2::new(-1.0, 0.0), 1),
+        ];
+        let mut world = BoidOrchestrator::from_boids(settings, boids, 1);
+        world.add_obstacle(100.0, 50.0, 10.0);
+        world.set_path(&[0.0, 0.0, 200.0, 100.0], false);
+        world.promote_leader(1);
+        world.set_trail_length(4);
+        world.tick(0.5);
+        world.tick(0.5);
+
+        let mut options = SvgOptions::new();
+        options.scale = 2.0;
+        let svg = frame_to_svg(&world, &options);
+
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="400" height="200" viewBox="0 0 200 100">"#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches(r##"<use href="#boid""##).count(), 2);
+        assert!(svg.contains(r##"<circle cx="100" cy="50" r="10" fill="#806060"/>"##));
+        assert!(svg.contains(r#"<polyline points="0,0 200,100""#));
+        assert!(svg.contains(r##"fill="#ffb020"/>"##));
+        assert_eq!(svg.matches("<polyline").count(), 3);
+
+        options.set_view_box(50.0, 25.0, 100.0, 50.0);
+        options.include_trails = false;
+        let cropped = frame_to_svg(&world, &options);
+        assert!(cropped.contains(r#"viewBox="50 25 100 50""#));
+        assert_eq!(cropped.matches("<polyline").count(), 1);
+    }
+
+    #[test]
+    fn numbers_are_compact() {
+        assert_eq!(number(1.0), "1");
+        assert_eq!(number(1.5), "1.5");
+        assert_eq!(number(-0.001), "0");
+        assert_eq!(number(12.3456), "12.35");
+    }
+}
@@ -0,0 +1,74 @@
+use cgmath::Vector2;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::boids::boid::Boid;
+
+/**
+ * The last few positions of every boid, oldest first. Only recorded while `length` is
+ * above zero, and boids that no longer exist are dropped on the next record.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TrailHistory {
+    length: usize,
+    trails: BTreeMap<u32, VecDeque<Vector2<f32>>>,
+}
+
+impl TrailHistory {
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length;
+        if length == 0 {
+            self.trails.clear();
+        }
+        for trail in self.trails.values_mut() {
+            while trail.len() > length {
+                trail.pop_front();
+            }
+        }
+    }
+
+    pub fn record(&mut self, boids: &[Boid]) {
+        if self.length == 0 {
+            return;
+        }
+        // Checked every time rather than only when there are more trails than boids, a
+        // death and a birth in the same tick leave the count unchanged.
+        let alive: HashSet<u32> = boids.iter().map(|boid| boid.id).collect();
+        self.trails.retain(|id, _| alive.contains(id));
+        for boid in boids {
+            let trail = self.trails.entry(boid.id).or_default();
+            if trail.len() == self.length {
+                trail.pop_front();
+            }
+            trail.push_back(boid.position);
+        }
+    }
+
+    pub fn trail(&self, boid_id: u32) -> Option<&VecDeque<Vector2<f32>>> {
+        self.trails.get(&boid_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.trails.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trails_of_removed_boids_are_dropped_even_when_others_replace_them() {
+        let boid = |id: u32, x: f32| Boid::new(Vector2::new(x, 0.0), Vector2::new(0.0, 0.0), id);
+        let mut trails = TrailHistory::default();
+        trails.set_length(3);
+        trails.record(&[boid(0, 0.0), boid(1, 0.0)]);
+        trails.record(&[boid(0, 1.0), boid(2, 5.0)]);
+        assert!(trails.trail(1).is_none());
+        assert_eq!(trails.trail(0).unwrap().len(), 2);
+        assert_eq!(trails.trail(2).unwrap(), &VecDeque::from(vec![Vector2::new(5.0, 0.0)]));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use crate::boids::boid::Boid;
+use crate::boids::rng::SimRng;
+use crate::boids::utils::LinearSerializable;
+
+/**
+ * Per-boid multipliers on the world settings, so a flock isn't made of identical
+ * boids. All 1.0 means the boid behaves exactly as the settings say.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoidTraits {
+    // On the velocity limit.
+    pub max_speed: f32,
+    // On the perception range, used by the metric neighbor mode.
+    pub perception: f32,
+    // On the avoidance range.
+    pub separation_distance: f32,
+    // On the collision radius, and how big renderers should draw the boid.
+    pub size: f32,
+    // On the four flocking rule modifiers.
+    pub cohesion: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub border: f32,
+}
+
+impl Default for BoidTraits {
+    fn default() -> Self {
+        BoidTraits {
+            max_speed: 1.0,
+            perception: 1.0,
+            separation_distance: 1.0,
+            size: 1.0,
+            cohesion: 1.0,
+            separation: 1.0,
+            alignment: 1.0,
+            border: 1.0,
+        }
+    }
+}
+
+impl BoidTraits {
+    // Keeps mutated and randomized traits from flipping a rule around or freezing a boid.
+    const MIN_MULTIPLIER: f32 = 0.1;
+
+    /**
+     * Every multiplier drawn uniformly from [1 - spread, 1 + spread]. A spread of 0
+     * doesn't touch the rng, so worlds without traits stay as they were.
+     */
+    pub fn random(spread: f32, rng: &mut SimRng) -> BoidTraits {
+        if spread <= 0.0 {
+            return BoidTraits::default();
+        }
+        BoidTraits::default().mutated(spread, rng)
+    }
+
+    /**
+     * A copy with each multiplier scaled by a random factor in [1 - amount, 1 + amount].
+     */
+    pub fn mutated(&self, amount: f32, rng: &mut SimRng) -> BoidTraits {
+        let mut values = self.as_array();
+        for value in &mut values {
+            *value = (*value * (1.0 + rng.range(-amount, amount))).max(BoidTraits::MIN_MULTIPLIER);
+        }
+        BoidTraits::from_array(values)
+    }
+
+    pub fn as_array(&self) -> [f32; 8] {
+        [
+            self.max_speed,
+            self.perception,
+            self.separation_distance,
+            self.size,
+            self.cohesion,
+            self.separation,
+            self.alignment,
+            self.border,
+        ]
+    }
+
+    pub fn from_array(values: [f32; 8]) -> BoidTraits {
+        let [max_speed, perception, separation_distance, size, cohesion, separation, alignment, border] =
+            values;
+        BoidTraits {
+            max_speed,
+            perception,
+            separation_distance,
+            size,
+            cohesion,
+            separation,
+            alignment,
+            border,
+        }
+    }
+}
+
+impl LinearSerializable for BoidTraits {
+    const NUM_ELEMENTS: usize = 8;
+    fn serialize_to_array(&self, buffer: &mut [f32], offset: usize) -> usize {
+        // Same order as the struct fields.
+        let start = offset * Self::NUM_ELEMENTS;
+        buffer[start..start + Self::NUM_ELEMENTS].copy_from_slice(&self.as_array());
+        Self::NUM_ELEMENTS
+    }
+
+    fn deserialize_from_array(buffer: &[f32], offset: usize) -> (Self, usize) {
+        let mut values = [0.0; 8];
+        values.copy_from_slice(&buffer[offset..offset + Self::NUM_ELEMENTS]);
+        (BoidTraits::from_array(values), Self::NUM_ELEMENTS)
+    }
+
+    fn serialized_size() -> usize {
+        Self::NUM_ELEMENTS
+    }
+}
+
+/**
+ * Writes every boid's traits into `buffer`, in the same order as the transfer array.
+ */
+pub fn serialize_traits(boids: &[Boid], buffer: &mut [f32]) {
+    for (i, boid) in boids.iter().enumerate() {
+        boid.traits.serialize_to_array(buffer, i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_traits_stay_within_the_spread() {
+        let mut rng = SimRng::new(12);
+        assert_eq!(BoidTraits::random(0.0, &mut rng), BoidTraits::default());
+        for _ in 0..100 {
+            let traits = BoidTraits::random(0.3, &mut rng);
+            assert!(traits.as_array().iter().all(|&value| (0.7..=1.3).contains(&value)));
+            let mut buffer = [0.0; 8];
+            traits.serialize_to_array(&mut buffer, 0);
+            assert_eq!(BoidTraits::deserialize_from_array(&buffer, 0).0, traits);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+
+use crate::boids::metrics::FlockMetrics;
+use crate::boids::rng::SimRng;
+use crate::boids::settings::WorldSettings;
+
+/**
+ * A number in `WorldSettings` the tuner is allowed to change, with the range it searches.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunableParameter {
+    VelocityLimit,
+    AvoidanceRange,
+    AvoidanceModifier,
+    CohesionModifier,
+    AlignmentModifier,
+    BorderModifier,
+    PerceptionRange,
+    WanderModifier,
+}
+
+impl TunableParameter {
+    pub const ALL: [TunableParameter; 8] = [
+        TunableParameter::VelocityLimit,
+        TunableParameter::AvoidanceRange,
+        TunableParameter::AvoidanceModifier,
+        TunableParameter::CohesionModifier,
+        TunableParameter::AlignmentModifier,
+        TunableParameter::BorderModifier,
+        TunableParameter::PerceptionRange,
+        TunableParameter::WanderModifier,
+    ];
+
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            TunableParameter::VelocityLimit => (5.0, 60.0),
+            TunableParameter::AvoidanceRange => (5.0, 80.0),
+            TunableParameter::PerceptionRange => (20.0, 250.0),
+            TunableParameter::WanderModifier => (0.0, 2.0),
+            _ => (0.0, 3.0),
+        }
+    }
+
+    pub fn get(&self, settings: &WorldSettings) -> f32 {
+        match self {
+            TunableParameter::VelocityLimit => settings.velocity_limit,
+            TunableParameter::AvoidanceRange => settings.avoidance.avoidance_range,
+            TunableParameter::AvoidanceModifier => settings.avoidance.avoidance_modifier,
+            TunableParameter::CohesionModifier => settings.pc.p_center_modifier,
+            TunableParameter::AlignmentModifier => settings.velocity_matching.velocity_matching_modifier,
+            TunableParameter::BorderModifier => settings.border_constraint.border_constraint_modifier,
+            TunableParameter::PerceptionRange => settings.neighbors.perception_range,
+            TunableParameter::WanderModifier => settings.wander.wander_modifier,
+        }
+    }
+
+    /**
+     * Sets the parameter, clamped to its range.
+     */
+    pub fn set(&self, settings: &mut WorldSettings, value: f32) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        match self {
+            TunableParameter::VelocityLimit => settings.velocity_limit = value,
+            TunableParameter::AvoidanceRange => settings.avoidance.avoidance_range = value,
+            TunableParameter::AvoidanceModifier => settings.avoidance.avoidance_modifier = value,
+            TunableParameter::CohesionModifier => settings.pc.p_center_modifier = value,
+            TunableParameter::AlignmentModifier => settings.velocity_matching.velocity_matching_modifier = value,
+            TunableParameter::BorderModifier => settings.border_constraint.border_constraint_modifier = value,
+            TunableParameter::PerceptionRange => settings.neighbors.perception_range = value,
+            TunableParameter::WanderModifier => settings.wander.wander_modifier = value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Polarization,
+    MeanSpeed,
+    MeanNearestDistance,
+    FlockCount,
+    LargestFlock,
+}
+
+impl Metric {
+    pub fn value(&self, metrics: &FlockMetrics) -> f32 {
+        match self {
+            Metric::Polarization => metrics.polarization,
+            Metric::MeanSpeed => metrics.mean_speed,
+            Metric::MeanNearestDistance => metrics.mean_nearest_distance,
+            Metric::FlockCount => metrics.flock_count as f32,
+            Metric::LargestFlock => metrics.largest_flock as f32,
+        }
+    }
+}
+
+/**
+ * One condition of a tuning target, like "polarization of at least 0.9".
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricBound {
+    pub metric: Metric,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+impl MetricBound {
+    /**
+     * How far `metrics` misses this bound, relative to the bound itself. 0 when it's met.
+     */
+    pub fn miss(&self, metrics: &FlockMetrics) -> f32 {
+        let value = self.metric.value(metrics);
+        let relative = |gap: f32, bound: f32| gap.max(0.0) / bound.abs().max(1.0);
+        self.min.map_or(0.0, |min| relative(min - value, min))
+            + self.max.map_or(0.0, |max| relative(value - max, max))
+    }
+}
+
+/**
+ * Sums the misses of every bound. Lower is better, 0 means the whole target is met.
+ */
+pub fn score(target: &[MetricBound], metrics: &FlockMetrics) -> f32 {
+    target.iter().map(|bound| bound.miss(metrics)).sum()
+}
+
+/**
+ * Settings for the genetic search. The first generation is sampled uniformly from the
+ * parameter ranges, so `generations: 1` is plain random search.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    pub population: u32,
+    pub generations: u32,
+    // Best candidates copied unchanged into the next generation.
+    pub elite: u32,
+    // Largest change a mutation makes, as a fraction of the parameter's range.
+    pub mutation: f32,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            population: 24,
+            generations: 10,
+            elite: 4,
+            mutation: 0.15,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TuningResult {
+    pub best: WorldSettings,
+    pub best_score: f32,
+    // Best score after each generation.
+    pub history: Vec<f32>,
+}
+
+/**
+ * Searches `parameters` of `base` for the settings with the lowest score. `evaluate`
+ * scores a whole generation at once, so the caller can run the candidates in parallel.
+ * Stops early once a candidate scores 0.
+ */
+pub fn tune(
+    base: &WorldSettings,
+    parameters: &[TunableParameter],
+    search: &SearchSettings,
+    rng: &mut SimRng,
+    mut evaluate: impl FnMut(&[WorldSettings]) -> Vec<f32>,
+) -> TuningResult {
+    let population = search.population.max(2) as usize;
+    let elite = (search.elite as usize).min(population - 1);
+    let with_values = |values: &[f32]| {
+        let mut settings = base.clone();
+        for (parameter, &value) in parameters.iter().zip(values) {
+            parameter.set(&mut settings, value);
+        }
+        settings
+    };
+
+    let mut candidates: Vec<Vec<f32>> = (0..population)
+        .map(|_| {
+            parameters
+                .iter()
+                .map(|parameter| {
+                    let (min, max) = parameter.range();
+                    rng.range(min, max)
+                })
+                .collect()
+        })
+        .collect();
+    let mut best = (base.clone(), f32::INFINITY);
+    let mut history = Vec::new();
+
+    for generation in 0..search.generations.max(1) {
+        let settings: Vec<WorldSettings> = candidates.iter().map(|values| with_values(values)).collect();
+        let scores = evaluate(&settings);
+        let mut ranked: Vec<usize> = (0..candidates.len()).collect();
+        ranked.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]));
+        if scores[ranked[0]] < best.1 {
+            best = (settings[ranked[0]].clone(), scores[ranked[0]]);
+        }
+        history.push(best.1);
+        if best.1 <= 0.0 || generation + 1 == search.generations {
+            break;
+        }
+
+        // Tournament of two for each parent, uniform crossover, then mutation.
+        let pick = |rng: &mut SimRng| {
+            let a = ranked[(rng.next_f32() * population as f32) as usize % population];
+            let b = ranked[(rng.next_f32() * population as f32) as usize % population];
+            if scores[a] <= scores[b] { a } else { b }
+        };
+        let mut next: Vec<Vec<f32>> = ranked[..elite].iter().map(|&i| candidates[i].clone()).collect();
+        while next.len() < population {
+            let (a, b) = (pick(rng), pick(rng));
+            let child = parameters
+                .iter()
+                .enumerate()
+                .map(|(p, parameter)| {
+                    let (min, max) = parameter.range();
+                    let gene = if rng.next_f32() < 0.5 { candidates[a][p] } else { candidates[b][p] };
+                    let step = (max - min) * search.mutation;
+                    (gene + rng.range(-step, step)).clamp(min, max)
+                })
+                .collect();
+            next.push(child);
+        }
+        candidates = next;
+    }
+
+    TuningResult {
+        best: best.0,
+        best_score: best.1,
+        history,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_score_relative_misses() {
+        let metrics = FlockMetrics {
+            polarization: 0.45,
+            flock_count: 1,
+            ..FlockMetrics::default()
+        };
+        let target = [
+            MetricBound {
+                metric: Metric::Polarization,
+                min: Some(0.9),
+                max: None,
+            },
+            MetricBound {
+                metric: Metric::FlockCount,
+                min: Some(3.0),
+                max: Some(5.0),
+            },
+        ];
+        // 0.45 short of a bound below 1 counts whole, 2 flocks short of 3 is 2/3.
+        assert!((score(&target, &metrics) - (0.45 + 2.0 / 3.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn search_converges_on_a_known_optimum() {
+        let base = WorldSettings::default(800, 600);
+        let parameters = [TunableParameter::VelocityLimit, TunableParameter::CohesionModifier];
+        let distance = |settings: &WorldSettings| {
+            (settings.velocity_limit - 40.0).abs() / 55.0 + (settings.pc.p_center_modifier - 0.5).abs() / 3.0
+        };
+        let search = SearchSettings {
+            population: 16,
+            generations: 30,
+            elite: 2,
+            mutation: 0.1,
+        };
+        let result = tune(&base, &parameters, &search, &mut SimRng::new(5), |generation| {
+            generation.iter().map(distance).collect()
+        });
+        assert!(result.best_score < 0.05, "best score {}", result.best_score);
+        assert_eq!(result.best_score, distance(&result.best));
+        assert!(result.history.windows(2).all(|pair| pair[1] <= pair[0]));
+        // Only the tuned parameters change.
+        assert_eq!(result.best.avoidance, base.avoidance);
+    }
+}
@@ -0,0 +1,197 @@
+use cgmath::Vector2;
+use std::collections::BTreeMap;
+
+use crate::boids::boid::Boid;
+use crate::boids::utils::LinearSerializable;
+
+/**
+ * The part of the world the camera sees. `width` and `height` are the size of the
+ * container in pixels, at `zoom` 2 the camera shows half as much of the world.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub center: Vector2<f32>,
+    pub width: f32,
+    pub height: f32,
+    pub zoom: f32,
+}
+
+impl Viewport {
+    pub fn new(center: Vector2<f32>, width: f32, height: f32, zoom: f32) -> Viewport {
+        Viewport {
+            center,
+            width: width.max(0.0),
+            height: height.max(0.0),
+            zoom: if zoom > 0.0 { zoom } else { 1.0 },
+        }
+    }
+
+    /**
+     * Min and max corner of the visible world rect, grown by `margin` world units.
+     */
+    pub fn bounds(&self, margin: f32) -> (Vector2<f32>, Vector2<f32>) {
+        let half = Vector2::new(self.width, self.height) / (2.0 * self.zoom);
+        let margin = Vector2::new(margin, margin);
+        (self.center - half - margin, self.center + half + margin)
+    }
+}
+
+/**
+ * Compact copies of the transfer data for a viewport: the boids inside it (plus a
+ * margin) in the usual transfer layout, and optionally the boids outside it merged into
+ * one point per `cluster_cell_size` square as [x, y, count, ...].
+ */
+#[derive(Clone, Debug)]
+pub struct CulledView {
+    viewport: Option<Viewport>,
+    margin: f32,
+    // Zero turns clustering off, the boids outside the viewport are just dropped.
+    cluster_cell_size: f32,
+    visible: Vec<f32>,
+    visible_ids: Vec<u32>,
+    clusters: Vec<f32>,
+}
+
+impl Default for CulledView {
+    fn default() -> Self {
+        CulledView {
+            viewport: None,
+            margin: CulledView::DEFAULT_MARGIN,
+            cluster_cell_size: 0.0,
+            visible: Vec::new(),
+            visible_ids: Vec::new(),
+            clusters: Vec::new(),
+        }
+    }
+}
+
+impl CulledView {
+    pub const DEFAULT_MARGIN: f32 = 50.0;
+
+    pub fn viewport(&self) -> Option<&Viewport> {
+        self.viewport.as_ref()
+    }
+
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.viewport = viewport;
+        if viewport.is_none() {
+            self.visible.clear();
+            self.visible_ids.clear();
+            self.clusters.clear();
+        }
+    }
+
+    pub fn margin(&self) -> f32 {
+        self.margin
+    }
+
+    pub fn set_margin(&mut self, margin: f32) {
+        self.margin = margin.max(0.0);
+    }
+
+    pub fn cluster_cell_size(&self) -> f32 {
+        self.cluster_cell_size
+    }
+
+    pub fn set_cluster_cell_size(&mut self, cell_size: f32) {
+        self.cluster_cell_size = cell_size.max(0.0);
+        if self.cluster_cell_size == 0.0 {
+            self.clusters.clear();
+        }
+    }
+
+    pub fn visible(&self) -> &[f32] {
+        &self.visible
+    }
+
+    pub fn visible_ids(&self) -> &[u32] {
+        &self.visible_ids
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.visible_ids.len()
+    }
+
+    pub fn clusters(&self) -> &[f32] {
+        &self.clusters
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        self.clusters.len() / 3
+    }
+
+    /**
+     * Refills the buffers from `boids` and their already serialized `transfer_array`.
+     * Does nothing without a viewport.
+     */
+    pub fn update(&mut self, boids: &[Boid], transfer_array: &[f32]) {
+        let viewport = match self.viewport {
+            Some(viewport) => viewport,
+            None => return,
+        };
+        let (min, max) = viewport.bounds(self.margin);
+        self.visible.clear();
+        self.visible_ids.clear();
+        self.clusters.clear();
+        let mut cells: BTreeMap<(i32, i32), (f32, f32, u32)> = BTreeMap::new();
+
+        for (boid, data) in boids.iter().zip(transfer_array.chunks_exact(Boid::NUM_ELEMENTS)) {
+            let p = boid.position;
+            if p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y {
+                self.visible.extend_from_slice(data);
+                self.visible_ids.push(boid.id);
+            } else if self.cluster_cell_size > 0.0 {
+                let key = (
+                    (p.x / self.cluster_cell_size).floor() as i32,
+                    (p.y / self.cluster_cell_size).floor() as i32,
+                );
+                let cell = cells.entry(key).or_insert((0.0, 0.0, 0));
+                cell.0 += p.x;
+                cell.1 += p.y;
+                cell.2 += 1;
+            }
+        }
+
+        for (sum_x, sum_y, count) in cells.into_values() {
+            let n = count as f32;
+            self.clusters.extend_from_slice(&[sum_x / n, sum_y / n, n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::boid::serialize_boids;
+
+    #[test]
+    fn zoom_shrinks_the_visible_rect() {
+        let viewport = Viewport::new(Vector2::new(100.0, 50.0), 200.0, 100.0, 2.0);
+        let (min, max) = viewport.bounds(10.0);
+        assert_eq!((min.x, min.y, max.x, max.y), (40.0, 15.0, 160.0, 85.0));
+    }
+
+    #[test]
+    fn culls_and_clusters_boids_outside_the_viewport() {
+        let positions = [(10.0, 10.0), (90.0, 90.0), (500.0, 10.0), (510.0, 20.0), (900.0, 900.0)];
+        let boids: Vec<Boid> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| Boid::new(Vector2::new(x, y), Vector2::new(1.0, 0.0), i as u32))
+            .collect();
+        let mut transfer_array = vec![0.0; boids.len() * Boid::NUM_ELEMENTS];
+        serialize_boids(&boids, &mut transfer_array);
+
+        let mut view = CulledView::default();
+        view.update(&boids, &transfer_array);
+        assert_eq!(view.visible_count(), 0);
+
+        view.set_viewport(Some(Viewport::new(Vector2::new(50.0, 50.0), 100.0, 100.0, 1.0)));
+        view.set_margin(0.0);
+        view.set_cluster_cell_size(100.0);
+        view.update(&boids, &transfer_array);
+        assert_eq!(view.visible_ids(), &[0, 1]);
+        assert_eq!(view.visible(), &transfer_array[..2 * Boid::NUM_ELEMENTS]);
+        assert_eq!(view.clusters(), &[505.0, 15.0, 2.0, 900.0, 900.0, 1.0]);
+    }
+}
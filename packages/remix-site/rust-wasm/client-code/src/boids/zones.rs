@@ -0,0 +1,152 @@
+use cgmath::{InnerSpace, Vector2};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::boids::boid::Boid;
+use crate::boids::region::RegionShape;
+
+/**
+ * What a zone does to the boids inside it.
+ */
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ZoneEffect {
+    // Nothing, the zone only counts the boids inside.
+    Counter = 0,
+    // Multiplies the velocity limit by `strength`.
+    SlowDown = 1,
+    // Boids can't get in, they're put back on the edge.
+    NoGo = 2,
+    // Replaces the velocity limit with `strength`.
+    VelocityLimit = 3,
+}
+
+/**
+ * A named area of the world that changes how boids behave inside it.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    pub shape: RegionShape,
+    pub effect: ZoneEffect,
+    pub strength: f32,
+    // Boids inside after the last tick.
+    pub count: u32,
+}
+
+impl Zone {
+    pub fn new(name: &str, shape: RegionShape, effect: ZoneEffect, strength: f32) -> Zone {
+        Zone {
+            name: name.to_string(),
+            shape,
+            effect,
+            strength,
+            count: 0,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let shape = match self.shape {
+            RegionShape::Rect { min, max } => json!({
+                "type": "rect",
+                "x": min.x,
+                "y": min.y,
+                "width": max.x - min.x,
+                "height": max.y - min.y,
+            }),
+            RegionShape::Circle { center, radius } => json!({
+                "type": "circle",
+                "x": center.x,
+                "y": center.y,
+                "radius": radius,
+            }),
+        };
+        json!({
+            "name": self.name,
+            "shape": shape,
+            "effect": self.effect,
+            "strength": self.strength,
+            "count": self.count,
+        })
+    }
+}
+
+/**
+ * The velocity limit at `position`. Overlapping velocity limit zones take the lowest,
+ * then every slow down zone scales it.
+ */
+pub fn speed_limit(zones: &[Zone], position: Vector2<f32>, limit: f32) -> f32 {
+    let inside = || zones.iter().filter(move |zone| zone.shape.contains(position));
+    let limit = inside()
+        .filter(|zone| zone.effect == ZoneEffect::VelocityLimit)
+        .map(|zone| zone.strength.max(0.0))
+        .reduce(f32::min)
+        .unwrap_or(limit);
+    inside()
+        .filter(|zone| zone.effect == ZoneEffect::SlowDown)
+        .fold(limit, |limit, zone| limit * zone.strength.max(0.0))
+}
+
+/**
+ * The highest velocity limit anywhere in the world.
+ */
+pub fn max_speed_limit(zones: &[Zone], limit: f32) -> f32 {
+    zones
+        .iter()
+        .filter(|zone| zone.effect == ZoneEffect::VelocityLimit)
+        .fold(limit, |limit, zone| limit.max(zone.strength))
+}
+
+/**
+ * Puts a boid that ended up inside a no-go zone back on its edge, and drops the part of
+ * its velocity heading in.
+ */
+pub fn keep_out(zones: &[Zone], boid: &mut Boid) {
+    for zone in zones.iter().filter(|zone| zone.effect == ZoneEffect::NoGo) {
+        if let Some((edge, normal)) = zone.shape.exit(boid.position) {
+            boid.position = edge;
+            let into = boid.velocity.dot(normal);
+            if into < 0.0 {
+                boid.velocity -= normal * into;
+            }
+        }
+    }
+}
+
+pub fn update_counts(zones: &mut [Zone], boids: &[Boid]) {
+    for zone in zones.iter_mut() {
+        zone.count = boids.iter().filter(|boid| zone.shape.contains(boid.position)).count() as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str, effect: ZoneEffect, strength: f32) -> Zone {
+        let shape = RegionShape::Rect {
+            min: Vector2::new(0.0, 0.0),
+            max: Vector2::new(10.0, 10.0),
+        };
+        Zone::new(name, shape, effect, strength)
+    }
+
+    #[test]
+    fn limits_stack_and_no_go_zones_push_out() {
+        let zones = vec![
+            square("fast", ZoneEffect::VelocityLimit, 40.0),
+            square("slow", ZoneEffect::SlowDown, 0.5),
+            square("wall", ZoneEffect::NoGo, 0.0),
+        ];
+        assert_eq!(speed_limit(&zones, Vector2::new(5.0, 5.0), 25.0), 20.0);
+        assert_eq!(speed_limit(&zones, Vector2::new(50.0, 5.0), 25.0), 25.0);
+        assert_eq!(max_speed_limit(&zones, 25.0), 40.0);
+
+        let mut boid = Boid::new(Vector2::new(2.0, 5.0), Vector2::new(3.0, 1.0), 0);
+        keep_out(&zones, &mut boid);
+        assert_eq!(boid.position, Vector2::new(0.0, 5.0));
+        assert_eq!(boid.velocity, Vector2::new(0.0, 1.0));
+    }
+}
@@ -2,7 +2,9 @@ use wasm_bindgen::prelude::*;
 
 use std::fmt;
 use js_sys::Math::random;
-use crate::utils::set_panic_hook;
+use js_sys::Uint8Array;
+use crate::boids::rng::SimRng;
+use crate::utils::{set_panic_hook, uint8_view, BufferGeneration};
 
 #[wasm_bindgen]
 #[repr(u8)]
@@ -17,6 +19,7 @@ pub struct Universe {
     width: u32,
     height: u32,
     cells: Vec<Cell>,
+    cells_generation: BufferGeneration,
 }
 
 #[wasm_bindgen]
@@ -70,8 +73,36 @@ impl Universe {
             width,
             height,
             cells,
+            cells_generation: BufferGeneration::default(),
         }
     }
+    /**
+     * A random universe that's always the same for the same seed. Doesn't use any JS
+     * randomness, so it also works natively.
+     */
+    pub fn with_seed(width: u32, height: u32, seed: u32) -> Universe {
+        let mut rng = SimRng::new(seed);
+        let cells = (0..width * height)
+            .map(|_i| {
+                if rng.next_f32() > 0.5 {
+                    Cell::Alive
+                } else {
+                    Cell::Dead
+                }
+            })
+            .collect();
+        Universe {
+            width,
+            height,
+            cells,
+            cells_generation: BufferGeneration::default(),
+        }
+    }
+
+    pub fn live_cell_count(&self) -> u32 {
+        self.cells.iter().filter(|&&cell| cell == Cell::Alive).count() as u32
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -84,6 +115,22 @@ impl Universe {
         self.cells.as_ptr()
     }
 
+    /**
+     * A fresh `Uint8Array` over the cells (one byte each), without copying. Check
+     * `cells_generation` before reading an old view, `tick`, `randomize` and `clear`
+     * swap in a new buffer.
+     */
+    pub fn cells_view(&self) -> Uint8Array {
+        uint8_view(&self.cells)
+    }
+
+    /**
+     * Bumped whenever the cells buffer moved since this was last asked.
+     */
+    pub fn cells_generation(&self) -> u32 {
+        self.cells_generation.current(&self.cells)
+    }
+
     pub fn render(&self) -> String {
         self.to_string()
     }
@@ -124,7 +171,7 @@ impl fmt::Display for Universe {
                 let symbol = if cell == Cell::Dead { '◻' } else { '◼' };
                 write!(f, "{}", symbol)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
         Ok(())
@@ -191,16 +238,14 @@ impl Universe {
     }
 
     pub fn create_random_universe(width:u32, height: u32) -> Vec<Cell> {
-        let random_universe = (0..width * height)
+        (0..width * height)
             .map(|_i| {
                 if random() > 0.5 {
                     Cell::Alive
                 } else {
                     Cell::Dead
                 }
-            }).collect();
-
-        random_universe
+            }).collect()
     }
 }
 
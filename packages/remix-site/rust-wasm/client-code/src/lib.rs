@@ -1,8 +1,8 @@
 mod utils;
 
 use wasm_bindgen::prelude::*;
-mod game_of_life; 
-mod boids;
+pub mod game_of_life;
+pub mod boids;
 
 #[wasm_bindgen]
 extern "C" {
@@ -1,3 +1,7 @@
+use js_sys::{Float32Array, Uint8Array, WebAssembly};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use wasm_bindgen::JsCast;
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -8,3 +12,94 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/**
+ * Tracks where a buffer shared with JS lives. JS views over wasm memory go stale when
+ * the buffer is reallocated or when the memory grows (which detaches the old
+ * `ArrayBuffer`), so the generation is bumped whenever either is seen to change. JS
+ * keeps the generation its view was made at and makes a new view when they differ.
+ *
+ * Uses atomics rather than `Cell` so the owner stays `Sync` for the parallel tick.
+ */
+#[derive(Debug, Default)]
+pub struct BufferGeneration {
+    ptr: AtomicUsize,
+    len: AtomicUsize,
+    memory_pages: AtomicUsize,
+    generation: AtomicU32,
+}
+
+impl BufferGeneration {
+    /**
+     * The generation of `buffer`, bumped first if it moved since the last call.
+     */
+    pub fn current<T>(&self, buffer: &[T]) -> u32 {
+        let ptr = buffer.as_ptr() as usize;
+        let len = buffer.len();
+        let memory_pages = memory_pages();
+        let ptr_moved = self.ptr.swap(ptr, Ordering::Relaxed) != ptr;
+        let len_changed = self.len.swap(len, Ordering::Relaxed) != len;
+        let memory_grew = self.memory_pages.swap(memory_pages, Ordering::Relaxed) != memory_pages;
+        if ptr_moved || len_changed || memory_grew {
+            self.generation.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.generation.load(Ordering::Relaxed)
+        }
+    }
+}
+
+/**
+ * A `Float32Array` over `buffer`, straight on top of wasm memory (no copy). Only valid
+ * until `buffer` moves, see `BufferGeneration`.
+ */
+pub fn float32_view(buffer: &[f32]) -> Float32Array {
+    Float32Array::new_with_byte_offset_and_length(
+        &memory_buffer(),
+        buffer.as_ptr() as u32,
+        buffer.len() as u32,
+    )
+}
+
+/**
+ * A `Uint8Array` over the bytes of `buffer`, straight on top of wasm memory (no copy).
+ * Only valid until `buffer` moves, see `BufferGeneration`.
+ */
+pub fn uint8_view<T: Copy>(buffer: &[T]) -> Uint8Array {
+    Uint8Array::new_with_byte_offset_and_length(
+        &memory_buffer(),
+        buffer.as_ptr() as u32,
+        std::mem::size_of_val(buffer) as u32,
+    )
+}
+
+fn memory_buffer() -> wasm_bindgen::JsValue {
+    wasm_bindgen::memory()
+        .unchecked_into::<WebAssembly::Memory>()
+        .buffer()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn memory_pages() -> usize {
+    core::arch::wasm32::memory_size::<0>()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn memory_pages() -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_changes_only_when_the_buffer_moves() {
+        let tracker = BufferGeneration::default();
+        let mut buffer = vec![0.0f32; 4];
+        let first = tracker.current(&buffer);
+        buffer[0] = 1.0;
+        assert_eq!(tracker.current(&buffer), first);
+        buffer.push(2.0);
+        assert!(tracker.current(&buffer) > first);
+    }
+}